@@ -0,0 +1,176 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    commands::Command,
+    parse::{self, RespElement},
+    Server,
+};
+
+/// Name of the append-only file, which logs every write command, within `dir`.
+pub(crate) const FILENAME: &str = "appendonly.aof";
+
+/// The append-only file which write commands are logged to, when `appendonly` is enabled.
+///
+/// Like Redis with `appendfsync always`, each command is fsynced before it's replied to,
+/// so every write a client has seen a reply for is durable.
+#[derive(Debug, Default)]
+pub(crate) struct Aof(Mutex<Option<File>>);
+
+impl Aof {
+    /// Start appending to the file at `path`, creating it if it doesn't exist.
+    pub(crate) fn open(&self, path: &Path) -> io::Result<()> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        *self.0.lock().unwrap() = Some(file);
+        Ok(())
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.0.lock().unwrap().is_some()
+    }
+
+    /// Log a write command, exactly as it was sent in RESP, if the file is open.
+    pub(crate) fn append(&self, command: &[u8]) -> io::Result<()> {
+        let mut file = self.0.lock().unwrap();
+        let Some(file) = file.as_mut() else {
+            return Ok(());
+        };
+        file.write_all(command)?;
+        file.sync_data()
+    }
+}
+
+/// Rebuild the dataset by executing every command in the append-only file at `path`,
+/// returning how many were replayed.
+///
+/// A command cut off at the end of the file, as left by a crash part way through
+/// writing it, is ignored.
+pub(crate) fn replay(path: &Path, server: &Arc<Server>) -> anyhow::Result<usize> {
+    let contents = std::fs::read(path)?;
+    let mut input = &contents[..];
+    let mut replayed = 0;
+    while !input.is_empty() {
+        // Like Redis, anything other than an array of arguments means the file is corrupt.
+        if input[0] != b'*' {
+            anyhow::bail!(
+                "Bad format reading {} at byte {}",
+                path.display(),
+                contents.len() - input.len()
+            );
+        }
+        let element = match parse::parse_element(input, u64::MAX) {
+            Ok((rest, element)) => {
+                input = rest;
+                element
+            }
+            Err(_) => {
+                eprintln!(
+                    "Ignoring a truncated command at the end of {}",
+                    path.display()
+                );
+                break;
+            }
+        };
+        if is_select(&element) {
+            continue;
+        }
+        match Command::try_from(element) {
+            Ok(cmd) => {
+                cmd.execute(server);
+                replayed += 1;
+            }
+            Err(e) => eprintln!("Unable to replay command from {}: {}", path.display(), e),
+        }
+    }
+    Ok(replayed)
+}
+
+/// Only database 0 exists, so the `SELECT 0` Redis writes before the first command
+/// is skipped rather than replayed.
+fn is_select(element: &RespElement) -> bool {
+    match element {
+        RespElement::Array(args) => matches!(
+            args.first(),
+            Some(RespElement::BulkString(name)) if name.as_ref().eq_ignore_ascii_case("SELECT")
+        ),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rstest::rstest;
+
+    use crate::db::Value;
+
+    use super::*;
+
+    const FIXTURE: &[u8] = b"*2\r\n$6\r\nSELECT\r\n$1\r\n0\r\n\
+        *3\r\n$3\r\nSET\r\n$3\r\nfoo\r\n$3\r\nbar\r\n\
+        *5\r\n$3\r\nSET\r\n$3\r\nbaz\r\n$3\r\nqux\r\n$2\r\nPX\r\n$6\r\n100000\r\n";
+
+    /// A final `SET truncated value` cut off at various points.
+    #[rstest]
+    #[case(b"")]
+    #[case(b"*")]
+    #[case(b"*3\r\n$3\r\nSET\r\n")]
+    #[case(b"*3\r\n$3\r\nSET\r\n$9\r\ntrunc")]
+    #[case(b"*3\r\n$3\r\nSET\r\n$9\r\ntruncated\r\n$5\r\nval")]
+    fn test_replay(#[case] tail: &[u8]) {
+        let dir = std::env::temp_dir().join(format!(
+            "redis-aof-test-{}-{}",
+            std::process::id(),
+            tail.len()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(FILENAME);
+        std::fs::write(&path, [FIXTURE, tail].concat()).unwrap();
+
+        let server = Arc::new(Server::new(HashMap::new()));
+        let replayed = replay(&path, &server);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(replayed.unwrap(), 2);
+        let foo = server.db.get_live("foo").unwrap();
+        assert_eq!(foo.value, Value::String("bar".into()));
+        assert!(server.db.get_live("baz").unwrap().expires_at.is_some());
+        assert!(server.db.get_live("truncated").is_none());
+    }
+
+    #[test]
+    fn test_replay_bad_format() {
+        let path = std::env::temp_dir().join(format!("redis-aof-bad-{}.aof", std::process::id()));
+        std::fs::write(&path, [FIXTURE, b"?garbage\r\n"].concat()).unwrap();
+
+        let server = Arc::new(Server::new(HashMap::new()));
+        let replayed = replay(&path, &server);
+        std::fs::remove_file(&path).unwrap();
+        assert!(replayed.is_err());
+    }
+
+    #[test]
+    fn test_append_then_replay() {
+        let path =
+            std::env::temp_dir().join(format!("redis-aof-append-{}.aof", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let aof = Aof::default();
+        aof.append(b"*1\r\n$4\r\nPING\r\n").unwrap();
+        assert!(!path.exists());
+
+        aof.open(&path).unwrap();
+        assert!(aof.is_enabled());
+        aof.append(&FIXTURE[..FIXTURE.len() / 2]).unwrap();
+        aof.append(&FIXTURE[FIXTURE.len() / 2..]).unwrap();
+
+        let server = Arc::new(Server::new(HashMap::new()));
+        let replayed = replay(&path, &server);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(replayed.unwrap(), 2);
+    }
+}
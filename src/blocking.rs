@@ -0,0 +1,27 @@
+use tokio::sync::watch;
+
+/// Wakes clients blocked waiting for keys to be written to, such as by `XREAD BLOCK`.
+///
+/// Waiters aren't tracked per key: every write wakes every blocked client, which then
+/// checks its own keys again. That's cheap enough while few clients block at once.
+#[derive(Debug)]
+pub(crate) struct KeyWaiters(watch::Sender<()>);
+
+impl Default for KeyWaiters {
+    fn default() -> Self {
+        KeyWaiters(watch::channel(()).0)
+    }
+}
+
+impl KeyWaiters {
+    /// Start watching for writes. Subscribe before checking the keys, so a write
+    /// between the check and waiting isn't missed.
+    pub(crate) fn subscribe(&self) -> watch::Receiver<()> {
+        self.0.subscribe()
+    }
+
+    /// Wake every blocked client after a write to a key they may be waiting on.
+    pub(crate) fn signal_write(&self) {
+        self.0.send_replace(());
+    }
+}
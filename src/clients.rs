@@ -0,0 +1,197 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    net::SocketAddr,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use bytes::Bytes;
+use tokio::sync::{mpsc, Notify};
+
+use crate::parse::RespElement;
+
+/// Where a client connected from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ClientAddr {
+    Tcp(SocketAddr),
+    /// The path of the Unix socket the client connected to, since its end is unnamed.
+    Unix(Arc<Path>),
+}
+
+impl From<SocketAddr> for ClientAddr {
+    fn from(addr: SocketAddr) -> Self {
+        ClientAddr::Tcp(addr)
+    }
+}
+
+impl fmt::Display for ClientAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientAddr::Tcp(addr) => addr.fmt(f),
+            // Redis shows Unix socket clients with a port of zero.
+            ClientAddr::Unix(path) => write!(f, "{}:0", path.display()),
+        }
+    }
+}
+
+/// A connected client, as tracked by the server for `CLIENT LIST` and friends.
+#[derive(Debug)]
+pub(crate) struct Client {
+    id: u64,
+    addr: ClientAddr,
+    name: String,
+    connected_at: Instant,
+    /// The connection's flags in `CLIENT LIST`, as last reported by its `ClientState`.
+    flags: &'static str,
+    kill: Arc<Notify>,
+}
+
+impl Client {
+    fn info_line(&self) -> String {
+        format!(
+            "id={} addr={} name={} age={} flags={}",
+            self.id,
+            self.addr,
+            self.name,
+            self.connected_at.elapsed().as_secs(),
+            self.flags
+        )
+    }
+}
+
+/// State belonging to a single connection, which some commands read or change.
+#[derive(Debug)]
+pub(crate) struct ClientState {
+    pub(crate) id: u64,
+    /// Messages to deliver to the client outside of command replies, such as pub-sub messages.
+    pub(crate) messages: mpsc::UnboundedSender<RespElement>,
+    /// Pub-sub channels the client is subscribed to.
+    pub(crate) channels: BTreeSet<String>,
+    /// Commands propagated to the client once it has attached as a replica with `PSYNC`.
+    pub(crate) replica_feed: Option<mpsc::UnboundedReceiver<Bytes>>,
+    /// Whether the client has run `MONITOR`.
+    pub(crate) monitoring: bool,
+    /// Set by `CLIENT NO-EVICT`. Clients are never evicted, so this is only reported back.
+    pub(crate) no_evict: bool,
+    /// Set by `CLIENT NO-TOUCH`, so the client's commands don't update keys' access times.
+    pub(crate) no_touch: bool,
+    /// The port a replica says it accepts connections on, with `REPLCONF listening-port`.
+    pub(crate) listening_port: Option<u16>,
+    /// The RESP version chosen with `HELLO`, which decides how some replies are sent.
+    pub(crate) resp_version: u8,
+}
+
+impl ClientState {
+    /// State for the connection with the given id, and the receiving end of its messages.
+    pub(crate) fn new(id: u64) -> (Self, mpsc::UnboundedReceiver<RespElement>) {
+        let (messages, rx) = mpsc::unbounded_channel();
+        let state = ClientState {
+            id,
+            messages,
+            channels: BTreeSet::new(),
+            replica_feed: None,
+            monitoring: false,
+            no_evict: false,
+            no_touch: false,
+            listening_port: None,
+            resp_version: 2,
+        };
+        (state, rx)
+    }
+
+    /// The flags `CLIENT LIST` shows for the connection: `e` for `NO-EVICT` and `T` for
+    /// `NO-TOUCH`, or `N` for neither.
+    pub(crate) fn flags(&self) -> &'static str {
+        match (self.no_evict, self.no_touch) {
+            (false, false) => "N",
+            (true, false) => "e",
+            (false, true) => "T",
+            (true, true) => "eT",
+        }
+    }
+}
+
+/// Shared registry of every open connection.
+///
+/// Connections register themselves when `process` starts and are removed when it returns.
+/// Each client holds a `Notify` which is signalled to ask the connection to close itself.
+#[derive(Debug, Default)]
+pub(crate) struct ClientRegistry {
+    next_id: AtomicU64,
+    clients: Mutex<BTreeMap<u64, Client>>,
+}
+
+impl ClientRegistry {
+    /// Register a new connection, returning its id and the handle used to kill it.
+    pub(crate) fn register(&self, addr: ClientAddr) -> (u64, Arc<Notify>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let kill = Arc::new(Notify::new());
+        self.clients.lock().unwrap().insert(
+            id,
+            Client {
+                id,
+                addr,
+                name: String::new(),
+                connected_at: Instant::now(),
+                flags: "N",
+                kill: kill.clone(),
+            },
+        );
+        (id, kill)
+    }
+
+    pub(crate) fn unregister(&self, id: u64) {
+        self.clients.lock().unwrap().remove(&id);
+    }
+
+    /// One line per client, in the same shape as Redis' `CLIENT LIST`.
+    pub(crate) fn list(&self) -> String {
+        self.clients
+            .lock()
+            .unwrap()
+            .values()
+            .map(|client| client.info_line() + "\n")
+            .collect()
+    }
+
+    /// The `CLIENT LIST` line for the client with the given id.
+    pub(crate) fn info(&self, id: u64) -> Option<String> {
+        let clients = self.clients.lock().unwrap();
+        clients.get(&id).map(|client| client.info_line() + "\n")
+    }
+
+    pub(crate) fn set_name(&self, id: u64, name: String) {
+        if let Some(client) = self.clients.lock().unwrap().get_mut(&id) {
+            client.name = name;
+        }
+    }
+
+    pub(crate) fn set_flags(&self, id: u64, flags: &'static str) {
+        if let Some(client) = self.clients.lock().unwrap().get_mut(&id) {
+            client.flags = flags;
+        }
+    }
+
+    pub(crate) fn addr(&self, id: u64) -> Option<ClientAddr> {
+        let clients = self.clients.lock().unwrap();
+        clients.get(&id).map(|client| client.addr.clone())
+    }
+
+    /// Signal the connection with the given id to close, returning the number of clients killed.
+    pub(crate) fn kill(&self, id: u64) -> usize {
+        match self.clients.lock().unwrap().remove(&id) {
+            Some(client) => {
+                // `notify_one` stores a permit, so the connection sees this even if it is
+                // currently busy executing a command rather than waiting on the socket.
+                client.kill.notify_one();
+                1
+            }
+            None => 0,
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+
+use crate::{
+    db::DbValue,
+    eviction,
+    parse::RespElement,
+    pubsub::{notify_keyspace_event, EventClass},
+    Server,
+};
+
+use super::{expect_string, parse_signed_int, Command, CommandError, CommandExecutor, FromResp};
+
+/// Whether a `BITCOUNT` range is in bytes or bits.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum BitUnit {
+    Byte,
+    Bit,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct BitCountCommand {
+    key: String,
+    /// Inclusive start and end, which may be negative to count back from the end.
+    range: Option<(i64, i64, BitUnit)>,
+}
+
+impl CommandExecutor for BitCountCommand {
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        let bytes = match server.db.with_live(&self.key, expect_string) {
+            Some(Ok(bytes)) => bytes,
+            Some(Err(e)) => return RespElement::SimpleError(e),
+            None => return RespElement::Integer(0),
+        };
+
+        let count = match self.range {
+            None => bytes.iter().map(|b| b.count_ones()).sum(),
+            Some((start, end, BitUnit::Byte)) => match clamp_range(start, end, bytes.len()) {
+                Some((start, end)) => bytes[start..=end].iter().map(|b| b.count_ones()).sum(),
+                None => 0,
+            },
+            Some((start, end, BitUnit::Bit)) => match clamp_range(start, end, bytes.len() * 8) {
+                Some((start, end)) => (start..=end)
+                    .map(|bit| (bytes[bit / 8] >> (7 - bit % 8)) as u32 & 1)
+                    .sum(),
+                None => 0,
+            },
+        };
+        RespElement::Integer(count as i64)
+    }
+}
+
+/// Resolve an inclusive range with negative indices counting back from `len`, clamping it
+/// to the value. Returns `None` if the range is empty.
+pub(crate) fn clamp_range(start: i64, end: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let resolve = |index: i64| if index < 0 { index + len } else { index };
+    let start = resolve(start).max(0);
+    let end = resolve(end).min(len - 1);
+    if start > end {
+        return None;
+    }
+    Some((start as usize, end as usize))
+}
+
+impl FromResp for BitCountCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        let key = match elements.get(1) {
+            Some(RespElement::BulkString(key)) => key.as_ref().to_owned(),
+            Some(_) => return Err(CommandError::SyntaxError),
+            None => return Err(CommandError::WrongArity("bitcount")),
+        };
+
+        let range = match &elements[2..] {
+            [] => None,
+            [start, end] => Some((
+                parse_signed_int(start)?,
+                parse_signed_int(end)?,
+                BitUnit::Byte,
+            )),
+            [start, end, RespElement::BulkString(unit)] => {
+                let unit = match unit.as_ref().to_uppercase().as_str() {
+                    "BYTE" => BitUnit::Byte,
+                    "BIT" => BitUnit::Bit,
+                    _ => return Err(CommandError::SyntaxError),
+                };
+                Some((parse_signed_int(start)?, parse_signed_int(end)?, unit))
+            }
+            _ => return Err(CommandError::SyntaxError),
+        };
+        Ok(BitCountCommand { key, range })
+    }
+}
+
+impl From<BitCountCommand> for Command {
+    fn from(cmd: BitCountCommand) -> Self {
+        Command::BitCount(cmd)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum BitOperation {
+    And,
+    Or,
+    Xor,
+    Not,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct BitOpCommand {
+    op: BitOperation,
+    dest: String,
+    sources: Vec<String>,
+}
+
+impl CommandExecutor for BitOpCommand {
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        if let Err(e) = eviction::make_room(server) {
+            return RespElement::SimpleError(e);
+        }
+
+        let mut values: Vec<Bytes> = Vec::with_capacity(self.sources.len());
+        for key in &self.sources {
+            match server.db.with_live(key, expect_string) {
+                Some(Ok(s)) => values.push(s),
+                Some(Err(e)) => return RespElement::SimpleError(e),
+                None => values.push(Bytes::new()),
+            }
+        }
+
+        // Shorter values are treated as if padded with zero bytes.
+        let len = values.iter().map(Bytes::len).max().unwrap_or(0);
+        let byte = |value: &Bytes, i: usize| value.get(i).copied().unwrap_or(0);
+        let result: Vec<u8> = (0..len)
+            .map(|i| {
+                let mut bytes = values.iter().map(|value| byte(value, i));
+                let first = bytes.next().unwrap_or(0);
+                match self.op {
+                    BitOperation::And => bytes.fold(first, |acc, b| acc & b),
+                    BitOperation::Or => bytes.fold(first, |acc, b| acc | b),
+                    BitOperation::Xor => bytes.fold(first, |acc, b| acc ^ b),
+                    BitOperation::Not => !first,
+                }
+            })
+            .collect();
+
+        // Like Redis, an empty result deletes the destination rather than storing an empty string.
+        let mut db = server.db.write(&self.dest);
+        if result.is_empty() {
+            db.remove(&self.dest);
+        } else {
+            db.insert(self.dest.clone(), DbValue::new(result.into(), None));
+        }
+        drop(db);
+        if len > 0 {
+            notify_keyspace_event(server, EventClass::String, "set", &self.dest);
+        }
+        RespElement::Integer(len as i64)
+    }
+}
+
+impl FromResp for BitOpCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        if elements.len() < 4 {
+            return Err(CommandError::WrongArity("bitop"));
+        }
+        let mut args = Vec::with_capacity(elements.len() - 1);
+        for element in &elements[1..] {
+            args.push(match element {
+                RespElement::BulkString(arg) => arg.as_ref().to_owned(),
+                _ => return Err(CommandError::SyntaxError),
+            });
+        }
+
+        let op = match args[0].to_uppercase().as_str() {
+            "AND" => BitOperation::And,
+            "OR" => BitOperation::Or,
+            "XOR" => BitOperation::Xor,
+            "NOT" => BitOperation::Not,
+            _ => return Err(CommandError::SyntaxError),
+        };
+        let sources = args.split_off(2);
+        if op == BitOperation::Not && sources.len() != 1 {
+            return Err(CommandError::BitOpNotArity);
+        }
+        Ok(BitOpCommand {
+            op,
+            dest: args.pop().unwrap(),
+            sources,
+        })
+    }
+}
+
+impl From<BitOpCommand> for Command {
+    fn from(cmd: BitOpCommand) -> Self {
+        Command::BitOp(cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::commands::test_util::{command, execute};
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(&["BITCOUNT", "mykey"], 26)]
+    #[case(&["BITCOUNT", "mykey", "0", "0"], 4)]
+    #[case(&["BITCOUNT", "mykey", "1", "1"], 6)]
+    #[case(&["BITCOUNT", "mykey", "-2", "-1"], 7)]
+    #[case(&["BITCOUNT", "mykey", "1", "1", "BYTE"], 6)]
+    #[case(&["BITCOUNT", "mykey", "5", "30", "BIT"], 17)]
+    #[case(&["BITCOUNT", "mykey", "-100", "100"], 26)]
+    #[case(&["BITCOUNT", "mykey", "4", "2"], 0)]
+    #[case(&["BITCOUNT", "missing"], 0)]
+    fn test_bitcount(#[case] args: &[&str], #[case] expected: i64) {
+        let args: Vec<&[u8]> = args.iter().map(|arg| arg.as_bytes()).collect();
+        let server = Arc::new(Server::new(HashMap::new()));
+        execute(&server, &[b"SET", b"mykey", b"foobar"]);
+        assert_eq!(execute(&server, &args), RespElement::Integer(expected));
+    }
+
+    #[test]
+    fn test_bitop_and_pads_shorter_values() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        execute(&server, &[b"SET", b"a", b"abc"]);
+        execute(&server, &[b"SET", b"b", b"a"]);
+
+        let resp = execute(&server, &[b"BITOP", b"AND", b"dest", b"a", b"b"]);
+        assert_eq!(resp, RespElement::Integer(3));
+        assert_eq!(
+            server.db.get_live("dest").unwrap().value,
+            b"a\0\0".to_vec().into()
+        );
+    }
+
+    #[test]
+    fn test_bitop_not() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        execute(&server, &[b"SET", b"a", b"foo"]);
+
+        let resp = execute(&server, &[b"BITOP", b"NOT", b"dest", b"a"]);
+        assert_eq!(resp, RespElement::Integer(3));
+        assert_eq!(
+            server.db.get_live("dest").unwrap().value,
+            vec![!b'f', !b'o', !b'o'].into()
+        );
+    }
+
+    #[test]
+    fn test_bitop_not_requires_one_source() {
+        let result = command(&[b"BITOP", b"NOT", b"dest", b"a", b"b"]);
+        assert!(matches!(result, Err(CommandError::BitOpNotArity)));
+    }
+
+    #[test]
+    fn test_bitop_missing_sources_deletes_dest() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        execute(&server, &[b"SET", b"dest", b"value"]);
+
+        let resp = execute(&server, &[b"BITOP", b"OR", b"dest", b"missing"]);
+        assert_eq!(resp, RespElement::Integer(0));
+        assert!(server.db.get_live("dest").is_none());
+    }
+}
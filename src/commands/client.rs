@@ -0,0 +1,226 @@
+use std::sync::Arc;
+
+use crate::{
+    clients::ClientState,
+    parse::{RespElement, SimpleError},
+    Server,
+};
+
+use super::{parse_int, Command, CommandError, CommandExecutor, FromResp};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum ClientCommand {
+    List,
+    Kill(u64),
+    /// The `CLIENT LIST` line for the calling connection.
+    Info,
+    NoEvict(bool),
+    NoTouch(bool),
+}
+
+impl ClientCommand {
+    /// Execute a subcommand which reads or changes the state of the calling connection.
+    pub(crate) fn execute_for(
+        self,
+        server: &Arc<Server>,
+        client: &mut ClientState,
+    ) -> Vec<RespElement> {
+        let reply = match self {
+            // Connections are registered for as long as they're open, so this always has a line.
+            Self::Info => {
+                RespElement::BulkString(server.clients.info(client.id).unwrap_or_default().into())
+            }
+            Self::NoEvict(on) => {
+                client.no_evict = on;
+                server.clients.set_flags(client.id, client.flags());
+                RespElement::SimpleString("OK".to_owned().into())
+            }
+            Self::NoTouch(on) => {
+                client.no_touch = on;
+                server.clients.set_flags(client.id, client.flags());
+                RespElement::SimpleString("OK".to_owned().into())
+            }
+            cmd => cmd.execute(server),
+        };
+        vec![reply]
+    }
+}
+
+impl CommandExecutor for ClientCommand {
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        match self {
+            Self::List => RespElement::BulkString(server.clients.list().into()),
+            Self::Kill(id) => RespElement::Integer(server.clients.kill(id) as i64),
+            Self::Info | Self::NoEvict(_) | Self::NoTouch(_) => {
+                RespElement::SimpleError(SimpleError::from(
+                    "ERR CLIENT subcommand is only allowed from a client connection".to_owned(),
+                ))
+            }
+        }
+    }
+}
+
+/// Parse the `on` or `off` argument of subcommands like `CLIENT NO-EVICT`.
+fn parse_on_off(elements: &[RespElement]) -> Result<bool, CommandError> {
+    match elements {
+        [_, _, RespElement::BulkString(value)] if value.as_ref().eq_ignore_ascii_case("ON") => {
+            Ok(true)
+        }
+        [_, _, RespElement::BulkString(value)] if value.as_ref().eq_ignore_ascii_case("OFF") => {
+            Ok(false)
+        }
+        _ => Err(CommandError::SyntaxError),
+    }
+}
+
+impl FromResp for ClientCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        let subcommand = match elements.get(1) {
+            Some(RespElement::BulkString(subcommand)) => subcommand.as_ref().to_uppercase(),
+            Some(_) => return Err(CommandError::SyntaxError),
+            None => return Err(CommandError::WrongArity("client")),
+        };
+
+        match subcommand.as_str() {
+            "LIST" => Ok(ClientCommand::List),
+            "INFO" if elements.len() == 2 => Ok(ClientCommand::Info),
+            "INFO" => Err(CommandError::WrongArity("client|info")),
+            "NO-EVICT" => Ok(ClientCommand::NoEvict(parse_on_off(&elements)?)),
+            "NO-TOUCH" => Ok(ClientCommand::NoTouch(parse_on_off(&elements)?)),
+            "KILL" => {
+                if elements.len() != 4 {
+                    return Err(CommandError::SyntaxError);
+                }
+                match &elements[2] {
+                    RespElement::BulkString(filter)
+                        if filter.as_ref().eq_ignore_ascii_case("ID") =>
+                    {
+                        Ok(ClientCommand::Kill(parse_int(&elements[3])?))
+                    }
+                    _ => Err(CommandError::SyntaxError),
+                }
+            }
+            _ => Err(CommandError::UnknownSubcommand("CLIENT", subcommand)),
+        }
+    }
+}
+
+impl From<ClientCommand> for Command {
+    fn from(cmd: ClientCommand) -> Self {
+        Command::Client(cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::clients::ClientAddr;
+    use crate::commands::test_util::{command, execute};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_client_list_and_kill() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        let (first, first_kill) = server
+            .clients
+            .register(ClientAddr::Tcp("127.0.0.1:5000".parse().unwrap()));
+        let (second, _) = server
+            .clients
+            .register(ClientAddr::Tcp("127.0.0.1:5001".parse().unwrap()));
+
+        let list = execute(&server, &[b"CLIENT", b"LIST"]);
+        let RespElement::BulkString(list) = list else {
+            panic!("Expected bulk string");
+        };
+        assert!(list
+            .as_ref()
+            .contains(&format!("id={first} addr=127.0.0.1:5000")));
+        assert!(list
+            .as_ref()
+            .contains(&format!("id={second} addr=127.0.0.1:5001")));
+
+        let resp = execute(
+            &server,
+            &[b"CLIENT", b"KILL", b"ID", first.to_string().as_bytes()],
+        );
+        assert_eq!(resp, RespElement::Integer(1));
+        // The killed connection is signalled to close.
+        first_kill.notified().await;
+
+        let list = execute(&server, &[b"CLIENT", b"LIST"]);
+        let RespElement::BulkString(list) = list else {
+            panic!("Expected bulk string");
+        };
+        assert!(!list.as_ref().contains(&format!("id={first} ")));
+        assert!(list.as_ref().contains(&format!("id={second} ")));
+
+        let resp = execute(
+            &server,
+            &[b"CLIENT", b"KILL", b"ID", first.to_string().as_bytes()],
+        );
+        assert_eq!(resp, RespElement::Integer(0));
+    }
+
+    #[test]
+    fn test_client_info_and_flags() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        let (id, _) = server
+            .clients
+            .register(ClientAddr::Tcp("127.0.0.1:5000".parse().unwrap()));
+        server
+            .clients
+            .register(ClientAddr::Tcp("127.0.0.1:5001".parse().unwrap()));
+        let (mut client, _messages) = ClientState::new(id);
+
+        let resp = command(&[b"CLIENT", b"INFO"])
+            .unwrap()
+            .execute_for(&server, &mut client);
+        let [RespElement::BulkString(info)] = resp.as_slice() else {
+            panic!("Expected a bulk string, got {:?}", resp);
+        };
+        assert!(info
+            .as_ref()
+            .starts_with(&format!("id={id} addr=127.0.0.1:5000 ")));
+        assert_eq!(info.as_ref().lines().count(), 1);
+
+        let ok = vec![RespElement::SimpleString("OK".to_owned().into())];
+        assert_eq!(
+            command(&[b"CLIENT", b"NO-EVICT", b"on"])
+                .unwrap()
+                .execute_for(&server, &mut client),
+            ok
+        );
+        assert_eq!(
+            command(&[b"CLIENT", b"NO-TOUCH", b"ON"])
+                .unwrap()
+                .execute_for(&server, &mut client),
+            ok
+        );
+        assert!(client.no_evict && client.no_touch);
+        let info = |client: &mut ClientState| match command(&[b"CLIENT", b"INFO"])
+            .unwrap()
+            .execute_for(&server, client)
+            .as_slice()
+        {
+            [RespElement::BulkString(info)] => info.as_ref().to_owned(),
+            resp => panic!("Expected a bulk string, got {:?}", resp),
+        };
+        assert!(info(&mut client).contains(" flags=eT"));
+
+        // A pooled connection mustn't keep them once it's reset.
+        Command::Reset.execute_for(&server, &mut client);
+        assert!(!client.no_evict && !client.no_touch);
+        assert!(info(&mut client).contains(" flags=N"));
+        assert!(matches!(
+            command(&[b"CLIENT", b"NO-EVICT", b"maybe"]),
+            Err(CommandError::SyntaxError)
+        ));
+    }
+}
@@ -0,0 +1,114 @@
+use std::sync::Arc;
+
+use crate::{parse::RespElement, Server};
+
+use super::{Command, CommandError, CommandExecutor, FromResp};
+
+/// `CLUSTER` subcommands which cluster-aware clients send when connecting, answered as a
+/// single node without cluster support so they fall back to treating it as one.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum ClusterCommand {
+    Info,
+    Slots,
+    Shards,
+    MyId,
+}
+
+impl CommandExecutor for ClusterCommand {
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        match self {
+            Self::Info => RespElement::BulkString(
+                "cluster_enabled:0\r\n\
+                 cluster_state:ok\r\n\
+                 cluster_slots_assigned:0\r\n\
+                 cluster_known_nodes:1\r\n\
+                 cluster_size:0\r\n"
+                    .into(),
+            ),
+            Self::Slots | Self::Shards => RespElement::Array(vec![]),
+            Self::MyId => RespElement::BulkString(server.node_id.clone().into()),
+        }
+    }
+}
+
+impl FromResp for ClusterCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        let subcommand = match elements.get(1) {
+            Some(RespElement::BulkString(subcommand)) => subcommand.as_ref().to_uppercase(),
+            Some(_) => return Err(CommandError::SyntaxError),
+            None => return Err(CommandError::WrongArity("cluster")),
+        };
+
+        match (subcommand.as_str(), elements.len()) {
+            ("INFO", 2) => Ok(ClusterCommand::Info),
+            ("INFO", _) => Err(CommandError::WrongArity("cluster|info")),
+            ("SLOTS", 2) => Ok(ClusterCommand::Slots),
+            ("SLOTS", _) => Err(CommandError::WrongArity("cluster|slots")),
+            ("SHARDS", 2) => Ok(ClusterCommand::Shards),
+            ("SHARDS", _) => Err(CommandError::WrongArity("cluster|shards")),
+            ("MYID", 2) => Ok(ClusterCommand::MyId),
+            ("MYID", _) => Err(CommandError::WrongArity("cluster|myid")),
+            _ => Err(CommandError::UnknownSubcommand("CLUSTER", subcommand)),
+        }
+    }
+}
+
+impl From<ClusterCommand> for Command {
+    fn from(cmd: ClusterCommand) -> Self {
+        Command::Cluster(cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::commands::test_util::{command, execute};
+
+    use super::*;
+
+    #[test]
+    fn test_cluster_info() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        let RespElement::BulkString(info) = execute(&server, &[b"CLUSTER", b"INFO"]) else {
+            panic!("Expected a bulk string");
+        };
+        assert!(info.as_ref().contains("cluster_enabled:0\r\n"));
+        assert!(info.as_ref().contains("cluster_state:ok\r\n"));
+        assert_eq!(
+            execute(&server, &[b"CLUSTER", b"slots"]),
+            RespElement::Array(vec![])
+        );
+        assert_eq!(
+            execute(&server, &[b"CLUSTER", b"SHARDS"]),
+            RespElement::Array(vec![])
+        );
+    }
+
+    #[test]
+    fn test_cluster_myid_is_stable() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        let RespElement::BulkString(id) = execute(&server, &[b"CLUSTER", b"MYID"]) else {
+            panic!("Expected a bulk string");
+        };
+        assert_eq!(id.as_ref().len(), 40);
+        assert!(id.as_ref().chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(
+            execute(&server, &[b"CLUSTER", b"MYID"]),
+            RespElement::BulkString(id)
+        );
+    }
+
+    #[test]
+    fn test_cluster_unknown_subcommand() {
+        assert!(matches!(
+            command(&[b"CLUSTER", b"NODES"]),
+            Err(CommandError::UnknownSubcommand("CLUSTER", _))
+        ));
+    }
+}
@@ -0,0 +1,365 @@
+use std::sync::{atomic::Ordering, Arc};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+
+use crate::{
+    glob,
+    parse::{RespElement, SimpleError},
+    rdb, Server,
+};
+
+use super::{parse_int, save::SaveCommand, Command, CommandError, CommandExecutor, FromResp};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum DebugCommand {
+    /// Sleep without replying. With `global`, every shard of the keyspace is locked for
+    /// the duration, stalling every other client like a sleep in Redis' event loop.
+    Sleep {
+        duration: Duration,
+        global: bool,
+    },
+    Object(String),
+    SetActiveExpire(bool),
+    /// Save the dataset and load it back, replacing what's in memory.
+    Reload,
+    /// Whether a string matches a glob-style pattern, to test the matcher directly.
+    StringMatchLen {
+        pattern: Bytes,
+        string: Bytes,
+    },
+    /// Subcommands which only make sense for Redis' internals, accepted so test suites
+    /// which call them still run.
+    NoOp,
+}
+
+impl DebugCommand {
+    /// Execute the command on behalf of a connection, sleeping without blocking other clients
+    /// unless asked to.
+    pub(crate) async fn execute_blocking(self, server: &Arc<Server>) -> RespElement {
+        match self {
+            Self::Sleep {
+                duration,
+                global: false,
+            } => {
+                tokio::time::sleep(duration).await;
+                RespElement::SimpleString("OK".to_owned().into())
+            }
+            // The shard locks aren't async, so they're held on a thread which can block.
+            cmd @ Self::Sleep { global: true, .. } => {
+                let server = server.clone();
+                tokio::task::spawn_blocking(move || cmd.execute(&server))
+                    .await
+                    .expect("DEBUG SLEEP panicked")
+            }
+            cmd => cmd.execute(server),
+        }
+    }
+}
+
+impl CommandExecutor for DebugCommand {
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        match self {
+            // Without a connection to suspend, block the calling thread like Redis does.
+            Self::Sleep { duration, global } => {
+                let locked = global.then(|| server.db.write_all());
+                std::thread::sleep(duration);
+                drop(locked);
+                RespElement::SimpleString("OK".to_owned().into())
+            }
+            Self::Object(key) => {
+                let shard = server.db.read(&key);
+                let Some(value) = shard.get(&key).filter(|v| !v.is_expired(Instant::now())) else {
+                    return RespElement::SimpleError(SimpleError::from(
+                        "ERR no such key".to_owned(),
+                    ));
+                };
+                let serialised_len = rdb::serialise_value(&value.value).len();
+                RespElement::BulkString(
+                    format!(
+                        "Value at:{:p} refcount:1 encoding:{} serializedlength:{}",
+                        value,
+                        value.value.encoding(),
+                        serialised_len
+                    )
+                    .into(),
+                )
+            }
+            Self::SetActiveExpire(enabled) => {
+                server.active_expire.store(enabled, Ordering::Relaxed);
+                RespElement::SimpleString("OK".to_owned().into())
+            }
+            Self::Reload => {
+                let saved = SaveCommand.execute(server);
+                if let RespElement::SimpleError(_) = saved {
+                    return saved;
+                }
+                let path = server
+                    .rdb_path()
+                    .expect("SAVE succeeded without an RDB path");
+                match rdb::load(&path) {
+                    Ok(db) => {
+                        server.db.replace(db);
+                        RespElement::SimpleString("OK".to_owned().into())
+                    }
+                    Err(e) => RespElement::SimpleError(SimpleError::from(format!(
+                        "ERR Error trying to load the RDB dump: {}",
+                        e
+                    ))),
+                }
+            }
+            Self::StringMatchLen { pattern, string } => {
+                RespElement::Integer(glob::matches(&pattern, &string) as i64)
+            }
+            Self::NoOp => RespElement::SimpleString("OK".to_owned().into()),
+        }
+    }
+}
+
+impl FromResp for DebugCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        let subcommand = match elements.get(1) {
+            Some(RespElement::BulkString(subcommand)) => subcommand.as_ref().to_uppercase(),
+            Some(_) => return Err(CommandError::SyntaxError),
+            None => return Err(CommandError::WrongArity("debug")),
+        };
+
+        match subcommand.as_str() {
+            "SLEEP" => {
+                let (seconds, global) = match elements.as_slice() {
+                    [_, _, RespElement::BulkString(seconds)] => (seconds, false),
+                    [_, _, RespElement::BulkString(seconds), RespElement::BulkString(option)]
+                        if option.as_ref().eq_ignore_ascii_case("GLOBAL") =>
+                    {
+                        (seconds, true)
+                    }
+                    _ => return Err(CommandError::SyntaxError),
+                };
+                let seconds: f64 = seconds
+                    .as_ref()
+                    .parse()
+                    .map_err(|_| CommandError::NotAFloat)?;
+                // Like Redis, negative durations don't sleep at all.
+                let duration = Duration::try_from_secs_f64(seconds.max(0.0))
+                    .map_err(|_| CommandError::NotAFloat)?;
+                Ok(DebugCommand::Sleep { duration, global })
+            }
+            "OBJECT" => match elements.as_slice() {
+                [_, _, RespElement::BulkString(key)] => {
+                    Ok(DebugCommand::Object(key.as_ref().to_owned()))
+                }
+                _ => Err(CommandError::SyntaxError),
+            },
+            "SET-ACTIVE-EXPIRE" => match elements.as_slice() {
+                [_, _, enabled] => Ok(DebugCommand::SetActiveExpire(parse_int(enabled)? != 0)),
+                _ => Err(CommandError::SyntaxError),
+            },
+            "RELOAD" => match elements.as_slice() {
+                [_, _] => Ok(DebugCommand::Reload),
+                _ => Err(CommandError::SyntaxError),
+            },
+            "STRINGMATCH-LEN" => match elements.as_slice() {
+                [_, _, RespElement::BulkString(pattern), RespElement::BulkString(string)] => {
+                    Ok(DebugCommand::StringMatchLen {
+                        pattern: pattern.clone().into_bytes(),
+                        string: string.clone().into_bytes(),
+                    })
+                }
+                _ => Err(CommandError::SyntaxError),
+            },
+            "JMAP" | "QUICKLIST-PACKED-THRESHOLD" => Ok(DebugCommand::NoOp),
+            _ => Err(CommandError::UnknownSubcommand("DEBUG", subcommand)),
+        }
+    }
+}
+
+impl From<DebugCommand> for Command {
+    fn from(cmd: DebugCommand) -> Self {
+        Command::Debug(cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Instant;
+
+    use crate::{
+        commands::test_util::{command, execute},
+        db::{DbValue, Value},
+        stream::StreamId,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_debug_sleep() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        let Command::Debug(cmd) = command(&[b"DEBUG", b"SLEEP", b"0.1"]).unwrap() else {
+            panic!("Expected DEBUG");
+        };
+
+        let start = Instant::now();
+        let reply = cmd.execute_blocking(&server).await;
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(reply, RespElement::SimpleString("OK".to_owned().into()));
+    }
+
+    #[test]
+    fn test_debug_sleep_invalid() {
+        assert!(matches!(
+            command(&[b"DEBUG", b"SLEEP", b"soon"]),
+            Err(CommandError::NotAFloat)
+        ));
+        assert!(matches!(
+            command(&[b"DEBUG", b"NAP"]),
+            Err(CommandError::UnknownSubcommand("DEBUG", _))
+        ));
+    }
+
+    #[rstest::rstest]
+    #[case("12345", "int")]
+    #[case("hello", "embstr")]
+    #[case(&"x".repeat(45), "raw")]
+    fn test_debug_object(#[case] value: &str, #[case] encoding: &str) {
+        let server = Arc::new(Server::new(HashMap::new()));
+        execute(&server, &[b"SET", b"key", value.as_bytes()]);
+
+        let RespElement::BulkString(reply) = execute(&server, &[b"DEBUG", b"OBJECT", b"key"])
+        else {
+            panic!("Expected bulk string");
+        };
+        assert!(reply.as_ref().contains(&format!(" encoding:{encoding} ")));
+        assert!(reply.as_ref().contains(" serializedlength:"));
+    }
+
+    #[test]
+    fn test_debug_object_serializedlength_matches_dump() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        let value = "x".repeat(100);
+        server
+            .db
+            .insert("key".to_owned(), DbValue::new(value.into(), None));
+
+        let RespElement::BulkString(reply) = execute(&server, &[b"DEBUG", b"OBJECT", b"key"])
+        else {
+            panic!("Expected bulk string");
+        };
+        let payload = rdb::dump_value(&server.db.get_live("key").unwrap().value);
+        // The payload adds a type byte, then a two byte version and eight byte checksum.
+        let expected = payload.len() - 11;
+        assert!(
+            reply
+                .as_ref()
+                .ends_with(&format!(" serializedlength:{expected}")),
+            "{reply:?}"
+        );
+    }
+
+    #[test]
+    fn test_debug_object_missing_key() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        assert_eq!(
+            execute(&server, &[b"DEBUG", b"OBJECT", b"missing"]),
+            RespElement::SimpleError(SimpleError::from("ERR no such key".to_owned()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_active_expire() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        let reply = execute(&server, &[b"DEBUG", b"SET-ACTIVE-EXPIRE", b"0"]);
+        assert_eq!(reply, RespElement::SimpleString("OK".to_owned().into()));
+        execute(&server, &[b"SET", b"key", b"value", b"PX", b"10"]);
+
+        let task = tokio::spawn(crate::expiry::active_expire(server.clone()));
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(server.db.read("key").get("key").is_some());
+
+        execute(&server, &[b"DEBUG", b"SET-ACTIVE-EXPIRE", b"1"]);
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        task.abort();
+        assert!(server.db.read("key").get("key").is_none());
+    }
+
+    #[test]
+    fn test_debug_reload() {
+        let dir = std::env::temp_dir().join(format!("redis-reload-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut opts = HashMap::new();
+        opts.insert("dir".to_owned(), crate::OptValue::path(dir.clone()));
+        opts.insert(
+            "dbfilename".to_owned(),
+            crate::OptValue::String("dump.rdb".into()),
+        );
+        let server = Arc::new(Server::new(opts));
+        for args in [
+            ["SET", "foo", "bar", "EX", "100"],
+            ["SET", "baz", "qux", "PX", "5000"],
+            ["XADD", "stream", "1-1", "field", "value"],
+        ] {
+            let args: Vec<&[u8]> = args.iter().map(|arg| arg.as_bytes()).collect();
+            execute(&server, &args);
+        }
+
+        let reply = execute(&server, &[b"DEBUG", b"RELOAD"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(reply, RespElement::SimpleString("OK".to_owned().into()));
+
+        let ttl = |key: &str| {
+            let value = server.db.get_live(key).unwrap();
+            value.expires_at.unwrap().duration_since(Instant::now())
+        };
+        assert_eq!(server.db.get_live("foo").unwrap().value, "bar".into());
+        assert!(ttl("foo") > Duration::from_secs(98) && ttl("foo") <= Duration::from_secs(100));
+        assert_eq!(server.db.get_live("baz").unwrap().value, "qux".into());
+        assert!(ttl("baz") > Duration::from_secs(3) && ttl("baz") <= Duration::from_secs(5));
+        let Value::Stream(stream) = server.db.get_live("stream").unwrap().value else {
+            panic!("Expected a stream");
+        };
+        assert_eq!(stream.last_id, StreamId::new(1, 1));
+        assert_eq!(
+            stream.entries[&StreamId::new(1, 1)],
+            [("field".into(), "value".into())]
+        );
+    }
+
+    #[rstest::rstest]
+    #[case("h[^e]llo", "hallo", 1)]
+    #[case("h[^e]llo", "hello", 0)]
+    #[case("a\\*", "a*", 1)]
+    #[case("a\\*", "ab", 0)]
+    fn test_debug_stringmatch_len(
+        #[case] pattern: &str,
+        #[case] string: &str,
+        #[case] expected: i64,
+    ) {
+        let server = Arc::new(Server::new(HashMap::new()));
+        assert_eq!(
+            execute(
+                &server,
+                &[
+                    b"DEBUG",
+                    b"STRINGMATCH-LEN",
+                    pattern.as_bytes(),
+                    string.as_bytes()
+                ]
+            ),
+            RespElement::Integer(expected)
+        );
+    }
+
+    #[test]
+    fn test_debug_reload_without_rdb_path() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        assert!(matches!(
+            execute(&server, &[b"DEBUG", b"RELOAD"]),
+            RespElement::SimpleError(_)
+        ));
+    }
+}
@@ -0,0 +1,230 @@
+use std::{sync::Arc, time::Instant};
+
+use bytes::Bytes;
+
+use crate::{
+    db::{self, DbValue},
+    eviction, expiry,
+    parse::{NullBulkString, RespElement, SimpleError},
+    pubsub::{notify_keyspace_event, EventClass},
+    rdb, Server,
+};
+
+use super::{parse_signed_int, set::ExpiryOpt, Command, CommandError, CommandExecutor, FromResp};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct DumpCommand(String);
+
+impl CommandExecutor for DumpCommand {
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        let shard = server.db.read(&self.0);
+        let Some(value) = shard.get(&self.0).filter(|v| !v.is_expired(Instant::now())) else {
+            return NullBulkString.into();
+        };
+        RespElement::BulkString(rdb::dump_value(&value.value).into())
+    }
+}
+
+impl FromResp for DumpCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        match elements.as_slice() {
+            [_, RespElement::BulkString(key)] => Ok(DumpCommand(key.as_ref().to_owned())),
+            [_, _] => Err(CommandError::SyntaxError),
+            _ => Err(CommandError::WrongArity("dump")),
+        }
+    }
+}
+
+impl From<DumpCommand> for Command {
+    fn from(cmd: DumpCommand) -> Self {
+        Command::Dump(cmd)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct RestoreCommand {
+    key: String,
+    /// Milliseconds until the key expires, or a UNIX time with `ABSTTL`; zero never expires.
+    ttl: u64,
+    payload: Bytes,
+    replace: bool,
+    absolute_ttl: bool,
+}
+
+impl CommandExecutor for RestoreCommand {
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        let value = match rdb::restore_value(&self.payload) {
+            Ok(value) => value,
+            Err(e) => return RespElement::SimpleError(SimpleError::from(format!("ERR {}", e))),
+        };
+        let expires_at = match (self.ttl, self.absolute_ttl) {
+            (0, _) => None,
+            (ttl, false) => expiry::deadline_from(&ExpiryOpt::Milliseconds(ttl)),
+            (ttl, true) => expiry::deadline_from(&ExpiryOpt::TimestampMilliseconds(ttl)),
+        };
+        if let Err(e) = eviction::make_room(server) {
+            return RespElement::SimpleError(e);
+        }
+        let now = Instant::now();
+
+        let mut db = server.db.write(&self.key);
+        let exists = db::get_live(&mut db, &self.key).is_some();
+        if exists && !self.replace {
+            return RespElement::SimpleError(SimpleError::from(
+                "BUSYKEY Target key name already exists.".to_owned(),
+            ));
+        }
+        // A time which has already passed expires the key immediately.
+        let value = DbValue::new(value, expires_at);
+        if value.is_expired(now) {
+            db.remove(&self.key);
+        } else {
+            db.insert(self.key.clone(), value);
+        }
+        drop(db);
+        notify_keyspace_event(server, EventClass::Generic, "restore", &self.key);
+        RespElement::SimpleString("OK".to_owned().into())
+    }
+}
+
+impl FromResp for RestoreCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        if elements.len() < 4 {
+            return Err(CommandError::WrongArity("restore"));
+        }
+        let mut elements = elements.into_iter().skip(1);
+        let (Some(RespElement::BulkString(key)), Some(ttl), Some(RespElement::BulkString(payload))) =
+            (elements.next(), elements.next(), elements.next())
+        else {
+            return Err(CommandError::SyntaxError);
+        };
+        let ttl = u64::try_from(parse_signed_int(&ttl)?).map_err(|_| CommandError::InvalidTtl)?;
+
+        let mut replace = false;
+        let mut absolute_ttl = false;
+        for option in elements {
+            match option {
+                RespElement::BulkString(option)
+                    if option.as_ref().eq_ignore_ascii_case("REPLACE") =>
+                {
+                    replace = true;
+                }
+                RespElement::BulkString(option)
+                    if option.as_ref().eq_ignore_ascii_case("ABSTTL") =>
+                {
+                    absolute_ttl = true;
+                }
+                _ => return Err(CommandError::SyntaxError),
+            }
+        }
+        Ok(RestoreCommand {
+            key: key.unwrap(),
+            ttl,
+            payload: payload.into_bytes(),
+            replace,
+            absolute_ttl,
+        })
+    }
+}
+
+impl From<RestoreCommand> for Command {
+    fn from(cmd: RestoreCommand) -> Self {
+        Command::Restore(cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::commands::test_util::{command, execute};
+    use crate::db::Value;
+
+    use super::*;
+
+    #[test]
+    fn test_dump_and_restore() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        // Not valid UTF-8, so the payload must survive as exact bytes.
+        let value = Bytes::from_static(b"\xff\x00binary");
+        server
+            .db
+            .insert("foo".to_owned(), DbValue::new(value.clone().into(), None));
+
+        let RespElement::BulkString(payload) = execute(&server, &[b"DUMP", b"foo"]) else {
+            panic!("Expected a bulk string");
+        };
+        server.db.write("foo").remove("foo");
+        assert_eq!(execute(&server, &[b"DUMP", b"foo"]), NullBulkString.into());
+
+        let restore: &[&[u8]] = &[b"RESTORE", b"foo", b"0", payload.as_bytes()];
+        assert_eq!(
+            execute(&server, restore),
+            RespElement::SimpleString("OK".to_owned().into())
+        );
+        let restored = server.db.get_live("foo").unwrap();
+        assert_eq!(restored.value, Value::String(value));
+        assert_eq!(restored.expires_at, None);
+
+        assert_eq!(
+            execute(&server, restore),
+            RespElement::SimpleError(SimpleError::from(
+                "BUSYKEY Target key name already exists.".to_owned()
+            ))
+        );
+        assert_eq!(
+            execute(
+                &server,
+                &[b"RESTORE", b"foo", b"10000", payload.as_bytes(), b"REPLACE"]
+            ),
+            RespElement::SimpleString("OK".to_owned().into())
+        );
+        assert!(server.db.get_live("foo").unwrap().expires_at.is_some());
+    }
+
+    #[test]
+    fn test_restore_respects_maxmemory() {
+        let mut opts = HashMap::new();
+        opts.insert("maxmemory".to_owned(), crate::OptValue::UInt(1));
+        let server = Arc::new(Server::new(opts));
+        server
+            .db
+            .insert("foo".to_owned(), DbValue::new("bar".into(), None));
+        let RespElement::BulkString(payload) = execute(&server, &[b"DUMP", b"foo"]) else {
+            panic!("Expected a bulk string");
+        };
+
+        assert_eq!(
+            execute(&server, &[b"RESTORE", b"baz", b"0", payload.as_bytes()]),
+            RespElement::SimpleError(SimpleError::from(
+                "OOM command not allowed when used memory > 'maxmemory'.".to_owned()
+            ))
+        );
+        assert!(server.db.get_live("baz").is_none());
+    }
+
+    #[test]
+    fn test_restore_bad_payload() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        assert_eq!(
+            execute(&server, &[b"RESTORE", b"foo", b"0", b"not a payload"]),
+            RespElement::SimpleError(SimpleError::from(
+                "ERR DUMP payload version or checksum are wrong".to_owned()
+            ))
+        );
+        assert!(matches!(
+            command(&[b"RESTORE", b"foo", b"-1", b"payload"]),
+            Err(CommandError::InvalidTtl)
+        ));
+    }
+}
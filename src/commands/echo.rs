@@ -1,21 +1,14 @@
-use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex},
-};
+use std::sync::Arc;
 
-use crate::{parse::RespElement, OptValue};
+use crate::{parse::RespElement, Server};
 
-use super::{Command, CommandError, CommandExecutor, DbValue, FromResp};
+use super::{Command, CommandError, CommandExecutor, FromResp};
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub(crate) struct EchoCommand(String);
 
 impl CommandExecutor for EchoCommand {
-    fn execute(
-        self,
-        _db: &Arc<Mutex<HashMap<String, DbValue>>>,
-        _opts: &Arc<HashMap<String, OptValue>>,
-    ) -> RespElement {
+    fn execute(self, _server: &Arc<Server>) -> RespElement {
         RespElement::BulkString(self.0.into())
     }
 }
@@ -28,7 +21,7 @@ impl FromResp for EchoCommand {
         Self: Sized,
     {
         if elements.len() != 2 {
-            return Err(CommandError::InvalidCommand);
+            return Err(CommandError::WrongArity("echo"));
         }
 
         if let RespElement::BulkString(command) = &elements[0] {
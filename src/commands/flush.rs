@@ -0,0 +1,106 @@
+use std::sync::Arc;
+
+use crate::{parse::RespElement, Server};
+
+use super::{Command, CommandError, CommandExecutor, FromResp};
+
+/// `FLUSHDB` or `FLUSHALL`, which are the same as there's only a single database.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum FlushCommand {
+    Db,
+    All,
+}
+
+impl FlushCommand {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Db => "flushdb",
+            Self::All => "flushall",
+        }
+    }
+}
+
+impl CommandExecutor for FlushCommand {
+    /// Like Redis, no keyspace events are published, rather than a `del` for every key.
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        server.db.replace(std::iter::empty());
+        RespElement::SimpleString("OK".to_owned().into())
+    }
+}
+
+impl FromResp for FlushCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        let cmd = match elements.first() {
+            Some(RespElement::BulkString(name))
+                if name.as_ref().eq_ignore_ascii_case("FLUSHDB") =>
+            {
+                Self::Db
+            }
+            _ => Self::All,
+        };
+        // Flushing is always synchronous, so `ASYNC` only changes how long the reply takes.
+        match elements.as_slice() {
+            [_] => Ok(cmd),
+            [_, RespElement::BulkString(mode)]
+                if mode.as_ref().eq_ignore_ascii_case("ASYNC")
+                    || mode.as_ref().eq_ignore_ascii_case("SYNC") =>
+            {
+                Ok(cmd)
+            }
+            [_, _] => Err(CommandError::SyntaxError),
+            _ => Err(CommandError::WrongArity(cmd.name())),
+        }
+    }
+}
+
+impl From<FlushCommand> for Command {
+    fn from(cmd: FlushCommand) -> Self {
+        Command::Flush(cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        clients::ClientState,
+        commands::test_util::{command, execute},
+        OptValue,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_flush_publishes_no_events() {
+        let mut opts = HashMap::new();
+        opts.insert(
+            "notify-keyspace-events".to_owned(),
+            OptValue::String("KEA".into()),
+        );
+        let server = Arc::new(Server::new(opts));
+        let (mut client, mut messages) = ClientState::new(1);
+        command(&[b"SUBSCRIBE", b"__keyevent@0__:set", b"__keyspace@0__:foo"])
+            .unwrap()
+            .execute_for(&server, &mut client);
+
+        execute(&server, &[b"SET", b"foo", b"bar"]);
+        assert!(messages.try_recv().is_ok());
+        assert!(messages.try_recv().is_ok());
+
+        let flushes: [&[&[u8]]; 2] = [&[b"FLUSHDB"], &[b"FLUSHALL", b"ASYNC"]];
+        for flush in flushes {
+            assert_eq!(
+                execute(&server, flush),
+                RespElement::SimpleString("OK".to_owned().into())
+            );
+        }
+        assert!(server.db.get_live("foo").is_none());
+        assert!(messages.try_recv().is_err());
+    }
+}
@@ -0,0 +1,210 @@
+use std::sync::Arc;
+
+use crate::{
+    clients::ClientState,
+    parse::{RespElement, SimpleError},
+    Server, VERSION,
+};
+
+use super::{into_map, parse_int, Command, CommandError, FromResp};
+
+/// `HELLO [protover [AUTH username password] [SETNAME clientname]]`, which switches the
+/// connection's protocol version and describes the server, so is executed with
+/// `Command::execute_for`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct HelloCommand {
+    protover: Option<u64>,
+    auth: Option<(String, String)>,
+    setname: Option<String>,
+}
+
+impl HelloCommand {
+    pub(crate) fn execute_for(
+        self,
+        server: &Arc<Server>,
+        client: &mut ClientState,
+    ) -> Vec<RespElement> {
+        let error = |message: &str| {
+            vec![RespElement::SimpleError(SimpleError::from(
+                message.to_owned(),
+            ))]
+        };
+        let version = match self.protover {
+            None => client.resp_version,
+            Some(version @ (2 | 3)) => version as u8,
+            Some(_) => return error("NOPROTO unsupported protocol version"),
+        };
+        // There are no passwords, so only the default user exists and any password works.
+        if self.auth.is_some_and(|(username, _)| username != "default") {
+            return error("WRONGPASS invalid username-password pair or user is disabled.");
+        }
+        client.resp_version = version;
+        if let Some(name) = self.setname {
+            server.clients.set_name(client.id, name);
+        }
+
+        let role = if server.replication.is_replica() {
+            "replica"
+        } else {
+            "master"
+        };
+        let fields = vec![
+            RespElement::BulkString("server".into()),
+            RespElement::BulkString("redis".into()),
+            RespElement::BulkString("version".into()),
+            RespElement::BulkString(VERSION.into()),
+            RespElement::BulkString("proto".into()),
+            RespElement::Integer(version as i64),
+            RespElement::BulkString("id".into()),
+            RespElement::Integer(client.id as i64),
+            RespElement::BulkString("mode".into()),
+            RespElement::BulkString("standalone".into()),
+            RespElement::BulkString("role".into()),
+            RespElement::BulkString(role.into()),
+            RespElement::BulkString("modules".into()),
+            RespElement::Array(vec![]),
+        ];
+        let reply = if version == 3 {
+            into_map(fields)
+        } else {
+            RespElement::Array(fields)
+        };
+        vec![reply]
+    }
+}
+
+impl FromResp for HelloCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        let mut hello = HelloCommand {
+            protover: None,
+            auth: None,
+            setname: None,
+        };
+        let Some(protover) = elements.get(1) else {
+            return Ok(hello);
+        };
+        hello.protover = Some(parse_int(protover).map_err(|_| CommandError::InvalidProtover)?);
+
+        let mut args = elements[2..].iter();
+        while let Some(arg) = args.next() {
+            let RespElement::BulkString(option) = arg else {
+                return Err(CommandError::SyntaxError);
+            };
+            match option.as_ref().to_uppercase().as_str() {
+                "AUTH" => {
+                    let (
+                        Some(RespElement::BulkString(username)),
+                        Some(RespElement::BulkString(password)),
+                    ) = (args.next(), args.next())
+                    else {
+                        return Err(CommandError::SyntaxError);
+                    };
+                    hello.auth = Some((username.as_ref().to_owned(), password.as_ref().to_owned()));
+                }
+                "SETNAME" => {
+                    let Some(RespElement::BulkString(name)) = args.next() else {
+                        return Err(CommandError::SyntaxError);
+                    };
+                    hello.setname = Some(name.as_ref().to_owned());
+                }
+                _ => return Err(CommandError::SyntaxError),
+            }
+        }
+        Ok(hello)
+    }
+}
+
+impl From<HelloCommand> for Command {
+    fn from(cmd: HelloCommand) -> Self {
+        Command::Hello(cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        clients::ClientAddr, commands::test_util::command, parse::RespSerialise, OptValue,
+    };
+
+    use super::*;
+
+    /// The type marker of the only reply to a command.
+    fn reply_type(server: &Arc<Server>, client: &mut ClientState, args: &[&[u8]]) -> u8 {
+        let replies = command(args).unwrap().execute_for(server, client);
+        assert_eq!(replies.len(), 1);
+        replies[0].serialise()[0]
+    }
+
+    #[test]
+    fn test_hello_switches_to_map_replies() {
+        let mut opts = HashMap::new();
+        opts.insert("dbfilename".to_owned(), OptValue::String("dump.rdb".into()));
+        let server = Arc::new(Server::new(opts));
+        let (mut client, _messages) = ClientState::new(1);
+
+        assert_eq!(reply_type(&server, &mut client, &[b"HELLO"]), b'*');
+        assert_eq!(
+            reply_type(&server, &mut client, &[b"CONFIG", b"GET", b"dbfilename"]),
+            b'*'
+        );
+
+        assert_eq!(reply_type(&server, &mut client, &[b"HELLO", b"3"]), b'%');
+        assert_eq!(client.resp_version, 3);
+        assert_eq!(
+            command(&[b"CONFIG", b"GET", b"dbfilename"])
+                .unwrap()
+                .execute_for(&server, &mut client),
+            [RespElement::Map(vec![(
+                RespElement::BulkString("dbfilename".into()),
+                RespElement::BulkString("dump.rdb".into()),
+            )])]
+        );
+
+        assert_eq!(reply_type(&server, &mut client, &[b"HELLO", b"2"]), b'*');
+        assert_eq!(
+            reply_type(&server, &mut client, &[b"CONFIG", b"GET", b"dbfilename"]),
+            b'*'
+        );
+    }
+
+    #[test]
+    fn test_hello_errors() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        let (id, _kill) = server
+            .clients
+            .register(ClientAddr::Tcp("127.0.0.1:5000".parse().unwrap()));
+        let (mut client, _messages) = ClientState::new(id);
+        assert_eq!(
+            command(&[b"HELLO", b"4"])
+                .unwrap()
+                .execute_for(&server, &mut client),
+            [RespElement::SimpleError(SimpleError::from(
+                "NOPROTO unsupported protocol version".to_owned()
+            ))]
+        );
+        assert_eq!(
+            reply_type(
+                &server,
+                &mut client,
+                &[b"HELLO", b"3", b"AUTH", b"alice", b"secret"]
+            ),
+            b'-'
+        );
+        assert_eq!(client.resp_version, 2);
+
+        command(&[
+            b"HELLO", b"3", b"AUTH", b"default", b"secret", b"SETNAME", b"conn",
+        ])
+        .unwrap()
+        .execute_for(&server, &mut client);
+        assert_eq!(client.resp_version, 3);
+        assert!(server.clients.info(id).unwrap().contains(" name=conn "));
+    }
+}
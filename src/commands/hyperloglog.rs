@@ -0,0 +1,269 @@
+use std::sync::Arc;
+
+use crate::{
+    db::{self, DbValue, Value},
+    eviction,
+    hll::HyperLogLog,
+    parse::RespElement,
+    pubsub::{notify_keyspace_event, EventClass},
+    Server,
+};
+
+use super::{wrong_type, Command, CommandError, CommandExecutor, FromResp};
+
+/// Pull the string arguments out of a command, after the command name.
+fn string_args(elements: &[RespElement]) -> Result<Vec<String>, CommandError> {
+    let mut args = Vec::with_capacity(elements.len().saturating_sub(1));
+    for element in elements.iter().skip(1) {
+        args.push(match element {
+            RespElement::BulkString(arg) => arg.as_ref().to_owned(),
+            _ => return Err(CommandError::SyntaxError),
+        });
+    }
+    Ok(args)
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct PfAddCommand {
+    key: String,
+    elements: Vec<String>,
+}
+
+impl CommandExecutor for PfAddCommand {
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        if let Err(e) = eviction::make_room(server) {
+            return RespElement::SimpleError(e);
+        }
+
+        let mut db = server.db.write(&self.key);
+        let updated = match db::get_live(&mut db, &self.key) {
+            Some(mut existing) => {
+                let Value::HyperLogLog(hll) = &mut existing.value else {
+                    return wrong_type();
+                };
+                Some(self.elements.iter().fold(false, |changed, element| {
+                    hll.add(element.as_bytes()) | changed
+                }))
+            }
+            None => None,
+        };
+        let changed = updated.unwrap_or_else(|| {
+            let mut hll = HyperLogLog::default();
+            for element in &self.elements {
+                hll.add(element.as_bytes());
+            }
+            db.insert(self.key.clone(), DbValue::new(hll.into(), None));
+            // Creating the key counts as a change, even without any elements.
+            true
+        });
+        drop(db);
+
+        if changed {
+            notify_keyspace_event(server, EventClass::String, "pfadd", &self.key);
+        }
+        RespElement::Integer(changed as i64)
+    }
+}
+
+impl FromResp for PfAddCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        let mut args = string_args(&elements)?;
+        if args.is_empty() {
+            return Err(CommandError::WrongArity("pfadd"));
+        }
+        let elements = args.split_off(1);
+        Ok(PfAddCommand {
+            key: args.pop().unwrap(),
+            elements,
+        })
+    }
+}
+
+impl From<PfAddCommand> for Command {
+    fn from(cmd: PfAddCommand) -> Self {
+        Command::PfAdd(cmd)
+    }
+}
+
+/// The union of the HyperLogLogs stored at `keys`, treating missing keys as empty.
+fn union(server: &Server, keys: &[String]) -> Result<HyperLogLog, RespElement> {
+    let mut union = HyperLogLog::default();
+    for key in keys {
+        let merged = server.db.with_live(key, |value| match &value.value {
+            Value::HyperLogLog(hll) => {
+                union.merge(hll);
+                Ok(())
+            }
+            _ => Err(wrong_type()),
+        });
+        merged.unwrap_or(Ok(()))?;
+    }
+    Ok(union)
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct PfCountCommand(Vec<String>);
+
+impl CommandExecutor for PfCountCommand {
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        match union(server, &self.0) {
+            Ok(hll) => RespElement::Integer(hll.count() as i64),
+            Err(e) => e,
+        }
+    }
+}
+
+impl FromResp for PfCountCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        let keys = string_args(&elements)?;
+        if keys.is_empty() {
+            return Err(CommandError::WrongArity("pfcount"));
+        }
+        Ok(PfCountCommand(keys))
+    }
+}
+
+impl From<PfCountCommand> for Command {
+    fn from(cmd: PfCountCommand) -> Self {
+        Command::PfCount(cmd)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct PfMergeCommand {
+    dest: String,
+    sources: Vec<String>,
+}
+
+impl CommandExecutor for PfMergeCommand {
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        if let Err(e) = eviction::make_room(server) {
+            return RespElement::SimpleError(e);
+        }
+        let sources = match union(server, &self.sources) {
+            Ok(hll) => hll,
+            Err(e) => return e,
+        };
+
+        let mut db = server.db.write(&self.dest);
+        let merged = match db::get_live(&mut db, &self.dest) {
+            Some(mut existing) => {
+                let Value::HyperLogLog(hll) = &mut existing.value else {
+                    return wrong_type();
+                };
+                hll.merge(&sources);
+                true
+            }
+            None => false,
+        };
+        if !merged {
+            db.insert(self.dest.clone(), DbValue::new(sources.into(), None));
+        }
+        drop(db);
+
+        notify_keyspace_event(server, EventClass::String, "pfadd", &self.dest);
+        RespElement::SimpleString("OK".to_owned().into())
+    }
+}
+
+impl FromResp for PfMergeCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        let mut args = string_args(&elements)?;
+        if args.is_empty() {
+            return Err(CommandError::WrongArity("pfmerge"));
+        }
+        let sources = args.split_off(1);
+        Ok(PfMergeCommand {
+            dest: args.pop().unwrap(),
+            sources,
+        })
+    }
+}
+
+impl From<PfMergeCommand> for Command {
+    fn from(cmd: PfMergeCommand) -> Self {
+        Command::PfMerge(cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::commands::test_util::execute;
+
+    fn pfadd_range(server: &Arc<Server>, key: &str, range: std::ops::Range<usize>) {
+        let elements: Vec<String> = range.map(|i| format!("element:{i}")).collect();
+        let mut args: Vec<&[u8]> = vec![b"PFADD", key.as_bytes()];
+        args.extend(elements.iter().map(String::as_bytes));
+        execute(server, &args);
+    }
+
+    fn pfcount(server: &Arc<Server>, keys: &[&str]) -> i64 {
+        let mut args: Vec<&[u8]> = vec![b"PFCOUNT"];
+        args.extend(keys.iter().map(|key| key.as_bytes()));
+        let RespElement::Integer(count) = execute(server, &args) else {
+            panic!("Expected integer");
+        };
+        count
+    }
+
+    #[test]
+    fn test_pfcount_estimate() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        pfadd_range(&server, "hll", 0..1000);
+
+        let estimate = pfcount(&server, &["hll"]);
+        assert!((estimate - 1000).abs() < 30, "estimate was {estimate}");
+    }
+
+    #[test]
+    fn test_pfadd_reports_changes() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        let resp = execute(&server, &[b"PFADD", b"hll", b"a", b"b"]);
+        assert_eq!(resp, RespElement::Integer(1));
+        let resp = execute(&server, &[b"PFADD", b"hll", b"a"]);
+        assert_eq!(resp, RespElement::Integer(0));
+        assert_eq!(pfcount(&server, &["hll", "missing"]), 2);
+    }
+
+    #[test]
+    fn test_pfmerge() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        pfadd_range(&server, "first", 0..600);
+        pfadd_range(&server, "second", 400..1000);
+
+        let resp = execute(&server, &[b"PFMERGE", b"dest", b"first", b"second"]);
+        assert_eq!(resp, RespElement::SimpleString("OK".to_owned().into()));
+        let estimate = pfcount(&server, &["dest"]);
+        assert!((estimate - 1000).abs() < 30, "estimate was {estimate}");
+        assert_eq!(pfcount(&server, &["first", "second"]), estimate);
+    }
+
+    #[test]
+    fn test_wrong_type() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        execute(&server, &[b"SET", b"string", b"value"]);
+        execute(&server, &[b"PFADD", b"hll", b"a"]);
+
+        assert_eq!(execute(&server, &[b"PFADD", b"string", b"a"]), wrong_type());
+        assert_eq!(execute(&server, &[b"PFCOUNT", b"string"]), wrong_type());
+        assert_eq!(execute(&server, &[b"GET", b"hll"]), wrong_type());
+    }
+}
@@ -0,0 +1,54 @@
+use std::sync::Arc;
+
+use crate::{parse::RespElement, Server};
+
+use super::{Command, CommandError, CommandExecutor, FromResp};
+
+/// `INFO [section ...]`, where no sections means the default ones.
+///
+/// Only the `replication` section is reported so far, and unknown sections are ignored
+/// like in Redis.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct InfoCommand(Vec<String>);
+
+impl CommandExecutor for InfoCommand {
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        let replication = self.0.is_empty()
+            || self.0.iter().any(|section| {
+                matches!(
+                    section.as_str(),
+                    "replication" | "default" | "all" | "everything"
+                )
+            });
+        let info = if replication {
+            server.replication.info()
+        } else {
+            String::new()
+        };
+        RespElement::BulkString(info.into())
+    }
+}
+
+impl FromResp for InfoCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        let mut sections = Vec::with_capacity(elements.len() - 1);
+        for element in &elements[1..] {
+            match element {
+                RespElement::BulkString(section) => sections.push(section.as_ref().to_lowercase()),
+                _ => return Err(CommandError::SyntaxError),
+            }
+        }
+        Ok(InfoCommand(sections))
+    }
+}
+
+impl From<InfoCommand> for Command {
+    fn from(cmd: InfoCommand) -> Self {
+        Command::Info(cmd)
+    }
+}
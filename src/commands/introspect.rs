@@ -0,0 +1,925 @@
+use std::sync::Arc;
+
+use crate::{
+    parse::{NullArray, RespElement, SimpleError},
+    Server,
+};
+
+use super::{Command, CommandError, CommandExecutor, FromResp};
+
+/// Static description of a command implemented by the server.
+#[derive(Debug)]
+pub(crate) struct CommandSpec {
+    pub(crate) name: &'static str,
+    /// Redis-style arity: positive for an exact argument count (including the command name),
+    /// negative for a minimum.
+    pub(crate) arity: i64,
+    /// Redis-style flags, such as `write` for commands which change the dataset.
+    pub(crate) flags: &'static [&'static str],
+    pub(crate) summary: &'static str,
+    /// Each range of key arguments, in order, such as a destination key and then its sources.
+    pub(crate) keys: &'static [KeySpec],
+}
+
+/// Where a command's key arguments live, following Redis' `first_key`/`last_key`/`step` scheme.
+///
+/// For example `MSET k1 v1 k2 v2` would be `first: 1, last: -1, step: 2`.
+#[derive(Debug)]
+pub(crate) struct KeySpec {
+    pub(crate) first: usize,
+    /// Index of the last key; negative values count back from the end of the arguments.
+    pub(crate) last: i64,
+    pub(crate) step: usize,
+    /// Like Redis' keyword search, the keys start after the first argument at or after
+    /// `first` matching this, for commands like `XREAD ... STREAMS k1 k2 id1 id2`.
+    pub(crate) keyword: Option<&'static str>,
+    /// Only this fraction of the range holds keys, such as 2 when they're followed by as
+    /// many other arguments.
+    pub(crate) limit: usize,
+    /// How the command accesses the keys, as reported by `COMMAND GETKEYSANDFLAGS`:
+    /// `RO`, `RW` or `OW` (overwrite), then the kind of access such as `access` or `update`.
+    pub(crate) flags: &'static [&'static str],
+    /// Flags used instead when the invocation doesn't include an option.
+    pub(crate) without_option: Option<OptionFlags>,
+}
+
+/// How keys are accessed when an option such as SET's `GET` isn't given.
+#[derive(Debug)]
+pub(crate) struct OptionFlags {
+    pub(crate) option: &'static str,
+    /// The first argument which could be the option, so values before it aren't mistaken
+    /// for it.
+    pub(crate) from: usize,
+    pub(crate) flags: &'static [&'static str],
+}
+
+impl KeySpec {
+    /// The only key is the first argument.
+    const fn single(flags: &'static [&'static str]) -> KeySpec {
+        KeySpec {
+            first: 1,
+            last: 1,
+            step: 1,
+            keyword: None,
+            limit: 1,
+            flags,
+            without_option: None,
+        }
+    }
+
+    /// Pick the keys out of a full invocation (including the command name).
+    pub(crate) fn extract<'a>(&self, args: &'a [String]) -> Vec<&'a str> {
+        let first = match self.keyword {
+            Some(keyword) => {
+                let found = args
+                    .iter()
+                    .skip(self.first)
+                    .position(|arg| arg.eq_ignore_ascii_case(keyword));
+                match found {
+                    Some(pos) => self.first + pos + 1,
+                    None => return Vec::new(),
+                }
+            }
+            None => self.first,
+        };
+        let last = if self.last < 0 {
+            args.len() as i64 + self.last
+        } else {
+            self.last
+        };
+        let len = (last + 1 - first as i64).max(0) as usize / self.limit;
+        args.iter()
+            .skip(first)
+            .take(len)
+            .step_by(self.step)
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// How the keys are accessed by a full invocation (including the command name).
+    fn flags(&self, args: &[String]) -> &'static [&'static str] {
+        match &self.without_option {
+            Some(without)
+                if !args
+                    .iter()
+                    .skip(without.from)
+                    .any(|arg| arg.eq_ignore_ascii_case(without.option)) =>
+            {
+                without.flags
+            }
+            _ => self.flags,
+        }
+    }
+}
+
+impl CommandSpec {
+    /// The spec in the shape of a `COMMAND INFO` reply:
+    /// `[name, arity, [flags], first key, last key, step]`.
+    fn info(&self) -> RespElement {
+        // Like Redis, the ranges are merged into one for the legacy first/last/step fields,
+        // which can't describe keys found by a keyword.
+        let (first, last, step) = match (self.keys.first(), self.keys.last()) {
+            (Some(first), Some(last)) if self.keys.iter().all(|spec| spec.keyword.is_none()) => {
+                (first.first as i64, last.last, first.step as i64)
+            }
+            _ => (0, 0, 0),
+        };
+        RespElement::Array(vec![
+            RespElement::BulkString(self.name.into()),
+            RespElement::Integer(self.arity),
+            RespElement::Array(
+                self.flags
+                    .iter()
+                    .map(|&flag| RespElement::SimpleString(flag.to_owned().into()))
+                    .collect(),
+            ),
+            RespElement::Integer(first),
+            RespElement::Integer(last),
+            RespElement::Integer(step),
+        ])
+    }
+
+    /// The keys of a full invocation (including the command name), with how each is accessed.
+    fn keys_and_flags<'a>(&self, args: &'a [String]) -> Vec<(&'a str, &'static [&'static str])> {
+        self.keys
+            .iter()
+            .flat_map(|spec| {
+                let flags = spec.flags(args);
+                spec.extract(args).into_iter().map(move |key| (key, flags))
+            })
+            .collect()
+    }
+
+    pub(crate) fn accepts_arg_count(&self, count: usize) -> bool {
+        if self.arity >= 0 {
+            count as i64 == self.arity
+        } else {
+            count as i64 >= -self.arity
+        }
+    }
+}
+
+/// Every command the server understands. Keep this in sync with `Command::try_from`.
+pub(crate) const COMMAND_TABLE: &[CommandSpec] = &[
+    CommandSpec {
+        name: "bgsave",
+        arity: -1,
+        flags: &["admin", "noscript"],
+        summary: "Asynchronously saves the database(s) to disk.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "bitcount",
+        arity: -2,
+        flags: &["readonly"],
+        summary: "Counts the number of set bits (population counting) in a string.",
+        keys: &[KeySpec::single(&["RO", "access"])],
+    },
+    CommandSpec {
+        name: "bitop",
+        arity: -4,
+        flags: &["write", "denyoom"],
+        summary: "Performs bitwise operations on multiple strings, and stores the result.",
+        keys: &[
+            KeySpec {
+                first: 2,
+                last: 2,
+                step: 1,
+                keyword: None,
+                limit: 1,
+                flags: &["OW", "update"],
+                without_option: None,
+            },
+            KeySpec {
+                first: 3,
+                last: -1,
+                step: 1,
+                keyword: None,
+                limit: 1,
+                flags: &["RO", "access"],
+                without_option: None,
+            },
+        ],
+    },
+    CommandSpec {
+        name: "client",
+        arity: -2,
+        flags: &[],
+        summary: "A container for client connection commands.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "cluster",
+        arity: -2,
+        flags: &[],
+        summary: "A container for Redis Cluster commands.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "command",
+        arity: -1,
+        flags: &["loading", "stale"],
+        summary: "Returns detailed information about all commands.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "config",
+        arity: -2,
+        flags: &[],
+        summary: "A container for server configuration commands.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "debug",
+        arity: -2,
+        flags: &["admin", "noscript", "loading", "stale"],
+        summary: "A container for debugging commands.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "dump",
+        arity: 2,
+        flags: &["readonly"],
+        summary: "Returns a serialized representation of the value stored at a key.",
+        keys: &[KeySpec::single(&["RO"])],
+    },
+    CommandSpec {
+        name: "echo",
+        arity: 2,
+        flags: &["fast"],
+        summary: "Returns the given string.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "failover",
+        arity: -1,
+        flags: &["admin", "noscript", "stale"],
+        summary: "Starts a coordinated failover from a server to one of its replicas.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "flushall",
+        arity: -1,
+        flags: &["write"],
+        summary: "Removes all keys from all databases.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "flushdb",
+        arity: -1,
+        flags: &["write"],
+        summary: "Remove all keys from the current database.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "get",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        summary: "Returns the string value of a key.",
+        keys: &[KeySpec::single(&["RO", "access"])],
+    },
+    CommandSpec {
+        name: "getrange",
+        arity: 4,
+        flags: &["readonly"],
+        summary: "Returns a substring of the string stored at a key.",
+        keys: &[KeySpec::single(&["RO", "access"])],
+    },
+    CommandSpec {
+        name: "hello",
+        arity: -1,
+        flags: &["noscript", "loading", "stale", "fast", "no_auth"],
+        summary: "Handshakes with the Redis server.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "info",
+        arity: -1,
+        flags: &["loading", "stale"],
+        summary: "Returns information and statistics about the server.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "lastsave",
+        arity: 1,
+        flags: &["loading", "stale", "fast"],
+        summary: "Returns the Unix timestamp of the last successful save to disk.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "lolwut",
+        arity: -1,
+        flags: &["readonly", "fast"],
+        summary: "Displays computer art and the Redis version.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "memory",
+        arity: -2,
+        flags: &[],
+        summary: "Reports on memory usage, such as the bytes used by a key.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "monitor",
+        arity: 1,
+        flags: &["admin", "noscript", "loading", "stale"],
+        summary: "Listens for all requests received by the server in real-time.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "object",
+        arity: -2,
+        flags: &[],
+        summary: "Inspects the internals of the value stored at a key.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "pfadd",
+        arity: -2,
+        flags: &["write", "denyoom", "fast"],
+        summary: "Adds elements to a HyperLogLog key. Creates the key if it doesn't exist.",
+        keys: &[KeySpec::single(&["RW", "insert"])],
+    },
+    CommandSpec {
+        name: "pfcount",
+        arity: -2,
+        flags: &["readonly"],
+        summary: "Returns the approximated cardinality of the set(s) observed by the HyperLogLog key(s).",
+        keys: &[KeySpec {
+            first: 1,
+            last: -1,
+            step: 1,
+            keyword: None,
+            limit: 1,
+            flags: &["RW", "access"],
+            without_option: None,
+        }],
+    },
+    CommandSpec {
+        name: "pfmerge",
+        arity: -2,
+        flags: &["write", "denyoom"],
+        summary: "Merges one or more HyperLogLog values into a single key.",
+        keys: &[
+            KeySpec {
+                first: 1,
+                last: 1,
+                step: 1,
+                keyword: None,
+                limit: 1,
+                flags: &["RW", "access", "insert"],
+                without_option: None,
+            },
+            KeySpec {
+                first: 2,
+                last: -1,
+                step: 1,
+                keyword: None,
+                limit: 1,
+                flags: &["RO", "access"],
+                without_option: None,
+            },
+        ],
+    },
+    CommandSpec {
+        name: "ping",
+        arity: -1,
+        flags: &["fast"],
+        summary: "Returns the server's liveliness response.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "psync",
+        arity: -3,
+        flags: &["admin", "noscript"],
+        summary: "An internal command used in replication.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "publish",
+        arity: 3,
+        flags: &["pubsub", "loading", "stale", "fast"],
+        summary: "Posts a message to a channel.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "quit",
+        arity: -1,
+        flags: &["noscript", "loading", "stale", "fast"],
+        summary: "Closes the connection.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "randomkey",
+        arity: 1,
+        flags: &["readonly"],
+        summary: "Returns a random key name from the database.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "replconf",
+        arity: -1,
+        flags: &["admin", "noscript", "loading", "stale"],
+        summary: "An internal command for configuring the replication stream.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "replicaof",
+        arity: 3,
+        flags: &["admin", "noscript", "stale"],
+        summary: "Configures a server as replica of another, or promotes it to a master.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "reset",
+        arity: 1,
+        flags: &["noscript", "loading", "stale", "fast"],
+        summary: "Resets the connection.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "restore",
+        arity: -4,
+        flags: &["write", "denyoom"],
+        summary: "Creates a key from the serialized representation of a value.",
+        keys: &[KeySpec::single(&["OW", "update"])],
+    },
+    CommandSpec {
+        name: "save",
+        arity: 1,
+        flags: &["admin", "noscript"],
+        summary: "Synchronously saves the database(s) to disk.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "scan",
+        arity: -2,
+        flags: &["readonly"],
+        summary: "Iterates over the key names in the database.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "set",
+        arity: -3,
+        flags: &["write", "denyoom"],
+        summary: "Sets the string value of a key, ignoring its type. The key is created if it doesn't exist.",
+        keys: &[KeySpec {
+            // Like Redis, SET only reads the old value with GET, so otherwise overwrites it.
+            without_option: Some(OptionFlags {
+                option: "GET",
+                from: 3,
+                flags: &["OW", "update"],
+            }),
+            ..KeySpec::single(&["RW", "access", "update"])
+        }],
+    },
+    CommandSpec {
+        name: "setrange",
+        arity: 4,
+        flags: &["write", "denyoom"],
+        summary: "Overwrites a part of a string value with another by an offset. Creates the key if it doesn't exist.",
+        keys: &[KeySpec::single(&["RW", "update"])],
+    },
+    CommandSpec {
+        name: "slaveof",
+        arity: 3,
+        flags: &["admin", "noscript", "stale"],
+        summary: "Sets a Redis server as a replica of another, or promotes it to being a master.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "slowlog",
+        arity: -2,
+        flags: &[],
+        summary: "Manages the slow log of commands which took too long to execute.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "subscribe",
+        arity: -2,
+        flags: &["pubsub", "noscript", "loading", "stale"],
+        summary: "Listens for messages published to channels.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "substr",
+        arity: 4,
+        flags: &["readonly"],
+        summary: "Returns a substring from a string value.",
+        keys: &[KeySpec::single(&["RO", "access"])],
+    },
+    CommandSpec {
+        name: "unsubscribe",
+        arity: -1,
+        flags: &["pubsub", "noscript", "loading", "stale"],
+        summary: "Stops listening to messages posted to channels.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "wait",
+        arity: 3,
+        flags: &["noscript"],
+        summary: "Blocks until the asynchronous replication of all preceding write commands sent by the connection is completed.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "waitaof",
+        arity: 4,
+        flags: &["noscript"],
+        summary: "Blocks until all of the preceding write commands sent by the connection are written to the append-only file of the master and/or replicas.",
+        keys: &[],
+    },
+    CommandSpec {
+        name: "xadd",
+        arity: -5,
+        flags: &["write", "denyoom", "fast"],
+        summary: "Appends a new message to a stream. Creates the key if it doesn't exist.",
+        keys: &[KeySpec::single(&["RW", "insert"])],
+    },
+    CommandSpec {
+        name: "xdel",
+        arity: -3,
+        flags: &["write", "fast"],
+        summary: "Returns the number of messages after removing them from a stream.",
+        keys: &[KeySpec::single(&["RW", "delete"])],
+    },
+    CommandSpec {
+        name: "xlen",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        summary: "Return the number of messages in a stream.",
+        keys: &[KeySpec::single(&["RO"])],
+    },
+    CommandSpec {
+        name: "xrange",
+        arity: -4,
+        flags: &["readonly"],
+        summary: "Returns the messages from a stream within a range of IDs.",
+        keys: &[KeySpec::single(&["RO", "access"])],
+    },
+    CommandSpec {
+        name: "xread",
+        arity: -4,
+        flags: &["readonly", "blocking", "movablekeys"],
+        summary: "Returns messages from multiple streams with IDs greater than the ones requested. Blocks until a message is available otherwise.",
+        keys: &[KeySpec {
+            first: 1,
+            last: -1,
+            step: 1,
+            // The keys are the first half of the arguments after STREAMS, then their IDs.
+            keyword: Some("STREAMS"),
+            limit: 2,
+            flags: &["RO", "access"],
+            without_option: None,
+        }],
+    },
+    CommandSpec {
+        name: "xrevrange",
+        arity: -4,
+        flags: &["readonly"],
+        summary: "Returns the messages from a stream within a range of IDs in reverse order.",
+        keys: &[KeySpec::single(&["RO", "access"])],
+    },
+    CommandSpec {
+        name: "xtrim",
+        arity: -4,
+        flags: &["write"],
+        summary: "Deletes messages from the beginning of a stream.",
+        keys: &[KeySpec::single(&["RW", "delete"])],
+    },
+];
+
+pub(crate) fn lookup(name: &str) -> Option<&'static CommandSpec> {
+    COMMAND_TABLE
+        .iter()
+        .find(|spec| spec.name.eq_ignore_ascii_case(name))
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum CommandIntrospect {
+    Count,
+    /// Docs for the named commands, or every command when empty.
+    Docs(Vec<String>),
+    /// The key arguments of a full command invocation.
+    GetKeys(Vec<String>),
+    /// Like `GetKeys`, along with how the command accesses each key.
+    GetKeysAndFlags(Vec<String>),
+    /// Specs for the named commands, or every command when empty, which is also what
+    /// `COMMAND` with no subcommand returns.
+    Info(Vec<String>),
+}
+
+impl CommandExecutor for CommandIntrospect {
+    fn execute(self, _server: &Arc<Server>) -> RespElement {
+        match self {
+            Self::Count => RespElement::Integer(COMMAND_TABLE.len() as i64),
+            Self::Docs(names) => {
+                let specs: Vec<&CommandSpec> = if names.is_empty() {
+                    COMMAND_TABLE.iter().collect()
+                } else {
+                    names.iter().filter_map(|name| lookup(name)).collect()
+                };
+
+                let mut docs = Vec::with_capacity(specs.len() * 2);
+                for spec in specs {
+                    docs.push(RespElement::BulkString(spec.name.into()));
+                    docs.push(RespElement::Array(vec![
+                        RespElement::BulkString("summary".into()),
+                        RespElement::BulkString(spec.summary.into()),
+                        RespElement::BulkString("arity".into()),
+                        RespElement::Integer(spec.arity),
+                    ]));
+                }
+                RespElement::Array(docs)
+            }
+            Self::GetKeys(args) => match keys_and_flags(&args) {
+                Ok(keys) => RespElement::Array(
+                    keys.into_iter()
+                        .map(|(key, _)| RespElement::BulkString(key.into()))
+                        .collect(),
+                ),
+                Err(e) => RespElement::SimpleError(e),
+            },
+            Self::GetKeysAndFlags(args) => match keys_and_flags(&args) {
+                Ok(keys) => RespElement::Array(
+                    keys.into_iter()
+                        .map(|(key, flags)| {
+                            RespElement::Array(vec![
+                                RespElement::BulkString(key.into()),
+                                RespElement::Array(
+                                    flags
+                                        .iter()
+                                        .map(|&flag| {
+                                            RespElement::SimpleString(flag.to_owned().into())
+                                        })
+                                        .collect(),
+                                ),
+                            ])
+                        })
+                        .collect(),
+                ),
+                Err(e) => RespElement::SimpleError(e),
+            },
+            Self::Info(names) if names.is_empty() => {
+                RespElement::Array(COMMAND_TABLE.iter().map(CommandSpec::info).collect())
+            }
+            // Unknown commands are null rather than left out, so replies line up with names.
+            Self::Info(names) => RespElement::Array(
+                names
+                    .iter()
+                    .map(|name| match lookup(name) {
+                        Some(spec) => spec.info(),
+                        None => NullArray.into(),
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl FromResp for CommandIntrospect {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        let subcommand = match elements.get(1) {
+            Some(RespElement::BulkString(subcommand)) => subcommand.as_ref().to_uppercase(),
+            Some(_) => return Err(CommandError::SyntaxError),
+            None => return Ok(CommandIntrospect::Info(Vec::new())),
+        };
+
+        match subcommand.as_str() {
+            "COUNT" if elements.len() == 2 => Ok(CommandIntrospect::Count),
+            "COUNT" => Err(CommandError::WrongArity("command|count")),
+            "DOCS" => Ok(CommandIntrospect::Docs(names(&elements[2..])?)),
+            "INFO" => Ok(CommandIntrospect::Info(names(&elements[2..])?)),
+            "GETKEYS" | "GETKEYSANDFLAGS" => {
+                if elements.len() < 3 {
+                    return Err(CommandError::WrongArity(if subcommand == "GETKEYS" {
+                        "command|getkeys"
+                    } else {
+                        "command|getkeysandflags"
+                    }));
+                }
+                let mut args = Vec::with_capacity(elements.len() - 2);
+                for element in &elements[2..] {
+                    args.push(match element {
+                        RespElement::BulkString(arg) => arg.as_ref().to_owned(),
+                        _ => return Err(CommandError::SyntaxError),
+                    });
+                }
+                if subcommand == "GETKEYS" {
+                    Ok(CommandIntrospect::GetKeys(args))
+                } else {
+                    Ok(CommandIntrospect::GetKeysAndFlags(args))
+                }
+            }
+            _ => Err(CommandError::UnknownSubcommand("COMMAND", subcommand)),
+        }
+    }
+}
+
+/// The keys of a full invocation given to `GETKEYS` or `GETKEYSANDFLAGS`, with how each
+/// is accessed.
+fn keys_and_flags(args: &[String]) -> Result<Vec<(&str, &'static [&'static str])>, SimpleError> {
+    let Some(spec) = lookup(&args[0]) else {
+        return Err(SimpleError::from(
+            "ERR Invalid command specified".to_owned(),
+        ));
+    };
+    if !spec.accepts_arg_count(args.len()) {
+        return Err(SimpleError::from(
+            "ERR Invalid number of arguments specified for command".to_owned(),
+        ));
+    }
+    let keys = spec.keys_and_flags(args);
+    if keys.is_empty() {
+        return Err(SimpleError::from(
+            "ERR The command has no key arguments".to_owned(),
+        ));
+    }
+    Ok(keys)
+}
+
+/// The command names given to a subcommand such as `DOCS`.
+fn names(elements: &[RespElement]) -> Result<Vec<String>, CommandError> {
+    elements
+        .iter()
+        .map(|element| match element {
+            RespElement::BulkString(name) => Ok(name.as_ref().to_owned()),
+            _ => Err(CommandError::SyntaxError),
+        })
+        .collect()
+}
+
+impl From<CommandIntrospect> for Command {
+    fn from(cmd: CommandIntrospect) -> Self {
+        Command::CommandIntrospect(cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::commands::test_util::execute;
+    use rstest::rstest;
+
+    #[test]
+    fn test_command_count_matches_table() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        let resp = execute(&server, &[b"COMMAND", b"COUNT"]);
+        assert_eq!(resp, RespElement::Integer(COMMAND_TABLE.len() as i64));
+    }
+
+    #[test]
+    fn test_command_docs() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        let resp = execute(&server, &[b"COMMAND", b"DOCS", b"get"]);
+        assert_eq!(
+            resp,
+            RespElement::Array(vec![
+                RespElement::BulkString("get".into()),
+                RespElement::Array(vec![
+                    RespElement::BulkString("summary".into()),
+                    RespElement::BulkString("Returns the string value of a key.".into()),
+                    RespElement::BulkString("arity".into()),
+                    RespElement::Integer(2),
+                ]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_command_info() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        let resp = execute(&server, &[b"COMMAND", b"INFO", b"set", b"nosuchcommand"]);
+        let flag = |flag: &str| RespElement::SimpleString(flag.to_owned().into());
+        assert_eq!(
+            resp,
+            RespElement::Array(vec![
+                RespElement::Array(vec![
+                    RespElement::BulkString("set".into()),
+                    RespElement::Integer(-3),
+                    RespElement::Array(vec![flag("write"), flag("denyoom")]),
+                    RespElement::Integer(1),
+                    RespElement::Integer(1),
+                    RespElement::Integer(1),
+                ]),
+                NullArray.into(),
+            ])
+        );
+
+        let RespElement::Array(all) = execute(&server, &[b"COMMAND", b"INFO"]) else {
+            panic!("Expected an array");
+        };
+        assert_eq!(all.len(), COMMAND_TABLE.len());
+    }
+
+    #[test]
+    fn test_command_without_subcommand() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        let RespElement::Array(all) = execute(&server, &[b"COMMAND"]) else {
+            panic!("Expected an array");
+        };
+        assert_eq!(all.len(), COMMAND_TABLE.len());
+        assert!(all.contains(&lookup("set").unwrap().info()));
+    }
+
+    #[rstest]
+    #[case(&["SET", "key", "value", "PX", "100"], &["key"])]
+    #[case(&["get", "key"], &["key"])]
+    #[case(&["XREAD", "STREAMS", "a", "b", "0", "0"], &["a", "b"])]
+    #[case(&["XREAD", "COUNT", "2", "BLOCK", "0", "streams", "a", "$"], &["a"])]
+    fn test_command_getkeys(#[case] args: &[&str], #[case] expected: &[&str]) {
+        let server = Arc::new(Server::new(HashMap::new()));
+        let mut invocation: Vec<&[u8]> = vec![b"COMMAND", b"GETKEYS"];
+        invocation.extend(args.iter().map(|arg| arg.as_bytes()));
+        let resp = execute(&server, &invocation);
+        assert_eq!(
+            resp,
+            RespElement::Array(
+                expected
+                    .iter()
+                    .map(|&key| RespElement::BulkString(key.into()))
+                    .collect()
+            )
+        );
+    }
+
+    #[rstest]
+    #[case(&["SET", "key", "value"], &[("key", &["OW", "update"][..])])]
+    #[case(&["SET", "key", "value", "get"], &[("key", &["RW", "access", "update"][..])])]
+    #[case(&["SET", "key", "GET"], &[("key", &["OW", "update"][..])])]
+    #[case(&["GET", "key"], &[("key", &["RO", "access"][..])])]
+    #[case(
+        &["XREAD", "STREAMS", "a", "b", "0", "0"],
+        &[("a", &["RO", "access"][..]), ("b", &["RO", "access"][..])]
+    )]
+    #[case(
+        &["PFMERGE", "dest", "a", "b"],
+        &[
+            ("dest", &["RW", "access", "insert"][..]),
+            ("a", &["RO", "access"][..]),
+            ("b", &["RO", "access"][..]),
+        ]
+    )]
+    fn test_command_getkeysandflags(#[case] args: &[&str], #[case] expected: &[(&str, &[&str])]) {
+        let server = Arc::new(Server::new(HashMap::new()));
+        let mut invocation: Vec<&[u8]> = vec![b"COMMAND", b"GETKEYSANDFLAGS"];
+        invocation.extend(args.iter().map(|arg| arg.as_bytes()));
+        let resp = execute(&server, &invocation);
+        assert_eq!(
+            resp,
+            RespElement::Array(
+                expected
+                    .iter()
+                    .map(|&(key, flags)| RespElement::Array(vec![
+                        RespElement::BulkString(key.into()),
+                        RespElement::Array(
+                            flags
+                                .iter()
+                                .map(|&flag| RespElement::SimpleString(flag.to_owned().into()))
+                                .collect()
+                        ),
+                    ]))
+                    .collect()
+            )
+        );
+    }
+
+    #[test]
+    fn test_command_getkeys_keyless_command() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        let resp = execute(&server, &[b"COMMAND", b"GETKEYS", b"PING"]);
+        assert_eq!(
+            resp,
+            RespElement::SimpleError(SimpleError::from(
+                "ERR The command has no key arguments".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_key_spec_with_step() {
+        // Shaped like MSET, which takes alternating keys and values.
+        let spec = KeySpec {
+            first: 1,
+            last: -1,
+            step: 2,
+            keyword: None,
+            limit: 1,
+            flags: &["OW", "update"],
+            without_option: None,
+        };
+        let args: Vec<String> = ["MSET", "k1", "v1", "k2", "v2"]
+            .iter()
+            .map(|&arg| arg.to_owned())
+            .collect();
+        assert_eq!(spec.extract(&args), vec!["k1", "k2"]);
+    }
+}
@@ -0,0 +1,314 @@
+use std::sync::Arc;
+
+use crate::{
+    glob,
+    parse::{NullBulkString, RespElement},
+    Server,
+};
+
+use super::{parse_int, Command, CommandError, CommandExecutor, FromResp};
+
+/// How many keys `SCAN` looks at without `COUNT`.
+const DEFAULT_SCAN_COUNT: usize = 10;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct RandomKeyCommand;
+
+impl CommandExecutor for RandomKeyCommand {
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        match server.db.random_key() {
+            Some(key) => RespElement::BulkString(key.into()),
+            None => NullBulkString.into(),
+        }
+    }
+}
+
+impl From<RandomKeyCommand> for Command {
+    fn from(cmd: RandomKeyCommand) -> Self {
+        Command::RandomKey(cmd)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct ScanCommand {
+    cursor: u64,
+    /// Only keys matching this glob-style pattern are returned.
+    pattern: Option<String>,
+    count: usize,
+    /// Only keys holding this type of value are returned.
+    type_name: Option<String>,
+}
+
+impl CommandExecutor for ScanCommand {
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        let (next, mut keys) = server.db.scan(self.cursor, self.count);
+        // Like Redis, the filters are applied after the keys are picked, so fewer than
+        // COUNT may be returned even when more match.
+        if let Some(pattern) = &self.pattern {
+            keys.retain(|(key, _)| glob::matches(pattern.as_bytes(), key.as_bytes()));
+        }
+        if let Some(wanted) = &self.type_name {
+            keys.retain(|(_, type_name)| wanted.eq_ignore_ascii_case(type_name));
+        }
+        RespElement::Array(vec![
+            RespElement::BulkString(next.to_string().into()),
+            RespElement::Array(
+                keys.into_iter()
+                    .map(|(key, _)| RespElement::BulkString(key.into()))
+                    .collect(),
+            ),
+        ])
+    }
+}
+
+impl FromResp for ScanCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        let cursor = match elements.get(1) {
+            Some(RespElement::BulkString(cursor)) => cursor
+                .as_ref()
+                .parse()
+                .map_err(|_| CommandError::InvalidCursor)?,
+            Some(_) => return Err(CommandError::InvalidCursor),
+            None => return Err(CommandError::WrongArity("scan")),
+        };
+
+        let mut pattern = None;
+        let mut count = DEFAULT_SCAN_COUNT;
+        let mut type_name = None;
+        let mut options = elements[2..].iter();
+        while let Some(option) = options.next() {
+            let RespElement::BulkString(option) = option else {
+                return Err(CommandError::SyntaxError);
+            };
+            let value = options.next().ok_or(CommandError::SyntaxError)?;
+            match option.as_ref().to_uppercase().as_str() {
+                "MATCH" => match value {
+                    RespElement::BulkString(value) => pattern = Some(value.as_ref().to_owned()),
+                    _ => return Err(CommandError::SyntaxError),
+                },
+                "COUNT" => {
+                    count = parse_int(value)? as usize;
+                    if count == 0 {
+                        return Err(CommandError::SyntaxError);
+                    }
+                }
+                "TYPE" => match value {
+                    RespElement::BulkString(value) => type_name = Some(value.as_ref().to_owned()),
+                    _ => return Err(CommandError::SyntaxError),
+                },
+                _ => return Err(CommandError::SyntaxError),
+            }
+        }
+        Ok(ScanCommand {
+            cursor,
+            pattern,
+            count,
+            type_name,
+        })
+    }
+}
+
+impl From<ScanCommand> for Command {
+    fn from(cmd: ScanCommand) -> Self {
+        Command::Scan(cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{HashMap, HashSet},
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        commands::test_util::{command, execute},
+        db::DbValue,
+        stream::Stream,
+    };
+
+    use super::*;
+
+    /// Scan the whole keyspace, `count` keys at a time.
+    fn scan_all(server: &Arc<Server>, count: &str) -> Vec<String> {
+        let mut cursor = "0".to_owned();
+        let mut keys = Vec::new();
+        loop {
+            let reply = execute(
+                server,
+                &[b"SCAN", cursor.as_bytes(), b"COUNT", count.as_bytes()],
+            );
+            let RespElement::Array(reply) = reply else {
+                panic!("Expected an array");
+            };
+            let [RespElement::BulkString(next), RespElement::Array(batch)] = &reply[..] else {
+                panic!("Expected a cursor and keys");
+            };
+            keys.extend(batch.iter().map(|key| match key {
+                RespElement::BulkString(key) => key.as_ref().to_owned(),
+                _ => panic!("Expected a bulk string"),
+            }));
+            cursor = next.as_ref().to_owned();
+            if cursor == "0" {
+                return keys;
+            }
+        }
+    }
+
+    #[test]
+    fn test_scan_returns_every_key_once() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        for i in 0..50 {
+            server
+                .db
+                .insert(format!("key:{i}"), DbValue::new("v".into(), None));
+        }
+        let keys = scan_all(&server, "7");
+        assert_eq!(keys.len(), 50);
+        assert_eq!(keys.iter().collect::<HashSet<_>>().len(), 50);
+
+        let reply = execute(
+            &server,
+            &[b"SCAN", b"0", b"MATCH", b"key:1?", b"COUNT", b"100"],
+        );
+        let RespElement::Array(reply) = reply else {
+            panic!("Expected an array");
+        };
+        assert_eq!(reply[0], RespElement::BulkString("0".into()));
+        assert!(matches!(&reply[1], RespElement::Array(keys) if keys.len() == 10));
+    }
+
+    #[test]
+    fn test_scan_survives_resizing() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        for i in 0..50 {
+            server
+                .db
+                .insert(format!("key:{i}"), DbValue::new("v".into(), None));
+        }
+        let mut keys = HashSet::new();
+        let mut cursor = 0;
+        let mut added = 0;
+        loop {
+            let (next, batch) = server.db.scan(cursor, 5);
+            keys.extend(batch.into_iter().map(|(key, _)| key));
+            // Grow the tables between calls, then shrink them again.
+            for i in added..added + 200 {
+                server
+                    .db
+                    .insert(format!("extra:{i}"), DbValue::new("v".into(), None));
+            }
+            for i in added..added + 200 {
+                server
+                    .db
+                    .write(&format!("extra:{i}"))
+                    .remove(&format!("extra:{i}"));
+            }
+            added += 200;
+            cursor = next;
+            if cursor == 0 {
+                break;
+            }
+        }
+        assert!((0..50).all(|i| keys.contains(&format!("key:{i}"))));
+    }
+
+    #[test]
+    fn test_scan_type() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        for i in 0..10 {
+            server
+                .db
+                .insert(format!("string:{i}"), DbValue::new("v".into(), None));
+            server.db.insert(
+                format!("stream:{i}"),
+                DbValue::new(Stream::default().into(), None),
+            );
+        }
+
+        let reply = execute(
+            &server,
+            &[b"SCAN", b"0", b"COUNT", b"100", b"TYPE", b"stream"],
+        );
+        let RespElement::Array(reply) = reply else {
+            panic!("Expected an array");
+        };
+        let RespElement::Array(keys) = &reply[1] else {
+            panic!("Expected keys");
+        };
+        assert_eq!(keys.len(), 10);
+        assert!(keys.iter().all(
+            |key| matches!(key, RespElement::BulkString(key) if key.as_ref().starts_with("stream:"))
+        ));
+
+        // Lists don't exist yet, so nothing matches.
+        let reply = execute(
+            &server,
+            &[b"SCAN", b"0", b"COUNT", b"100", b"TYPE", b"list"],
+        );
+        assert_eq!(
+            reply,
+            RespElement::Array(vec![
+                RespElement::BulkString("0".into()),
+                RespElement::Array(vec![]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_expired_keys_never_returned() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        let expired = Instant::now() - Duration::from_secs(1);
+        for i in 0..20 {
+            server.db.insert(
+                format!("expired:{i}"),
+                DbValue::new("v".into(), Some(expired)),
+            );
+        }
+        server
+            .db
+            .insert("live".to_owned(), DbValue::new("v".into(), None));
+
+        // A single call only removes the expired keys in the buckets it visits.
+        execute(&server, &[b"SCAN", b"0", b"COUNT", b"1"]);
+        assert!(server.db.snapshot().len() > 2);
+
+        for _ in 0..20 {
+            assert_eq!(
+                execute(&server, &[b"RANDOMKEY"]),
+                RespElement::BulkString("live".into())
+            );
+        }
+        assert_eq!(scan_all(&server, "3"), ["live"]);
+        // Scanning removed the expired keys it came across.
+        assert!(server.db.snapshot().keys().eq(["live"]));
+
+        server.db.write("live").remove("live");
+        assert_eq!(execute(&server, &[b"RANDOMKEY"]), NullBulkString.into());
+    }
+
+    #[test]
+    fn test_scan_invalid() {
+        assert!(matches!(
+            command(&[b"SCAN", b"abc"]),
+            Err(CommandError::InvalidCursor)
+        ));
+        assert!(matches!(
+            command(&[b"SCAN", b"0", b"COUNT", b"0"]),
+            Err(CommandError::SyntaxError)
+        ));
+        assert!(matches!(
+            command(&[b"SCAN", b"0", b"MATCH"]),
+            Err(CommandError::SyntaxError)
+        ));
+        assert!(matches!(
+            command(&[b"RANDOMKEY", b"foo"]),
+            Err(CommandError::WrongArity("randomkey"))
+        ));
+    }
+}
@@ -0,0 +1,99 @@
+use std::sync::Arc;
+
+use crate::{parse::RespElement, Server, VERSION};
+
+use super::{parse_int, Command, CommandError, CommandExecutor, FromResp};
+
+/// Size of the maze drawn by `LOLWUT`.
+const COLUMNS: usize = 40;
+const ROWS: usize = 10;
+
+/// `LOLWUT [VERSION version]`, which draws a maze like the Commodore 64 one-liner
+/// `10 PRINT CHR$(205.5+RND(1)); : GOTO 10`. The version seeds the maze, so the same
+/// version always draws the same one.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct LolwutCommand {
+    version: Option<u64>,
+}
+
+impl CommandExecutor for LolwutCommand {
+    fn execute(self, _server: &Arc<Server>) -> RespElement {
+        let mut out = maze(self.version.unwrap_or(0));
+        out.push_str("10 PRINT CHR$(205.5+RND(1)); : GOTO 10. Redis ver. ");
+        out.push_str(VERSION);
+        out.push('\n');
+        RespElement::BulkString(out.into())
+    }
+}
+
+/// Draw a maze of diagonal walls, each picked by a generator seeded with `seed`.
+fn maze(seed: u64) -> String {
+    let mut state = seed;
+    let mut out = String::with_capacity((COLUMNS + 1) * ROWS);
+    for _ in 0..ROWS {
+        for _ in 0..COLUMNS {
+            // Knuth's MMIX linear congruential generator, whose top bit is the most random.
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            out.push(if state >> 63 == 0 { '/' } else { '\\' });
+        }
+        out.push('\n');
+    }
+    out
+}
+
+impl FromResp for LolwutCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        match elements.as_slice() {
+            [_] => Ok(LolwutCommand { version: None }),
+            [_, RespElement::BulkString(option), version]
+                if option.as_ref().eq_ignore_ascii_case("VERSION") =>
+            {
+                Ok(LolwutCommand {
+                    version: Some(parse_int(version)?),
+                })
+            }
+            _ => Err(CommandError::SyntaxError),
+        }
+    }
+}
+
+impl From<LolwutCommand> for Command {
+    fn from(cmd: LolwutCommand) -> Self {
+        Command::Lolwut(cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::commands::test_util::execute;
+
+    use super::*;
+
+    #[test]
+    fn test_lolwut() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        let lolwut = |args: &[&[u8]]| {
+            let resp = execute(&server, args);
+            let RespElement::BulkString(art) = resp else {
+                panic!("Expected a bulk string, got {:?}", resp);
+            };
+            art.unwrap()
+        };
+        let art = lolwut(&[b"LOLWUT", b"VERSION", b"5"]);
+        assert!(art.ends_with(&format!("Redis ver. {}\n", VERSION)));
+        assert_eq!(art.lines().count(), ROWS + 1);
+
+        assert_eq!(lolwut(&[b"LOLWUT", b"version", b"5"]), art);
+        assert_ne!(lolwut(&[b"LOLWUT", b"VERSION", b"6"]), art);
+        assert_eq!(lolwut(&[b"LOLWUT"]), lolwut(&[b"LOLWUT", b"VERSION", b"0"]));
+    }
+}
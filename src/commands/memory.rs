@@ -0,0 +1,147 @@
+use std::{sync::Arc, time::Instant};
+
+use crate::{
+    parse::{NullBulkString, RespElement},
+    Server,
+};
+
+use super::{parse_int, Command, CommandError, CommandExecutor, FromResp};
+
+/// How many elements of a container `MEMORY USAGE` samples without `SAMPLES`.
+const DEFAULT_SAMPLES: usize = 5;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum MemoryCommand {
+    /// Estimate the bytes used by a key, sampling containers, or all of their
+    /// elements if `samples` is zero.
+    Usage { key: String, samples: usize },
+}
+
+impl CommandExecutor for MemoryCommand {
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        match self {
+            Self::Usage { key, samples } => {
+                let shard = server.db.read(&key);
+                match shard.get(&key).filter(|v| !v.is_expired(Instant::now())) {
+                    Some(value) => {
+                        RespElement::Integer(value.sampled_memory_usage(&key, samples) as i64)
+                    }
+                    None => RespElement::NullElement(NullBulkString),
+                }
+            }
+        }
+    }
+}
+
+impl FromResp for MemoryCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        let subcommand = match elements.get(1) {
+            Some(RespElement::BulkString(subcommand)) => subcommand.as_ref().to_uppercase(),
+            Some(_) => return Err(CommandError::SyntaxError),
+            None => return Err(CommandError::WrongArity("memory")),
+        };
+
+        match subcommand.as_str() {
+            "USAGE" => {
+                let key = match elements.get(2) {
+                    Some(RespElement::BulkString(key)) => key.as_ref().to_owned(),
+                    Some(_) => return Err(CommandError::SyntaxError),
+                    None => return Err(CommandError::WrongArity("memory|usage")),
+                };
+                let samples = match &elements[3..] {
+                    [] => DEFAULT_SAMPLES,
+                    [RespElement::BulkString(option), samples]
+                        if option.as_ref().eq_ignore_ascii_case("SAMPLES") =>
+                    {
+                        parse_int(samples)? as usize
+                    }
+                    _ => return Err(CommandError::SyntaxError),
+                };
+                Ok(MemoryCommand::Usage { key, samples })
+            }
+            _ => Err(CommandError::UnknownSubcommand("MEMORY", subcommand)),
+        }
+    }
+}
+
+impl From<MemoryCommand> for Command {
+    fn from(cmd: MemoryCommand) -> Self {
+        Command::Memory(cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        commands::test_util::{command, execute},
+        db::DbValue,
+        stream::{Stream, StreamId},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_memory_usage_string() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        server
+            .db
+            .insert("foo".to_owned(), DbValue::new("x".repeat(100).into(), None));
+
+        let RespElement::Integer(usage) = execute(&server, &[b"MEMORY", b"USAGE", b"foo"]) else {
+            panic!("Expected an integer");
+        };
+        assert!(usage >= 103);
+        assert_eq!(
+            execute(&server, &[b"MEMORY", b"USAGE", b"missing"]),
+            RespElement::NullElement(NullBulkString)
+        );
+    }
+
+    #[test]
+    fn test_memory_usage_samples_stream() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        let mut stream = Stream::default();
+        for seq in 1..=10 {
+            let value = if seq == 10 {
+                "v".repeat(1000)
+            } else {
+                "v".to_owned()
+            };
+            stream.add(StreamId::new(0, seq), vec![("f".into(), value.into())]);
+        }
+        server
+            .db
+            .insert("stream".to_owned(), DbValue::new(stream.into(), None));
+
+        let sampled = execute(&server, &[b"MEMORY", b"USAGE", b"stream"]);
+        let exact = execute(&server, &[b"MEMORY", b"USAGE", b"stream", b"SAMPLES", b"0"]);
+        let (RespElement::Integer(sampled), RespElement::Integer(exact)) = (sampled, exact) else {
+            panic!("Expected integers");
+        };
+        // The large entry is only counted when every entry is sampled.
+        assert!(exact > sampled + 900);
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(matches!(
+            command(&[b"MEMORY", b"USAGE"]),
+            Err(CommandError::WrongArity("memory|usage"))
+        ));
+        assert!(matches!(
+            command(&[b"MEMORY", b"USAGE", b"foo", b"SAMPLES"]),
+            Err(CommandError::SyntaxError)
+        ));
+        assert!(matches!(
+            command(&[b"MEMORY", b"DOCTOR"]),
+            Err(CommandError::UnknownSubcommand("MEMORY", _))
+        ));
+    }
+}
@@ -1,36 +1,126 @@
-use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex},
-};
+use std::sync::Arc;
 
 use bytes::Bytes;
 
+pub(crate) mod bits;
+pub(crate) mod client;
+pub(crate) mod cluster;
+pub(crate) mod debug;
+pub(crate) mod dump;
 pub(crate) mod echo;
+pub(crate) mod flush;
+pub(crate) mod hello;
+pub(crate) mod hyperloglog;
+pub(crate) mod info;
+pub(crate) mod introspect;
+pub(crate) mod keyspace;
+pub(crate) mod lolwut;
+pub(crate) mod memory;
+pub(crate) mod object;
 pub(crate) mod ping;
+pub(crate) mod pubsub;
+pub(crate) mod range;
+pub(crate) mod replication;
+pub(crate) mod save;
 pub(crate) mod set;
+pub(crate) mod slowlog;
+pub(crate) mod stream;
 
-use {echo::*, ping::*, set::*};
+use {
+    bits::*, client::*, cluster::*, debug::*, dump::*, echo::*, flush::*, hello::*, hyperloglog::*,
+    info::*, introspect::*, keyspace::*, lolwut::*, memory::*, object::*, ping::*, pubsub::*,
+    range::*, replication::*, save::*, set::*, slowlog::*, stream::*,
+};
 
 use crate::{
-    parse::{NullBulkString, RespElement},
-    OptValue,
+    clients::ClientState,
+    db::{self, DbValue, Shard, Value},
+    parse::{NullBulkString, RespElement, RespSerialise, SimpleError},
+    OptValue, Server,
 };
 
+/// What to send replicas and the AOF in place of a write command as it was received.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum Propagation {
+    /// These arguments.
+    Args(RespElement),
+    /// These arguments, with the one at `index` replaced by the command's reply, for
+    /// values only known once it's executed, such as the ID `XADD` generates.
+    WithReply {
+        args: Vec<RespElement>,
+        index: usize,
+    },
+}
+
+impl Propagation {
+    /// The command to propagate, given the reply to the one which was executed.
+    pub(crate) fn serialise(self, reply: Option<&RespElement>) -> Vec<u8> {
+        match self {
+            Self::Args(args) => args.serialise(),
+            Self::WithReply { mut args, index } => {
+                if let Some(reply) = reply {
+                    args[index] = reply.clone();
+                }
+                args.serialise()
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub(crate) enum Command {
     Ping(PingCommand),
     Echo(EchoCommand),
     Get(String),
     Set(SetCommand),
+    GetRange(GetRangeCommand),
+    SetRange(SetRangeCommand),
     GetConfig(Vec<String>),
+    Client(ClientCommand),
+    Quit,
+    CommandIntrospect(CommandIntrospect),
+    Save(SaveCommand),
+    Flush(FlushCommand),
+    BgSave(BgSaveCommand),
+    LastSave(LastSaveCommand),
+    Subscribe(SubscribeCommand),
+    Unsubscribe(UnsubscribeCommand),
+    Publish(PublishCommand),
+    BitCount(BitCountCommand),
+    BitOp(BitOpCommand),
+    PfAdd(PfAddCommand),
+    PfCount(PfCountCommand),
+    PfMerge(PfMergeCommand),
+    XAdd(XAddCommand),
+    XLen(XLenCommand),
+    XDel(XDelCommand),
+    XTrim(XTrimCommand),
+    XRange(XRangeCommand),
+    XRead(XReadCommand),
+    ReplicaOf(ReplicaOfCommand),
+    Failover(FailoverCommand),
+    ReplConf(ReplConfCommand),
+    Psync(PsyncCommand),
+    Wait(WaitCommand),
+    WaitAof(WaitAofCommand),
+    Debug(DebugCommand),
+    Monitor,
+    Reset,
+    SlowLog(SlowLogCommand),
+    Memory(MemoryCommand),
+    RandomKey(RandomKeyCommand),
+    Scan(ScanCommand),
+    Dump(DumpCommand),
+    Restore(RestoreCommand),
+    Object(ObjectCommand),
+    Lolwut(LolwutCommand),
+    Cluster(ClusterCommand),
+    Info(InfoCommand),
+    Hello(HelloCommand),
 }
 
 trait CommandExecutor {
-    fn execute(
-        self,
-        db: &Arc<Mutex<HashMap<String, DbValue>>>,
-        opts: &Arc<HashMap<String, OptValue>>,
-    ) -> RespElement;
+    fn execute(self, server: &Arc<Server>) -> RespElement;
 }
 
 trait FromResp {
@@ -41,75 +131,304 @@ trait FromResp {
         Self: Sized;
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
-pub(crate) struct DbValue {
-    value: Bytes,
-    expires_at: Option<std::time::Instant>,
-}
-
 impl Command {
-    pub(crate) fn execute(
+    /// Execute the command on behalf of a connection, returning every reply to send it.
+    /// Keys it reads aren't counted as accessed if the connection has `CLIENT NO-TOUCH` on.
+    pub(crate) fn execute_for(
         self,
-        db: &Arc<Mutex<HashMap<String, DbValue>>>,
-        opts: &Arc<HashMap<String, OptValue>>,
-    ) -> RespElement {
+        server: &Arc<Server>,
+        client: &mut ClientState,
+    ) -> Vec<RespElement> {
+        if client.no_touch {
+            db::without_touching(|| self.dispatch_for(server, client))
+        } else {
+            self.dispatch_for(server, client)
+        }
+    }
+
+    /// Commands which read or change the connection's state are handled here,
+    /// everything else is passed on to `execute`.
+    fn dispatch_for(self, server: &Arc<Server>, client: &mut ClientState) -> Vec<RespElement> {
         match self {
-            Self::Ping(ping_cmd) => ping_cmd.execute(db, opts),
-            Self::Echo(echo_cmd) => echo_cmd.execute(db, opts),
-            Self::Get(key) => {
-                let db = db.lock().unwrap();
-                match db.get(&key) {
-                    Some(db_value) => {
-                        if let Some(expires_at) = db_value.expires_at {
-                            if expires_at < std::time::Instant::now() {
-                                return NullBulkString.into();
-                            }
-                        }
+            Self::Ping(ping_cmd) => ping_cmd.execute_for(server, client),
+            Self::Client(client_cmd) => client_cmd.execute_for(server, client),
+            Self::Subscribe(subscribe_cmd) => subscribe_cmd.execute_for(server, client),
+            Self::Unsubscribe(unsubscribe_cmd) => unsubscribe_cmd.execute_for(server, client),
+            Self::Psync(psync_cmd) => psync_cmd.execute_for(server, client),
+            Self::Hello(hello_cmd) => hello_cmd.execute_for(server, client),
+            // Under RESP3, pairs of names and values are sent as a map.
+            Self::GetConfig(params) if client.resp_version == 3 => {
+                match Self::GetConfig(params).execute(server) {
+                    RespElement::Array(pairs) => vec![into_map(pairs)],
+                    reply => vec![reply],
+                }
+            }
+            Self::Monitor => {
+                client.monitoring = true;
+                server.monitors.add(client);
+                vec![RespElement::SimpleString("OK".to_owned().into())]
+            }
+            Self::ReplConf(replconf_cmd) => replconf_cmd.execute_for(server, client),
+            Self::Reset => {
+                // Transactions, databases and authentication aren't supported yet, so the
+                // protocol, name, client flags, subscriptions and monitoring are all the
+                // connection state to reset.
+                client.resp_version = 2;
+                server.clients.set_name(client.id, String::new());
+                client.no_evict = false;
+                client.no_touch = false;
+                server.clients.set_flags(client.id, client.flags());
+                UnsubscribeCommand::default().execute_for(server, client);
+                if client.monitoring {
+                    client.monitoring = false;
+                    server.monitors.remove(client.id);
+                }
+                vec![RespElement::SimpleString("RESET".to_owned().into())]
+            }
+            cmd => vec![cmd.execute(server)],
+        }
+    }
 
-                        RespElement::BulkString(db_value.value.clone().into())
-                    }
-                    None => NullBulkString.into(),
+    /// Execute the command on behalf of a connection like `execute_for`, waiting
+    /// for keys to change if it blocks.
+    pub(crate) async fn execute_blocking(
+        self,
+        server: &Arc<Server>,
+        client: &mut ClientState,
+    ) -> Vec<RespElement> {
+        if client.no_touch {
+            db::without_touching_async(self.dispatch_blocking(server, client)).await
+        } else {
+            self.dispatch_blocking(server, client).await
+        }
+    }
+
+    async fn dispatch_blocking(
+        self,
+        server: &Arc<Server>,
+        client: &mut ClientState,
+    ) -> Vec<RespElement> {
+        match self {
+            Self::XRead(xread_cmd) => vec![xread_cmd.execute_blocking(server).await],
+            Self::Wait(wait_cmd) => vec![wait_cmd.execute_blocking(server).await],
+            Self::WaitAof(waitaof_cmd) => vec![waitaof_cmd.execute_blocking(server).await],
+            Self::Debug(debug_cmd) => vec![debug_cmd.execute_blocking(server).await],
+            cmd => cmd.execute_for(server, client),
+        }
+    }
+
+    /// Whether the command may change the keyspace, so must be propagated to replicas.
+    pub(crate) fn is_write(&self) -> bool {
+        matches!(
+            self,
+            Self::Set(_)
+                | Self::BitOp(_)
+                | Self::PfAdd(_)
+                | Self::PfMerge(_)
+                | Self::XAdd(_)
+                | Self::XDel(_)
+                | Self::XTrim(_)
+                | Self::Restore(_)
+                | Self::Flush(_)
+                | Self::SetRange(_)
+        )
+    }
+
+    /// Whether the command can wait on other clients, so its execution time isn't
+    /// a sign of it being slow.
+    pub(crate) fn can_block(&self) -> bool {
+        matches!(self, Self::XRead(_) | Self::Wait(_) | Self::WaitAof(_))
+    }
+
+    /// Rewrite the command so that applying it again later, on a replica or from the
+    /// AOF, has the same effect, with what to propagate if it isn't the command as sent.
+    pub(crate) fn rewrite_for_propagation(self) -> (Command, Option<Propagation>) {
+        match self {
+            Self::Set(set_cmd) => match set_cmd.rewrite_for_propagation() {
+                Some(rewritten) => {
+                    let args = RespElement::from(&rewritten);
+                    (rewritten.into(), Some(Propagation::Args(args)))
                 }
+                None => (Self::Set(set_cmd), None),
+            },
+            Self::XAdd(xadd_cmd) => {
+                let propagation = xadd_cmd.propagation();
+                (Self::XAdd(xadd_cmd), propagation)
             }
-            Self::Set(set_cmd) => set_cmd.execute(db, opts),
+            cmd => (cmd, None),
+        }
+    }
+
+    /// Whether a RESP2 client may run the command while subscribed, when its replies
+    /// couldn't otherwise be told apart from published messages.
+    pub(crate) fn allowed_while_subscribed(&self) -> bool {
+        matches!(
+            self,
+            Self::Subscribe(_) | Self::Unsubscribe(_) | Self::Ping(_) | Self::Quit | Self::Reset
+        )
+    }
+
+    /// Whether the command administers the server, so isn't shown to monitors.
+    pub(crate) fn is_admin(&self) -> bool {
+        matches!(
+            self,
+            Self::GetConfig(_)
+                | Self::Save(_)
+                | Self::BgSave(_)
+                | Self::ReplicaOf(_)
+                | Self::Failover(_)
+                | Self::ReplConf(_)
+                | Self::Psync(_)
+                | Self::Debug(_)
+                | Self::Monitor
+        )
+    }
+
+    pub(crate) fn execute(self, server: &Arc<Server>) -> RespElement {
+        match self {
+            Self::Ping(ping_cmd) => ping_cmd.execute(server),
+            Self::Echo(echo_cmd) => echo_cmd.execute(server),
+            Self::Get(key) => match server.db.with_live(&key, expect_string) {
+                Some(Ok(s)) => RespElement::BulkString(s.into()),
+                Some(Err(e)) => RespElement::SimpleError(e),
+                None => NullBulkString.into(),
+            },
+            Self::Set(set_cmd) => set_cmd.execute(server),
+            Self::GetRange(getrange_cmd) => getrange_cmd.execute(server),
+            Self::SetRange(setrange_cmd) => setrange_cmd.execute(server),
             Self::GetConfig(params) => {
                 let mut vec = Vec::with_capacity(params.len());
                 for param in params {
-                    if let Some(value) = opts.get(&param) {
+                    if let Some(value) = server.opts.get(&param) {
                         vec.push(RespElement::BulkString(param.into()));
                         vec.push(value.into());
                     }
                 }
                 RespElement::Array(vec)
             }
+            Self::Client(client_cmd) => client_cmd.execute(server),
+            // The connection is closed by `process` once this reply has been written.
+            Self::Quit => RespElement::SimpleString("OK".to_owned().into()),
+            Self::CommandIntrospect(introspect_cmd) => introspect_cmd.execute(server),
+            Self::Save(save_cmd) => save_cmd.execute(server),
+            Self::Flush(flush_cmd) => flush_cmd.execute(server),
+            Self::BgSave(bgsave_cmd) => bgsave_cmd.execute(server),
+            Self::LastSave(lastsave_cmd) => lastsave_cmd.execute(server),
+            Self::Subscribe(_) => RespElement::SimpleError(SimpleError::from(
+                "ERR SUBSCRIBE is only allowed from a client connection".to_owned(),
+            )),
+            Self::Unsubscribe(_) => RespElement::SimpleError(SimpleError::from(
+                "ERR UNSUBSCRIBE is only allowed from a client connection".to_owned(),
+            )),
+            Self::Publish(publish_cmd) => publish_cmd.execute(server),
+            Self::BitCount(bitcount_cmd) => bitcount_cmd.execute(server),
+            Self::BitOp(bitop_cmd) => bitop_cmd.execute(server),
+            Self::PfAdd(pfadd_cmd) => pfadd_cmd.execute(server),
+            Self::PfCount(pfcount_cmd) => pfcount_cmd.execute(server),
+            Self::PfMerge(pfmerge_cmd) => pfmerge_cmd.execute(server),
+            Self::XAdd(xadd_cmd) => xadd_cmd.execute(server),
+            Self::XLen(xlen_cmd) => xlen_cmd.execute(server),
+            Self::XDel(xdel_cmd) => xdel_cmd.execute(server),
+            Self::XTrim(xtrim_cmd) => xtrim_cmd.execute(server),
+            Self::XRange(xrange_cmd) => xrange_cmd.execute(server),
+            Self::XRead(xread_cmd) => xread_cmd.execute(server),
+            Self::ReplicaOf(replicaof_cmd) => replicaof_cmd.execute(server),
+            Self::Failover(failover_cmd) => failover_cmd.execute(server),
+            Self::ReplConf(replconf_cmd) => replconf_cmd.execute(server),
+            Self::Wait(wait_cmd) => wait_cmd.execute(server),
+            Self::WaitAof(waitaof_cmd) => waitaof_cmd.execute(server),
+            Self::Debug(debug_cmd) => debug_cmd.execute(server),
+            Self::SlowLog(slowlog_cmd) => slowlog_cmd.execute(server),
+            Self::Memory(memory_cmd) => memory_cmd.execute(server),
+            Self::Lolwut(lolwut_cmd) => lolwut_cmd.execute(server),
+            Self::Cluster(cluster_cmd) => cluster_cmd.execute(server),
+            Self::Info(info_cmd) => info_cmd.execute(server),
+            Self::RandomKey(randomkey_cmd) => randomkey_cmd.execute(server),
+            Self::Scan(scan_cmd) => scan_cmd.execute(server),
+            Self::Dump(dump_cmd) => dump_cmd.execute(server),
+            Self::Restore(restore_cmd) => restore_cmd.execute(server),
+            Self::Object(object_cmd) => object_cmd.execute(server),
+            Self::Monitor => RespElement::SimpleError(SimpleError::from(
+                "ERR MONITOR is only allowed from a client connection".to_owned(),
+            )),
+            Self::Reset => RespElement::SimpleError(SimpleError::from(
+                "ERR RESET is only allowed from a client connection".to_owned(),
+            )),
+            Self::Psync(_) => RespElement::SimpleError(SimpleError::from(
+                "ERR PSYNC is only allowed from a client connection".to_owned(),
+            )),
+            Self::Hello(_) => RespElement::SimpleError(SimpleError::from(
+                "ERR HELLO is only allowed from a client connection".to_owned(),
+            )),
         }
     }
 }
 
-// FIXME: These clones do not feel good.
 impl From<&OptValue> for RespElement {
     fn from(value: &OptValue) -> Self {
+        // Cloning `Bytes` only shares them, so this doesn't copy the value.
         match value {
-            OptValue::String(s) => RespElement::BulkString(s.clone().into()),
+            OptValue::String(s) | OptValue::Path(_, s) => RespElement::BulkString(s.clone().into()),
             OptValue::UInt(i) => RespElement::Integer(*i as i64),
-            OptValue::Path(path_buf) => RespElement::BulkString(
-                path_buf
-                    .clone()
-                    .into_os_string()
-                    .into_string()
-                    .unwrap()
-                    .into(),
-            ),
+            OptValue::Int(i) => RespElement::Integer(*i),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 pub(crate) enum CommandError {
+    #[error("empty command")]
     MissingCommand,
+    #[error("invalid command")]
     InvalidCommand,
-    UnknownCommand,
+    #[error("unknown command '{0}'")]
+    UnknownCommand(String),
+    /// An unknown subcommand of a container command such as `CLIENT`.
+    #[error("unknown subcommand '{1}'. Try {0} HELP.")]
+    UnknownSubcommand(&'static str, String),
+    #[error("syntax error")]
     SyntaxError,
+    #[error("value is not an integer or out of range")]
+    NotAnInteger,
+    #[error("value is not a valid float")]
+    NotAFloat,
+    #[error("BITOP NOT must be called with a single source key.")]
+    BitOpNotArity,
+    #[error("Invalid stream ID specified as stream command argument")]
+    InvalidStreamId,
+    #[error(
+        "Unbalanced '{0}' list of streams: for each stream key an ID or '$' must be specified."
+    )]
+    UnbalancedStreams(&'static str),
+    #[error("timeout is negative")]
+    NegativeTimeout,
+    #[error("The MAXLEN argument must be >= 0.")]
+    NegativeMaxLen,
+    #[error("FAILOVER timeout must be greater than 0")]
+    InvalidFailoverTimeout,
+    #[error("FAILOVER with force option requires both a timeout and target HOST and IP.")]
+    FailoverForceArgs,
+    #[error("offset is out of range")]
+    InvalidOffset,
+    #[error("Protocol version is not an integer or out of range")]
+    InvalidProtover,
+    #[error("invalid cursor")]
+    InvalidCursor,
+    #[error("Invalid TTL value, must be >= 0")]
+    InvalidTtl,
+    /// The named command was given an expiry which isn't positive or is too large.
+    #[error("invalid expire time in '{0}' command")]
+    InvalidExpireTime(&'static str),
+    /// The named command, or `command|subcommand`, was given the wrong number of arguments.
+    #[error("wrong number of arguments for '{0}' command")]
+    WrongArity(&'static str),
+}
+
+impl CommandError {
+    /// The error reply sent to the client.
+    pub(crate) fn to_simple_error(&self) -> SimpleError {
+        SimpleError::from(format!("ERR {}", self))
+    }
 }
 
 impl TryFrom<RespElement> for Command {
@@ -125,11 +444,11 @@ impl TryFrom<RespElement> for Command {
                 let command = &elements[0];
                 match command {
                     RespElement::BulkString(command) => match command.as_ref() {
-                        "PING" => Ok(Command::Ping(PingCommand)),
+                        "PING" => Ok(PingCommand::from_resp(elements)?.into()),
                         "ECHO" => Ok(EchoCommand::from_resp(elements)?.into()),
                         "GET" => {
                             if elements.len() != 2 {
-                                return Err(CommandError::InvalidCommand);
+                                return Err(CommandError::WrongArity("get"));
                             }
 
                             let key = elements[1].clone();
@@ -139,17 +458,78 @@ impl TryFrom<RespElement> for Command {
                             }
                         }
                         "SET" => Ok(SetCommand::from_resp(elements)?.into()),
+                        "GETRANGE" | "SUBSTR" => Ok(GetRangeCommand::from_resp(elements)?.into()),
+                        "SETRANGE" => Ok(SetRangeCommand::from_resp(elements)?.into()),
+                        "CLIENT" => Ok(ClientCommand::from_resp(elements)?.into()),
+                        "QUIT" => Ok(Command::Quit),
+                        "COMMAND" => Ok(CommandIntrospect::from_resp(elements)?.into()),
+                        "SAVE" if elements.len() != 1 => Err(CommandError::WrongArity("save")),
+                        "SAVE" => Ok(SaveCommand.into()),
+                        "BGSAVE" => Ok(BgSaveCommand.into()),
+                        "LASTSAVE" if elements.len() != 1 => {
+                            Err(CommandError::WrongArity("lastsave"))
+                        }
+                        "LASTSAVE" => Ok(LastSaveCommand.into()),
+                        "FLUSHDB" | "FLUSHALL" => Ok(FlushCommand::from_resp(elements)?.into()),
+                        "SUBSCRIBE" => Ok(SubscribeCommand::from_resp(elements)?.into()),
+                        "UNSUBSCRIBE" => Ok(UnsubscribeCommand::from_resp(elements)?.into()),
+                        "PUBLISH" => Ok(PublishCommand::from_resp(elements)?.into()),
+                        "BITCOUNT" => Ok(BitCountCommand::from_resp(elements)?.into()),
+                        "BITOP" => Ok(BitOpCommand::from_resp(elements)?.into()),
+                        "PFADD" => Ok(PfAddCommand::from_resp(elements)?.into()),
+                        "PFCOUNT" => Ok(PfCountCommand::from_resp(elements)?.into()),
+                        "PFMERGE" => Ok(PfMergeCommand::from_resp(elements)?.into()),
+                        "XADD" => Ok(XAddCommand::from_resp(elements)?.into()),
+                        "XLEN" => Ok(XLenCommand::from_resp(elements)?.into()),
+                        "XDEL" => Ok(XDelCommand::from_resp(elements)?.into()),
+                        "XTRIM" => Ok(XTrimCommand::from_resp(elements)?.into()),
+                        "XRANGE" => Ok(XRangeCommand::from_resp(elements, false)?.into()),
+                        "XREVRANGE" => Ok(XRangeCommand::from_resp(elements, true)?.into()),
+                        "XREAD" => Ok(XReadCommand::from_resp(elements)?.into()),
+                        "REPLICAOF" | "SLAVEOF" => {
+                            Ok(ReplicaOfCommand::from_resp(elements)?.into())
+                        }
+                        "FAILOVER" => Ok(FailoverCommand::from_resp(elements)?.into()),
+                        "REPLCONF" => Ok(ReplConfCommand::from_resp(elements)?.into()),
+                        "PSYNC" => Ok(PsyncCommand::from_resp(elements)?.into()),
+                        "WAIT" => Ok(WaitCommand::from_resp(elements)?.into()),
+                        "WAITAOF" => Ok(WaitAofCommand::from_resp(elements)?.into()),
+                        "DEBUG" => Ok(DebugCommand::from_resp(elements)?.into()),
+                        "RANDOMKEY" if elements.len() != 1 => {
+                            Err(CommandError::WrongArity("randomkey"))
+                        }
+                        "RANDOMKEY" => Ok(RandomKeyCommand.into()),
+                        "SCAN" => Ok(ScanCommand::from_resp(elements)?.into()),
+                        "DUMP" => Ok(DumpCommand::from_resp(elements)?.into()),
+                        "RESTORE" => Ok(RestoreCommand::from_resp(elements)?.into()),
+                        "OBJECT" => Ok(ObjectCommand::from_resp(elements)?.into()),
+                        "MEMORY" => Ok(MemoryCommand::from_resp(elements)?.into()),
+                        "LOLWUT" => Ok(LolwutCommand::from_resp(elements)?.into()),
+                        "CLUSTER" => Ok(ClusterCommand::from_resp(elements)?.into()),
+                        "INFO" => Ok(InfoCommand::from_resp(elements)?.into()),
+                        "HELLO" => Ok(HelloCommand::from_resp(elements)?.into()),
+                        "SLOWLOG" => Ok(SlowLogCommand::from_resp(elements)?.into()),
+                        "MONITOR" if elements.len() != 1 => {
+                            Err(CommandError::WrongArity("monitor"))
+                        }
+                        "MONITOR" => Ok(Command::Monitor),
+                        "RESET" if elements.len() != 1 => Err(CommandError::WrongArity("reset")),
+                        "RESET" => Ok(Command::Reset),
                         "CONFIG" => {
-                            let subcommand = elements.get(1).ok_or(CommandError::SyntaxError)?;
+                            let subcommand =
+                                elements.get(1).ok_or(CommandError::WrongArity("config"))?;
                             let subcommand = match subcommand {
                                 RespElement::BulkString(subcommand) => subcommand.as_ref(),
                                 _ => return Err(CommandError::SyntaxError),
                             };
                             match subcommand {
+                                "GET" if elements.len() < 3 => {
+                                    Err(CommandError::WrongArity("config|get"))
+                                }
                                 "GET" => {
                                     let mut params = Vec::with_capacity(elements.len() - 2);
-                                    for i in 2..elements.len() {
-                                        params.push(match &elements[i] {
+                                    for element in &elements[2..] {
+                                        params.push(match element {
                                             RespElement::BulkString(param) => {
                                                 param.as_ref().to_owned()
                                             }
@@ -158,19 +538,70 @@ impl TryFrom<RespElement> for Command {
                                     }
                                     Ok(Command::GetConfig(params))
                                 }
-                                _ => Err(CommandError::UnknownCommand),
+                                _ => Err(CommandError::UnknownSubcommand(
+                                    "CONFIG",
+                                    subcommand.to_owned(),
+                                )),
                             }
                         }
-                        _ => Err(CommandError::UnknownCommand),
+                        name => Err(CommandError::UnknownCommand(name.to_owned())),
                     },
-                    _ => Err(CommandError::UnknownCommand),
+                    _ => Err(CommandError::InvalidCommand),
                 }
             }
-            _ => Err(CommandError::UnknownCommand),
+            _ => Err(CommandError::InvalidCommand),
         }
     }
 }
 
+/// The reply to a command used against a key holding a different type of value.
+fn wrong_type() -> RespElement {
+    RespElement::SimpleError(wrong_type_error())
+}
+
+fn wrong_type_error() -> SimpleError {
+    SimpleError::from(
+        "WRONGTYPE Operation against a key holding the wrong kind of value".to_owned(),
+    )
+}
+
+/// A RESP3 map of the pairs in a flat array of keys and values, as sent under RESP2.
+fn into_map(pairs: Vec<RespElement>) -> RespElement {
+    let mut pairs = pairs.into_iter();
+    let mut map = Vec::with_capacity(pairs.len() / 2);
+    while let (Some(key), Some(value)) = (pairs.next(), pairs.next()) {
+        map.push((key, value));
+    }
+    RespElement::Map(map)
+}
+
+/// The bytes of a string value, for commands which only work on strings, or the
+/// WRONGTYPE error for any other type.
+fn expect_string(value: &DbValue) -> Result<Bytes, SimpleError> {
+    match &value.value {
+        Value::String(bytes) => Ok(bytes.clone()),
+        _ => Err(wrong_type_error()),
+    }
+}
+
+/// Change the bytes of the string under `key` in place with `f`, for commands which write
+/// into strings, creating an empty one if the key doesn't exist. The WRONGTYPE error for any
+/// other type.
+fn with_string_mut<T>(
+    shard: &mut Shard,
+    key: &str,
+    f: impl FnOnce(&mut Bytes) -> T,
+) -> Result<T, SimpleError> {
+    if db::get_live(shard, key).is_none() {
+        shard.insert(key.to_owned(), DbValue::new(Bytes::new().into(), None));
+    }
+    let mut value = shard.get_mut(key).expect("the key was just created");
+    match &mut value.value {
+        Value::String(bytes) => Ok(f(bytes)),
+        _ => Err(wrong_type_error()),
+    }
+}
+
 // Docs say expiries should be positive integers, but the tests were sending a bulk string.
 fn parse_int(element: &RespElement) -> Result<u64, CommandError> {
     match element {
@@ -182,3 +613,131 @@ fn parse_int(element: &RespElement) -> Result<u64, CommandError> {
         _ => Err(CommandError::SyntaxError),
     }
 }
+
+fn parse_signed_int(element: &RespElement) -> Result<i64, CommandError> {
+    match element {
+        RespElement::Integer(value) => Ok(*value),
+        RespElement::BulkString(value) => value
+            .as_ref()
+            .parse()
+            .map_err(|_| CommandError::NotAnInteger),
+        _ => Err(CommandError::NotAnInteger),
+    }
+}
+
+/// Helpers for building and running commands in tests.
+#[cfg(test)]
+pub(crate) mod test_util {
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+
+    use crate::{parse::RespElement, Server};
+
+    use super::{Command, CommandError};
+
+    /// Parse a command from its arguments, as a client would send them.
+    pub(crate) fn command(args: &[&[u8]]) -> Result<Command, CommandError> {
+        Command::try_from(RespElement::Array(
+            args.iter()
+                .map(|&arg| RespElement::BulkString(Bytes::copy_from_slice(arg).into()))
+                .collect(),
+        ))
+    }
+
+    /// Parse and run a command, which must be valid.
+    pub(crate) fn execute(server: &Arc<Server>, args: &[&[u8]]) -> RespElement {
+        command(args).unwrap().execute(server)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::clients::ClientAddr;
+
+    use super::*;
+    use test_util::{command, execute};
+
+    #[rstest::rstest]
+    #[case(&["GET", "key"])]
+    #[case(&["GETRANGE", "key", "0", "-1"])]
+    #[case(&["BITCOUNT", "key"])]
+    #[case(&["SETRANGE", "key", "0", "x"])]
+    #[case(&["SETRANGE", "key", "0", ""])]
+    fn test_string_commands_reject_other_types(#[case] args: &[&str]) {
+        let args: Vec<&[u8]> = args.iter().map(|arg| arg.as_bytes()).collect();
+        let server = Arc::new(Server::new(HashMap::new()));
+        execute(&server, &[b"XADD", b"key", b"1-1", b"field", b"value"]);
+        assert_eq!(execute(&server, &args), wrong_type());
+
+        execute(&server, &[b"FLUSHALL"]);
+        execute(&server, &[b"PFADD", b"key", b"a"]);
+        assert_eq!(execute(&server, &args), wrong_type());
+    }
+
+    #[test]
+    fn test_reset_restores_connection_state() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        let (id, _kill) = server
+            .clients
+            .register(ClientAddr::Tcp("127.0.0.1:5000".parse().unwrap()));
+        let (mut client, _messages) = ClientState::new(id);
+        for args in [
+            &[&b"HELLO"[..], b"3", b"SETNAME", b"conn"][..],
+            &[b"CLIENT", b"NO-EVICT", b"on"],
+            &[b"CLIENT", b"NO-TOUCH", b"on"],
+            &[b"SUBSCRIBE", b"a", b"b"],
+            &[b"MONITOR"],
+        ] {
+            command(args).unwrap().execute_for(&server, &mut client);
+        }
+        assert!(server.clients.info(id).unwrap().contains(" name=conn "));
+
+        assert_eq!(
+            command(&[b"RESET"])
+                .unwrap()
+                .execute_for(&server, &mut client),
+            [RespElement::SimpleString("RESET".to_owned().into())]
+        );
+        assert!(client.channels.is_empty());
+        assert!(!client.monitoring);
+        assert!(!server.monitors.is_active());
+        assert_eq!(server.pubsub.publish("a", "hello"), 0);
+        assert_eq!(client.resp_version, 2);
+
+        let reply = command(&[b"CLIENT", b"INFO"])
+            .unwrap()
+            .execute_for(&server, &mut client);
+        let [RespElement::BulkString(info)] = reply.as_slice() else {
+            panic!("Expected a bulk string");
+        };
+        let info = info.as_ref();
+        assert!(info.contains(" name= "), "{info}");
+        assert!(info.contains(" flags=N"), "{info}");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_config_get_non_utf8_dir() {
+        use std::{ffi::OsString, os::unix::ffi::OsStringExt, path::PathBuf};
+
+        let dir = b"/tmp/\xffdir".to_vec();
+        let mut opts = HashMap::new();
+        opts.insert(
+            "dir".to_owned(),
+            OptValue::path(PathBuf::from(OsString::from_vec(dir.clone()))),
+        );
+        let server = Arc::new(Server::new(opts));
+
+        let resp = Command::GetConfig(vec!["dir".to_owned()]).execute(&server);
+        assert_eq!(
+            resp,
+            RespElement::Array(vec![
+                RespElement::BulkString("dir".into()),
+                RespElement::BulkString(dir.into()),
+            ])
+        );
+    }
+}
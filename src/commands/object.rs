@@ -0,0 +1,191 @@
+use std::{sync::Arc, time::Instant};
+
+use crate::{
+    parse::{RespElement, SimpleError},
+    Server,
+};
+
+use super::{Command, CommandError, CommandExecutor, FromResp};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum ObjectCommand {
+    Encoding(String),
+    RefCount(String),
+    /// Seconds since the key was last read or written.
+    IdleTime(String),
+    Freq(String),
+}
+
+impl ObjectCommand {
+    fn key(&self) -> &str {
+        match self {
+            Self::Encoding(key) | Self::RefCount(key) | Self::IdleTime(key) | Self::Freq(key) => {
+                key
+            }
+        }
+    }
+}
+
+impl CommandExecutor for ObjectCommand {
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        // Inspecting a key isn't an access, so this doesn't touch it.
+        let shard = server.db.read(self.key());
+        let Some(value) = shard
+            .get(self.key())
+            .filter(|v| !v.is_expired(Instant::now()))
+        else {
+            return RespElement::SimpleError(SimpleError::from("ERR no such key".to_owned()));
+        };
+        match self {
+            Self::Encoding(_) => RespElement::BulkString(value.value.encoding().into()),
+            // Values are never shared between keys.
+            Self::RefCount(_) => RespElement::Integer(1),
+            Self::IdleTime(_) => {
+                RespElement::Integer(value.last_access.idle_time().as_secs() as i64)
+            }
+            // Access frequency is only tracked by LFU eviction, which isn't supported.
+            Self::Freq(_) => RespElement::SimpleError(SimpleError::from(
+                "ERR An LFU maxmemory policy is not selected, access frequency not tracked."
+                    .to_owned(),
+            )),
+        }
+    }
+}
+
+impl FromResp for ObjectCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        let subcommand = match elements.get(1) {
+            Some(RespElement::BulkString(subcommand)) => subcommand.as_ref().to_uppercase(),
+            Some(_) => return Err(CommandError::SyntaxError),
+            None => return Err(CommandError::WrongArity("object")),
+        };
+        let key = match elements.as_slice() {
+            [_, _, RespElement::BulkString(key)] => key.as_ref().to_owned(),
+            [_, _, _] => return Err(CommandError::SyntaxError),
+            _ => return Err(CommandError::UnknownSubcommand("OBJECT", subcommand)),
+        };
+
+        match subcommand.as_str() {
+            "ENCODING" => Ok(ObjectCommand::Encoding(key)),
+            "REFCOUNT" => Ok(ObjectCommand::RefCount(key)),
+            "IDLETIME" => Ok(ObjectCommand::IdleTime(key)),
+            "FREQ" => Ok(ObjectCommand::Freq(key)),
+            _ => Err(CommandError::UnknownSubcommand("OBJECT", subcommand)),
+        }
+    }
+}
+
+impl From<ObjectCommand> for Command {
+    fn from(cmd: ObjectCommand) -> Self {
+        Command::Object(cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Duration};
+
+    use crate::{
+        clients::ClientState,
+        commands::test_util::{command, execute},
+        db::DbValue,
+        stream::Stream,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_object_encoding_and_refcount() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        server
+            .db
+            .insert("foo".to_owned(), DbValue::new("123".into(), None));
+
+        assert_eq!(
+            execute(&server, &[b"OBJECT", b"ENCODING", b"foo"]),
+            RespElement::BulkString("int".into())
+        );
+        assert_eq!(
+            execute(&server, &[b"OBJECT", b"REFCOUNT", b"foo"]),
+            RespElement::Integer(1)
+        );
+        assert_eq!(
+            execute(&server, &[b"OBJECT", b"IDLETIME", b"missing"]),
+            RespElement::SimpleError(SimpleError::from("ERR no such key".to_owned()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_object_idletime_increases() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        server
+            .db
+            .insert("foo".to_owned(), DbValue::new("bar".into(), None));
+        assert_eq!(
+            execute(&server, &[b"OBJECT", b"IDLETIME", b"foo"]),
+            RespElement::Integer(0)
+        );
+
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        assert_eq!(
+            execute(&server, &[b"OBJECT", b"IDLETIME", b"foo"]),
+            RespElement::Integer(1)
+        );
+
+        // Reading the key resets its idle time.
+        server.db.get_live("foo");
+        assert_eq!(
+            execute(&server, &[b"OBJECT", b"IDLETIME", b"foo"]),
+            RespElement::Integer(0)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_only_reads_reset_idletime() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        server
+            .db
+            .insert("foo".to_owned(), DbValue::new("bar".into(), None));
+        server
+            .db
+            .insert("s".to_owned(), DbValue::new(Stream::default().into(), None));
+        let (mut client, _messages) = ClientState::new(1);
+        let xread = command(&[b"XREAD", b"STREAMS", b"s", b"0"]).unwrap();
+        let get = command(&[b"GET", b"foo"]).unwrap();
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+
+        // Neither inspecting the key, nor reading it with NO-TOUCH, counts as an access.
+        assert_eq!(
+            execute(&server, &[b"OBJECT", b"IDLETIME", b"foo"]),
+            RespElement::Integer(1)
+        );
+        assert_eq!(
+            execute(&server, &[b"OBJECT", b"IDLETIME", b"foo"]),
+            RespElement::Integer(1)
+        );
+        client.no_touch = true;
+        get.clone().execute_for(&server, &mut client);
+        assert_eq!(
+            execute(&server, &[b"OBJECT", b"IDLETIME", b"foo"]),
+            RespElement::Integer(1)
+        );
+        // Nor do commands which can block.
+        xread.execute_blocking(&server, &mut client).await;
+        assert_eq!(
+            execute(&server, &[b"OBJECT", b"IDLETIME", b"s"]),
+            RespElement::Integer(1)
+        );
+
+        client.no_touch = false;
+        get.execute_for(&server, &mut client);
+        assert_eq!(
+            execute(&server, &[b"OBJECT", b"IDLETIME", b"foo"]),
+            RespElement::Integer(0)
+        );
+    }
+}
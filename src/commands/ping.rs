@@ -1,21 +1,111 @@
-use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex},
-};
+use std::sync::Arc;
 
-use crate::{parse::RespElement, OptValue};
+use crate::{clients::ClientState, parse::RespElement, Server};
 
-use super::{CommandExecutor, DbValue};
+use super::{Command, CommandError, CommandExecutor, FromResp};
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
-pub(crate) struct PingCommand;
+/// `PING [message]`, which replies with the message if given, or `PONG`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct PingCommand(Option<String>);
 
-impl CommandExecutor for PingCommand {
-    fn execute(
+impl PingCommand {
+    /// Under RESP2, a subscribed client can only tell replies from published messages by
+    /// their shape, so the reply is framed like a message: `["pong", message]`.
+    pub(crate) fn execute_for(
         self,
-        _db: &Arc<Mutex<HashMap<String, DbValue>>>,
-        _opts: &Arc<HashMap<String, OptValue>>,
-    ) -> RespElement {
-        RespElement::SimpleString("PONG".to_owned().into())
+        server: &Arc<Server>,
+        client: &mut ClientState,
+    ) -> Vec<RespElement> {
+        if client.channels.is_empty() || client.resp_version != 2 {
+            return vec![self.execute(server)];
+        }
+        vec![RespElement::Array(vec![
+            RespElement::BulkString("pong".into()),
+            RespElement::BulkString(self.0.unwrap_or_default().into()),
+        ])]
+    }
+}
+
+impl CommandExecutor for PingCommand {
+    fn execute(self, _server: &Arc<Server>) -> RespElement {
+        match self.0 {
+            Some(message) => RespElement::BulkString(message.into()),
+            None => RespElement::SimpleString("PONG".to_owned().into()),
+        }
+    }
+}
+
+impl FromResp for PingCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        match elements.as_slice() {
+            [_] => Ok(PingCommand(None)),
+            [_, RespElement::BulkString(message)] => {
+                Ok(PingCommand(Some(message.as_ref().to_owned())))
+            }
+            [_, _] => Err(CommandError::SyntaxError),
+            _ => Err(CommandError::WrongArity("ping")),
+        }
+    }
+}
+
+impl From<PingCommand> for Command {
+    fn from(cmd: PingCommand) -> Self {
+        Command::Ping(cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::commands::test_util::command;
+
+    fn execute_for(
+        server: &Arc<Server>,
+        client: &mut ClientState,
+        args: &[&[u8]],
+    ) -> Vec<RespElement> {
+        command(args).unwrap().execute_for(server, client)
+    }
+
+    #[test]
+    fn test_ping_while_subscribed() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        let (mut client, _messages) = ClientState::new(1);
+        let pong = |message: &str| {
+            vec![RespElement::Array(vec![
+                RespElement::BulkString("pong".into()),
+                RespElement::BulkString(message.into()),
+            ])]
+        };
+
+        assert_eq!(
+            execute_for(&server, &mut client, &[b"PING"]),
+            [RespElement::SimpleString("PONG".to_owned().into())]
+        );
+        assert_eq!(
+            execute_for(&server, &mut client, &[b"PING", b"hi"]),
+            [RespElement::BulkString("hi".into())]
+        );
+
+        execute_for(&server, &mut client, &[b"SUBSCRIBE", b"news"]);
+        assert_eq!(execute_for(&server, &mut client, &[b"PING"]), pong(""));
+        assert_eq!(
+            execute_for(&server, &mut client, &[b"PING", b"hi"]),
+            pong("hi")
+        );
+
+        // RESP3 replies can't be confused with pushed messages.
+        client.resp_version = 3;
+        assert_eq!(
+            execute_for(&server, &mut client, &[b"PING"]),
+            [RespElement::SimpleString("PONG".to_owned().into())]
+        );
     }
 }
@@ -0,0 +1,227 @@
+use std::sync::Arc;
+
+use crate::{
+    clients::ClientState,
+    parse::{NullBulkString, RespElement},
+    Server,
+};
+
+use super::{Command, CommandError, CommandExecutor, FromResp};
+
+/// `SUBSCRIBE channel [channel ...]`, which changes the state of the connection
+/// rather than the server so is executed with `Command::execute_for`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct SubscribeCommand(Vec<String>);
+
+impl SubscribeCommand {
+    /// Subscribe the client to each channel, replying with the number of channels
+    /// it is subscribed to after each one.
+    pub(crate) fn execute_for(
+        self,
+        server: &Arc<Server>,
+        client: &mut ClientState,
+    ) -> Vec<RespElement> {
+        self.0
+            .into_iter()
+            .map(|channel| {
+                if client.channels.insert(channel.clone()) {
+                    server.pubsub.subscribe(&channel, client);
+                }
+                RespElement::Array(vec![
+                    RespElement::BulkString("subscribe".into()),
+                    RespElement::BulkString(channel.into()),
+                    RespElement::Integer(client.channels.len() as i64),
+                ])
+            })
+            .collect()
+    }
+}
+
+impl FromResp for SubscribeCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        if elements.len() < 2 {
+            return Err(CommandError::WrongArity("subscribe"));
+        }
+        let mut channels = Vec::with_capacity(elements.len() - 1);
+        for element in &elements[1..] {
+            channels.push(match element {
+                RespElement::BulkString(channel) => channel.as_ref().to_owned(),
+                _ => return Err(CommandError::SyntaxError),
+            });
+        }
+        Ok(SubscribeCommand(channels))
+    }
+}
+
+impl From<SubscribeCommand> for Command {
+    fn from(cmd: SubscribeCommand) -> Self {
+        Command::Subscribe(cmd)
+    }
+}
+
+/// `UNSUBSCRIBE [channel ...]`, which unsubscribes from every channel when none are given.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub(crate) struct UnsubscribeCommand(Vec<String>);
+
+impl UnsubscribeCommand {
+    /// Unsubscribe the client from each channel, replying with the number of channels
+    /// it is still subscribed to after each one.
+    pub(crate) fn execute_for(
+        self,
+        server: &Arc<Server>,
+        client: &mut ClientState,
+    ) -> Vec<RespElement> {
+        let channels = if self.0.is_empty() {
+            client.channels.iter().cloned().collect()
+        } else {
+            self.0
+        };
+        if channels.is_empty() {
+            // Redis still replies once when there was nothing to unsubscribe from.
+            return vec![unsubscribe_reply(NullBulkString.into(), 0)];
+        }
+        channels
+            .into_iter()
+            .map(|channel| {
+                client.channels.remove(&channel);
+                server.pubsub.unsubscribe(&channel, client.id);
+                unsubscribe_reply(
+                    RespElement::BulkString(channel.into()),
+                    client.channels.len(),
+                )
+            })
+            .collect()
+    }
+}
+
+fn unsubscribe_reply(channel: RespElement, count: usize) -> RespElement {
+    RespElement::Array(vec![
+        RespElement::BulkString("unsubscribe".into()),
+        channel,
+        RespElement::Integer(count as i64),
+    ])
+}
+
+impl FromResp for UnsubscribeCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        let mut channels = Vec::with_capacity(elements.len().saturating_sub(1));
+        for element in elements.iter().skip(1) {
+            channels.push(match element {
+                RespElement::BulkString(channel) => channel.as_ref().to_owned(),
+                _ => return Err(CommandError::SyntaxError),
+            });
+        }
+        Ok(UnsubscribeCommand(channels))
+    }
+}
+
+impl From<UnsubscribeCommand> for Command {
+    fn from(cmd: UnsubscribeCommand) -> Self {
+        Command::Unsubscribe(cmd)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct PublishCommand {
+    channel: String,
+    message: String,
+}
+
+impl CommandExecutor for PublishCommand {
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        RespElement::Integer(server.pubsub.publish(&self.channel, &self.message) as i64)
+    }
+}
+
+impl FromResp for PublishCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        if elements.len() != 3 {
+            return Err(CommandError::WrongArity("publish"));
+        }
+        match (&elements[1], &elements[2]) {
+            (RespElement::BulkString(channel), RespElement::BulkString(message)) => {
+                Ok(PublishCommand {
+                    channel: channel.as_ref().to_owned(),
+                    message: message.as_ref().to_owned(),
+                })
+            }
+            _ => Err(CommandError::SyntaxError),
+        }
+    }
+}
+
+impl From<PublishCommand> for Command {
+    fn from(cmd: PublishCommand) -> Self {
+        Command::Publish(cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::commands::test_util::command;
+
+    fn reply(kind: &str, channel: &str, count: i64) -> RespElement {
+        RespElement::Array(vec![
+            RespElement::BulkString(kind.into()),
+            RespElement::BulkString(channel.into()),
+            RespElement::Integer(count),
+        ])
+    }
+
+    #[test]
+    fn test_subscription_counts() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        let (mut client, _messages) = ClientState::new(1);
+
+        assert_eq!(
+            command(&[b"SUBSCRIBE", b"a", b"b"])
+                .unwrap()
+                .execute_for(&server, &mut client),
+            [reply("subscribe", "a", 1), reply("subscribe", "b", 2)]
+        );
+        assert_eq!(
+            command(&[b"UNSUBSCRIBE", b"b"])
+                .unwrap()
+                .execute_for(&server, &mut client),
+            [reply("unsubscribe", "b", 1)]
+        );
+        command(&[b"SUBSCRIBE", b"c"])
+            .unwrap()
+            .execute_for(&server, &mut client);
+        assert_eq!(
+            command(&[b"UNSUBSCRIBE"])
+                .unwrap()
+                .execute_for(&server, &mut client),
+            [reply("unsubscribe", "a", 1), reply("unsubscribe", "c", 0)]
+        );
+        assert_eq!(server.pubsub.publish("a", "hello"), 0);
+        assert_eq!(
+            command(&[b"UNSUBSCRIBE"])
+                .unwrap()
+                .execute_for(&server, &mut client),
+            [RespElement::Array(vec![
+                RespElement::BulkString("unsubscribe".into()),
+                NullBulkString.into(),
+                RespElement::Integer(0),
+            ])]
+        );
+    }
+}
@@ -0,0 +1,260 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+
+use crate::{
+    db::{self, DbValue, Value},
+    eviction,
+    parse::{RespElement, SimpleError},
+    pubsub::{notify_keyspace_event, EventClass},
+    Server,
+};
+
+use super::{
+    bits::clamp_range, expect_string, parse_signed_int, with_string_mut, wrong_type, Command,
+    CommandError, CommandExecutor, FromResp,
+};
+
+/// `GETRANGE key start end`, also accepted as `SUBSTR`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct GetRangeCommand {
+    key: String,
+    /// Inclusive start and end byte offsets, which may be negative to count back from the end.
+    start: i64,
+    end: i64,
+}
+
+impl CommandExecutor for GetRangeCommand {
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        let bytes = match server.db.with_live(&self.key, expect_string) {
+            Some(Ok(bytes)) => bytes,
+            Some(Err(e)) => return RespElement::SimpleError(e),
+            None => return RespElement::BulkString(Bytes::new().into()),
+        };
+        let range = match clamp_range(self.start, self.end, bytes.len()) {
+            Some((start, end)) => bytes.slice(start..=end),
+            None => Bytes::new(),
+        };
+        RespElement::BulkString(range.into())
+    }
+}
+
+impl FromResp for GetRangeCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        let name = match elements.first() {
+            Some(RespElement::BulkString(name)) if name.as_ref().eq_ignore_ascii_case("SUBSTR") => {
+                "substr"
+            }
+            _ => "getrange",
+        };
+        let [_, RespElement::BulkString(key), start, end] = elements.as_slice() else {
+            return match elements.len() {
+                4 => Err(CommandError::SyntaxError),
+                _ => Err(CommandError::WrongArity(name)),
+            };
+        };
+        Ok(GetRangeCommand {
+            key: key.as_ref().to_owned(),
+            start: parse_signed_int(start)?,
+            end: parse_signed_int(end)?,
+        })
+    }
+}
+
+impl From<GetRangeCommand> for Command {
+    fn from(cmd: GetRangeCommand) -> Self {
+        Command::GetRange(cmd)
+    }
+}
+
+/// `SETRANGE key offset value`, which overwrites part of a string, padding it with zero
+/// bytes if it's shorter than `offset`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct SetRangeCommand {
+    key: String,
+    offset: usize,
+    value: Bytes,
+}
+
+impl CommandExecutor for SetRangeCommand {
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        let len = self.offset + self.value.len();
+        if !self.value.is_empty() && len as u64 > server.proto_max_bulk_len() {
+            return RespElement::SimpleError(SimpleError::from(
+                "ERR string exceeds maximum allowed size (proto-max-bulk-len)".to_owned(),
+            ));
+        }
+        if let Err(e) = eviction::make_room(server) {
+            return RespElement::SimpleError(e);
+        }
+
+        let mut shard = server.db.write(&self.key);
+        // Like Redis, an empty value doesn't create the key.
+        if self.value.is_empty() {
+            return match db::get_live(&mut shard, &self.key).as_deref() {
+                Some(DbValue {
+                    value: Value::String(existing),
+                    ..
+                }) => RespElement::Integer(existing.len() as i64),
+                Some(_) => wrong_type(),
+                None => RespElement::Integer(0),
+            };
+        }
+        let new_len = match with_string_mut(&mut shard, &self.key, |existing| {
+            let mut bytes = Vec::with_capacity(existing.len().max(len));
+            bytes.extend_from_slice(existing);
+            overwrite(&mut bytes, self.offset, &self.value);
+            *existing = bytes.into();
+            existing.len()
+        }) {
+            Ok(new_len) => new_len,
+            Err(e) => return RespElement::SimpleError(e),
+        };
+        drop(shard);
+        notify_keyspace_event(server, EventClass::String, "setrange", &self.key);
+        RespElement::Integer(new_len as i64)
+    }
+}
+
+/// Copy `value` into `bytes` at `offset`, first padding `bytes` with zeros to reach it.
+fn overwrite(bytes: &mut Vec<u8>, offset: usize, value: &[u8]) {
+    let end = offset + value.len();
+    if bytes.len() < end {
+        bytes.resize(end, 0);
+    }
+    bytes[offset..end].copy_from_slice(value);
+}
+
+impl FromResp for SetRangeCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        let elements: [RespElement; 4] = elements
+            .try_into()
+            .map_err(|_| CommandError::WrongArity("setrange"))?;
+        let [_, RespElement::BulkString(key), offset, RespElement::BulkString(value)] = elements
+        else {
+            return Err(CommandError::SyntaxError);
+        };
+        let offset =
+            usize::try_from(parse_signed_int(&offset)?).map_err(|_| CommandError::InvalidOffset)?;
+        Ok(SetRangeCommand {
+            key: key.unwrap(),
+            offset,
+            value: value.into_bytes(),
+        })
+    }
+}
+
+impl From<SetRangeCommand> for Command {
+    fn from(cmd: SetRangeCommand) -> Self {
+        Command::SetRange(cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rstest::rstest;
+
+    use super::*;
+    use crate::commands::test_util::{command, execute};
+
+    fn bulk(bytes: &[u8]) -> RespElement {
+        RespElement::BulkString(Bytes::copy_from_slice(bytes).into())
+    }
+
+    #[test]
+    fn test_binary_setrange_and_getrange() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        execute(&server, &[b"SET", b"key", b"hello world"]);
+
+        let binary = b"\xff\x00\xc3";
+        assert_eq!(
+            execute(&server, &[b"SETRANGE", b"key", b"4", binary]),
+            RespElement::Integer(11)
+        );
+        assert_eq!(
+            execute(&server, &[b"GET", b"key"]),
+            bulk(b"hell\xff\x00\xc3orld")
+        );
+        assert_eq!(
+            execute(&server, &[b"GETRANGE", b"key", b"4", b"6"]),
+            bulk(binary)
+        );
+        assert_eq!(
+            execute(&server, &[b"SUBSTR", b"key", b"-7", b"-5"]),
+            bulk(binary)
+        );
+
+        // Values which aren't valid UTF-8 are stored exactly as sent.
+        execute(&server, &[b"SET", b"key", b"\xff\x01"]);
+        assert_eq!(execute(&server, &[b"GET", b"key"]), bulk(b"\xff\x01"));
+        assert_eq!(
+            execute(&server, &[b"GETRANGE", b"key", b"1", b"1"]),
+            bulk(b"\x01")
+        );
+    }
+
+    #[rstest]
+    #[case(b"0", b"-1", b"hello")]
+    #[case(b"-3", b"100", b"llo")]
+    #[case(b"3", b"1", b"")]
+    #[case(b"10", b"20", b"")]
+    fn test_getrange(#[case] start: &[u8], #[case] end: &[u8], #[case] expected: &[u8]) {
+        let server = Arc::new(Server::new(HashMap::new()));
+        execute(&server, &[b"SET", b"key", b"hello"]);
+        assert_eq!(
+            execute(&server, &[b"GETRANGE", b"key", start, end]),
+            bulk(expected)
+        );
+    }
+
+    #[test]
+    fn test_setrange_pads_and_creates() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        assert_eq!(
+            execute(&server, &[b"SETRANGE", b"key", b"0", b""]),
+            RespElement::Integer(0)
+        );
+        assert!(server.db.get_live("key").is_none());
+
+        assert_eq!(
+            execute(&server, &[b"SETRANGE", b"key", b"3", b"abc"]),
+            RespElement::Integer(6)
+        );
+        assert_eq!(execute(&server, &[b"GET", b"key"]), bulk(b"\0\0\0abc"));
+        assert_eq!(
+            execute(&server, &[b"GETRANGE", b"missing", b"0", b"-1"]),
+            bulk(b"")
+        );
+        assert!(matches!(
+            command(&[b"SETRANGE", b"key", b"-1", b"x"]),
+            Err(CommandError::InvalidOffset)
+        ));
+        assert!(matches!(
+            command(&[b"SUBSTR", b"key", b"0"]),
+            Err(CommandError::WrongArity("substr"))
+        ));
+    }
+
+    #[test]
+    fn test_setrange_over_max_size() {
+        let mut opts = HashMap::new();
+        opts.insert("proto-max-bulk-len".to_owned(), crate::OptValue::UInt(10));
+        let server = Arc::new(Server::new(opts));
+        assert!(matches!(
+            execute(&server, &[b"SETRANGE", b"key", b"8", b"abc"]),
+            RespElement::SimpleError(_)
+        ));
+    }
+}
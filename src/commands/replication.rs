@@ -0,0 +1,437 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{
+    clients::{ClientAddr, ClientState},
+    parse::{RespElement, SimpleError},
+    replication, Server,
+};
+
+use super::{parse_int, parse_signed_int, Command, CommandError, CommandExecutor, FromResp};
+
+/// `REPLICAOF host port` or `REPLICAOF NO ONE`, also accepted as `SLAVEOF`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum ReplicaOfCommand {
+    Master { host: String, port: u16 },
+    NoOne,
+}
+
+impl CommandExecutor for ReplicaOfCommand {
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        match self {
+            ReplicaOfCommand::Master { host, port } => {
+                if server.replication.master() != Some((host.clone(), port)) {
+                    replication::replicate_from(server, host, port);
+                    RespElement::SimpleString("OK".to_owned().into())
+                } else {
+                    RespElement::SimpleString(
+                        "OK Already connected to specified master".to_owned().into(),
+                    )
+                }
+            }
+            ReplicaOfCommand::NoOne => {
+                replication::promote(server);
+                RespElement::SimpleString("OK".to_owned().into())
+            }
+        }
+    }
+}
+
+impl FromResp for ReplicaOfCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        let [_, host, port] = elements.as_slice() else {
+            return Err(CommandError::WrongArity("replicaof"));
+        };
+        let RespElement::BulkString(host) = host else {
+            return Err(CommandError::SyntaxError);
+        };
+        if host.as_ref().eq_ignore_ascii_case("NO")
+            && matches!(port, RespElement::BulkString(one) if one.as_ref().eq_ignore_ascii_case("ONE"))
+        {
+            return Ok(ReplicaOfCommand::NoOne);
+        }
+        let port =
+            u16::try_from(parse_signed_int(port)?).map_err(|_| CommandError::NotAnInteger)?;
+        Ok(ReplicaOfCommand::Master {
+            host: host.as_ref().to_owned(),
+            port,
+        })
+    }
+}
+
+impl From<ReplicaOfCommand> for Command {
+    fn from(cmd: ReplicaOfCommand) -> Self {
+        Command::ReplicaOf(cmd)
+    }
+}
+
+/// `FAILOVER [TO host port [FORCE]] [TIMEOUT milliseconds]` or `FAILOVER ABORT`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum FailoverCommand {
+    Start {
+        /// The replica to hand over to. Replica addresses aren't tracked, so this isn't
+        /// checked against the attached replicas.
+        target: Option<(String, u16)>,
+        force: bool,
+        timeout: Option<Duration>,
+    },
+    Abort,
+}
+
+impl CommandExecutor for FailoverCommand {
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        let error = |message: &str| RespElement::SimpleError(SimpleError::from(message.to_owned()));
+        if server.replication.is_replica() {
+            return error("ERR FAILOVER is not valid when server is a replica.");
+        }
+        match self {
+            FailoverCommand::Abort => {
+                if !server.replication.abort_failover() {
+                    return error("ERR No failover in progress.");
+                }
+            }
+            FailoverCommand::Start { timeout, .. } => {
+                if server.replication.replica_count() == 0 {
+                    return error("ERR FAILOVER requires connected replicas.");
+                }
+                if server.replication.failover_in_progress() {
+                    return error("ERR FAILOVER already in progress.");
+                }
+                replication::failover(server, timeout);
+            }
+        }
+        RespElement::SimpleString("OK".to_owned().into())
+    }
+}
+
+impl FromResp for FailoverCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        let mut target = None;
+        let mut force = false;
+        let mut abort = false;
+        let mut timeout = None;
+        let mut args = elements.iter().skip(1);
+        while let Some(arg) = args.next() {
+            let RespElement::BulkString(arg) = arg else {
+                return Err(CommandError::SyntaxError);
+            };
+            match arg.as_ref().to_ascii_uppercase().as_str() {
+                "TO" if target.is_none() => {
+                    let (Some(RespElement::BulkString(host)), Some(port)) =
+                        (args.next(), args.next())
+                    else {
+                        return Err(CommandError::SyntaxError);
+                    };
+                    let port = u16::try_from(parse_signed_int(port)?)
+                        .map_err(|_| CommandError::NotAnInteger)?;
+                    target = Some((host.as_ref().to_owned(), port));
+                }
+                "FORCE" if !force => force = true,
+                "ABORT" if !abort => abort = true,
+                "TIMEOUT" if timeout.is_none() => {
+                    let ms = args.next().ok_or(CommandError::SyntaxError)?;
+                    let ms = u64::try_from(parse_signed_int(ms)?)
+                        .ok()
+                        .filter(|&ms| ms > 0)
+                        .ok_or(CommandError::InvalidFailoverTimeout)?;
+                    timeout = Some(Duration::from_millis(ms));
+                }
+                _ => return Err(CommandError::SyntaxError),
+            }
+        }
+
+        if abort {
+            if target.is_some() || force || timeout.is_some() {
+                return Err(CommandError::SyntaxError);
+            }
+            return Ok(FailoverCommand::Abort);
+        }
+        if force && (target.is_none() || timeout.is_none()) {
+            return Err(CommandError::FailoverForceArgs);
+        }
+        Ok(FailoverCommand::Start {
+            target,
+            force,
+            timeout,
+        })
+    }
+}
+
+impl From<FailoverCommand> for Command {
+    fn from(cmd: FailoverCommand) -> Self {
+        Command::Failover(cmd)
+    }
+}
+
+/// `REPLCONF option value ...`, exchanged between masters and replicas.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum ReplConfCommand {
+    /// `REPLCONF ACK offset`, a replica confirming it has processed up to `offset`.
+    Ack(u64),
+    /// `REPLCONF GETACK *`, a master asking its replica for an `ACK`.
+    GetAck,
+    /// `REPLCONF listening-port port`, a replica saying which port it accepts connections on.
+    ListeningPort(u16),
+    /// Options such as `capa`, which don't change anything.
+    Other,
+}
+
+impl ReplConfCommand {
+    pub(crate) fn execute_for(
+        self,
+        server: &Arc<Server>,
+        client: &mut ClientState,
+    ) -> Vec<RespElement> {
+        match self {
+            // Like Redis, acknowledgements aren't replied to.
+            ReplConfCommand::Ack(offset) => {
+                server.replication.ack(client.id, offset);
+                vec![]
+            }
+            ReplConfCommand::ListeningPort(port) => {
+                client.listening_port = Some(port);
+                vec![RespElement::SimpleString("OK".to_owned().into())]
+            }
+            cmd => vec![cmd.execute(server)],
+        }
+    }
+}
+
+impl CommandExecutor for ReplConfCommand {
+    fn execute(self, _server: &Arc<Server>) -> RespElement {
+        match self {
+            ReplConfCommand::Other | ReplConfCommand::ListeningPort(_) => {
+                RespElement::SimpleString("OK".to_owned().into())
+            }
+            // These only make sense on a replication link, which is handled elsewhere.
+            ReplConfCommand::Ack(_) | ReplConfCommand::GetAck => {
+                RespElement::SimpleError(CommandError::SyntaxError.to_simple_error())
+            }
+        }
+    }
+}
+
+impl FromResp for ReplConfCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        if elements.len() % 2 == 0 {
+            return Err(CommandError::WrongArity("replconf"));
+        }
+        let [_, RespElement::BulkString(option), value] = elements.as_slice() else {
+            return Ok(ReplConfCommand::Other);
+        };
+        match option.as_ref().to_ascii_uppercase().as_str() {
+            "ACK" => Ok(ReplConfCommand::Ack(parse_int(value)?)),
+            "GETACK" => Ok(ReplConfCommand::GetAck),
+            "LISTENING-PORT" => Ok(ReplConfCommand::ListeningPort(
+                u16::try_from(parse_int(value)?).map_err(|_| CommandError::NotAnInteger)?,
+            )),
+            _ => Ok(ReplConfCommand::Other),
+        }
+    }
+}
+
+impl From<ReplConfCommand> for Command {
+    fn from(cmd: ReplConfCommand) -> Self {
+        Command::ReplConf(cmd)
+    }
+}
+
+/// `PSYNC replid offset`, which attaches the connection as a replica so is executed
+/// with `Command::execute_for`.
+///
+/// Partial resyncs aren't supported, so the replica always receives a full snapshot.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct PsyncCommand;
+
+impl PsyncCommand {
+    pub(crate) fn execute_for(
+        self,
+        server: &Arc<Server>,
+        client: &mut ClientState,
+    ) -> Vec<RespElement> {
+        let ip = match server.clients.addr(client.id) {
+            Some(ClientAddr::Tcp(addr)) => addr.ip().to_string(),
+            Some(ClientAddr::Unix(path)) => path.display().to_string(),
+            None => String::new(),
+        };
+        let port = client.listening_port.unwrap_or(0);
+        let (reply, feed) = server
+            .replication
+            .attach_replica(server, client.id, ip, port);
+        client.replica_feed = Some(feed);
+        vec![RespElement::SimpleString(reply.into())]
+    }
+}
+
+impl FromResp for PsyncCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        if elements.len() != 3 {
+            return Err(CommandError::WrongArity("psync"));
+        }
+        Ok(PsyncCommand)
+    }
+}
+
+impl From<PsyncCommand> for Command {
+    fn from(cmd: PsyncCommand) -> Self {
+        Command::Psync(cmd)
+    }
+}
+
+/// `WAIT numreplicas timeout`, which blocks until enough replicas have acknowledged
+/// every write so far.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct WaitCommand {
+    numreplicas: usize,
+    /// How long to wait, where zero waits forever.
+    timeout: Duration,
+}
+
+impl WaitCommand {
+    pub(crate) async fn execute_blocking(self, server: &Arc<Server>) -> RespElement {
+        let timeout = Some(self.timeout).filter(|timeout| !timeout.is_zero());
+        let acked = server.replication.wait(self.numreplicas, timeout).await;
+        RespElement::Integer(acked as i64)
+    }
+}
+
+impl CommandExecutor for WaitCommand {
+    /// Count the replicas which are already caught up, as there's no connection to block.
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        let acked = server
+            .replication
+            .acked_replicas(server.replication.offset());
+        RespElement::Integer(acked as i64)
+    }
+}
+
+impl FromResp for WaitCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        let [_, numreplicas, timeout] = elements.as_slice() else {
+            return Err(CommandError::WrongArity("wait"));
+        };
+        let numreplicas = parse_signed_int(numreplicas)?.max(0) as usize;
+        let timeout =
+            u64::try_from(parse_signed_int(timeout)?).map_err(|_| CommandError::NegativeTimeout)?;
+        Ok(WaitCommand {
+            numreplicas,
+            timeout: Duration::from_millis(timeout),
+        })
+    }
+}
+
+impl From<WaitCommand> for Command {
+    fn from(cmd: WaitCommand) -> Self {
+        Command::Wait(cmd)
+    }
+}
+
+/// `WAITAOF numlocal numreplicas timeout`, which blocks until every write so far has been
+/// fsynced to the local append-only file and acknowledged by enough replicas.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct WaitAofCommand {
+    numlocal: usize,
+    numreplicas: usize,
+    /// How long to wait, where zero waits forever.
+    timeout: Duration,
+}
+
+impl WaitAofCommand {
+    pub(crate) async fn execute_blocking(self, server: &Arc<Server>) -> RespElement {
+        if let Some(error) = self.check(server) {
+            return error;
+        }
+        let timeout = Some(self.timeout).filter(|timeout| !timeout.is_zero());
+        let acked = server.replication.wait(self.numreplicas, timeout).await;
+        self.reply(server, acked)
+    }
+
+    /// Redis refuses to wait for a local fsync which will never happen.
+    fn check(&self, server: &Server) -> Option<RespElement> {
+        let error = if server.replication.is_replica() {
+            "ERR WAITAOF cannot be used with replica instances. Please also note that writes to replicas are just local and are not propagated."
+        } else if self.numlocal > 0 && !server.aof.is_enabled() {
+            "ERR WAITAOF cannot be used when numlocal is set but appendonly is disabled."
+        } else {
+            return None;
+        };
+        Some(RespElement::SimpleError(SimpleError::from(
+            error.to_owned(),
+        )))
+    }
+
+    /// Every write is fsynced before it's replied to, so the local file is always caught up.
+    /// Replicas only acknowledge applying writes, which is counted in place of their fsyncs.
+    fn reply(&self, server: &Server, acked: usize) -> RespElement {
+        RespElement::Array(vec![
+            RespElement::Integer(server.aof.is_enabled() as i64),
+            RespElement::Integer(acked as i64),
+        ])
+    }
+}
+
+impl CommandExecutor for WaitAofCommand {
+    /// Count the replicas which are already caught up, as there's no connection to block.
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        if let Some(error) = self.check(server) {
+            return error;
+        }
+        let acked = server
+            .replication
+            .acked_replicas(server.replication.offset());
+        self.reply(server, acked)
+    }
+}
+
+impl FromResp for WaitAofCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        let [_, numlocal, numreplicas, timeout] = elements.as_slice() else {
+            return Err(CommandError::WrongArity("waitaof"));
+        };
+        let numlocal = parse_signed_int(numlocal)?.max(0) as usize;
+        let numreplicas = parse_signed_int(numreplicas)?.max(0) as usize;
+        let timeout =
+            u64::try_from(parse_signed_int(timeout)?).map_err(|_| CommandError::NegativeTimeout)?;
+        Ok(WaitAofCommand {
+            numlocal,
+            numreplicas,
+            timeout: Duration::from_millis(timeout),
+        })
+    }
+}
+
+impl From<WaitAofCommand> for Command {
+    fn from(cmd: WaitAofCommand) -> Self {
+        Command::WaitAof(cmd)
+    }
+}
@@ -0,0 +1,177 @@
+use std::sync::{atomic::Ordering, Arc};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    parse::{RespElement, SimpleError},
+    rdb, Server,
+};
+
+use super::{Command, CommandExecutor};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) struct SaveCommand;
+
+impl CommandExecutor for SaveCommand {
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        let Some(path) = server.rdb_path() else {
+            return RespElement::SimpleError(SimpleError::from(
+                "ERR dir and dbfilename must be configured to save".to_owned(),
+            ));
+        };
+
+        match rdb::save(&path, &server.db.snapshot()) {
+            Ok(()) => {
+                server.last_save.store(unix_time(), Ordering::Relaxed);
+                RespElement::SimpleString("OK".to_owned().into())
+            }
+            Err(e) => RespElement::SimpleError(SimpleError::from(format!("ERR {}", e))),
+        }
+    }
+}
+
+impl From<SaveCommand> for Command {
+    fn from(cmd: SaveCommand) -> Self {
+        Command::Save(cmd)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) struct BgSaveCommand;
+
+impl CommandExecutor for BgSaveCommand {
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        let Some(path) = server.rdb_path() else {
+            return RespElement::SimpleError(SimpleError::from(
+                "ERR dir and dbfilename must be configured to save".to_owned(),
+            ));
+        };
+        if server.bgsave_in_progress.swap(true, Ordering::AcqRel) {
+            return RespElement::SimpleError(SimpleError::from(
+                "ERR Background save already in progress".to_owned(),
+            ));
+        }
+
+        // Take a copy so the lock isn't held while writing to disk.
+        let db = server.db.snapshot();
+        let server = server.clone();
+        tokio::spawn(async move {
+            let result = tokio::task::spawn_blocking(move || rdb::save(&path, &db)).await;
+            match result {
+                Ok(Ok(())) => server.last_save.store(unix_time(), Ordering::Relaxed),
+                Ok(Err(e)) => eprintln!("Background saving error: {}", e),
+                Err(e) => eprintln!("Background saving task failed: {}", e),
+            }
+            server.bgsave_in_progress.store(false, Ordering::Release);
+        });
+
+        RespElement::SimpleString("Background saving started".to_owned().into())
+    }
+}
+
+impl From<BgSaveCommand> for Command {
+    fn from(cmd: BgSaveCommand) -> Self {
+        Command::BgSave(cmd)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) struct LastSaveCommand;
+
+impl CommandExecutor for LastSaveCommand {
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        RespElement::Integer(server.last_save.load(Ordering::Relaxed) as i64)
+    }
+}
+
+impl From<LastSaveCommand> for Command {
+    fn from(cmd: LastSaveCommand) -> Self {
+        Command::LastSave(cmd)
+    }
+}
+
+/// Seconds since the UNIX epoch, as reported by `LASTSAVE`.
+pub(crate) fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{commands::test_util::execute, OptValue};
+
+    #[test]
+    fn test_save_round_trip() {
+        let dir = std::env::temp_dir().join(format!("redis-save-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut opts = HashMap::new();
+        opts.insert("dir".to_owned(), OptValue::path(dir.clone()));
+        opts.insert("dbfilename".to_owned(), OptValue::String("dump.rdb".into()));
+        let server = Arc::new(Server::new(opts));
+
+        execute(&server, &[b"SET", b"foo", b"bar"]);
+        execute(&server, &[b"SET", b"baz", b"qux", b"EX", b"100"]);
+        let resp = execute(&server, &[b"SAVE"]);
+        assert_eq!(resp, RespElement::SimpleString("OK".to_owned().into()));
+
+        let db = rdb::load(&dir.join("dump.rdb")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(db.len(), 2);
+        assert_eq!(db["foo"].value, "bar".into());
+        assert_eq!(db["foo"].expires_at, None);
+        assert_eq!(db["baz"].value, "qux".into());
+        assert!(db["baz"].expires_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_bgsave_updates_lastsave() {
+        let dir = std::env::temp_dir().join(format!("redis-bgsave-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut opts = HashMap::new();
+        opts.insert("dir".to_owned(), OptValue::path(dir.clone()));
+        opts.insert("dbfilename".to_owned(), OptValue::String("dump.rdb".into()));
+        let server = Arc::new(Server::new(opts));
+        server.last_save.store(0, Ordering::Relaxed);
+
+        execute(&server, &[b"SET", b"foo", b"bar"]);
+        let resp = execute(&server, &[b"BGSAVE"]);
+        assert_eq!(
+            resp,
+            RespElement::SimpleString("Background saving started".to_owned().into())
+        );
+
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while server.bgsave_in_progress.load(Ordering::Acquire) {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        let RespElement::Integer(last_save) = execute(&server, &[b"LASTSAVE"]) else {
+            panic!("Expected integer");
+        };
+        assert!(last_save > 0);
+        let db = rdb::load(&dir.join("dump.rdb")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(db["foo"].value, "bar".into());
+    }
+
+    #[test]
+    fn test_save_write_error() {
+        let mut opts = HashMap::new();
+        opts.insert(
+            "dir".to_owned(),
+            OptValue::path("/definitely/not/a/real/dir".into()),
+        );
+        opts.insert("dbfilename".to_owned(), OptValue::String("dump.rdb".into()));
+        let server = Arc::new(Server::new(opts));
+
+        let resp = execute(&server, &[b"SAVE"]);
+        assert!(matches!(resp, RespElement::SimpleError(_)));
+    }
+}
@@ -1,19 +1,21 @@
-use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex},
-};
+use std::{mem, sync::Arc};
+
+use bytes::Bytes;
 
 use crate::{
+    db::{self, DbValue, Value},
+    eviction, expiry,
     parse::{NullBulkString, RespElement},
-    OptValue,
+    pubsub::{notify_keyspace_event, EventClass},
+    Server,
 };
 
-use super::{parse_int, Command, CommandError, CommandExecutor, DbValue, FromResp};
+use super::{parse_signed_int, wrong_type, Command, CommandError, CommandExecutor, FromResp};
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub(crate) struct SetCommand {
     key: String,
-    value: String,
+    value: Bytes,
     only_if: Option<SetOnlyIf>,
     get: bool,
     expiry: Option<ExpiryOpt>,
@@ -35,15 +37,24 @@ pub(crate) enum ExpiryOpt {
 }
 
 impl CommandExecutor for SetCommand {
-    fn execute(
-        self,
-        db: &Arc<Mutex<HashMap<String, DbValue>>>,
-        _opts: &Arc<HashMap<String, OptValue>>,
-    ) -> RespElement {
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        if let Err(e) = eviction::make_room(server) {
+            return RespElement::SimpleError(e);
+        }
+
         let mut should_set = true;
-        let mut db = db.lock().unwrap();
+        let mut db = server.db.write(&self.key);
+        // Expired keys don't count as existing for NX and XX, or have an old value for GET.
+        let exists = db::get_live(&mut db, &self.key).is_some();
+        // GET can only return the old value if it is a string, so nothing is set otherwise.
+        if self.get
+            && db
+                .get(&self.key)
+                .is_some_and(|old| !matches!(old.value, Value::String(_)))
+        {
+            return wrong_type();
+        }
         if self.only_if.is_some() || self.get {
-            let exists = db.contains_key(&self.key);
             match (exists, self.only_if) {
                 (true, Some(SetOnlyIf::DoesNotExists)) => should_set = false,
                 (false, Some(SetOnlyIf::AlreadyExists)) => should_set = false,
@@ -51,32 +62,22 @@ impl CommandExecutor for SetCommand {
             };
         };
         if should_set {
+            let expires_at = match &self.expiry {
+                Some(ExpiryOpt::KeepTtl) => db.get(&self.key).and_then(|old| old.expires_at),
+                Some(expiry) => expiry::deadline_from(expiry),
+                None => None,
+            };
             let old_value = db.insert(
-                self.key,
-                DbValue {
-                    value: self.value.into(),
-                    expires_at: if let Some(expiry) = self.expiry {
-                        Some(match expiry {
-                            ExpiryOpt::Seconds(i) => {
-                                std::time::Instant::now() + std::time::Duration::from_secs(i)
-                            }
-                            ExpiryOpt::Milliseconds(i) => {
-                                std::time::Instant::now() + std::time::Duration::from_millis(i)
-                            }
-                            ExpiryOpt::TimestampSeconds(_) => todo!(),
-                            ExpiryOpt::TimestampMilliseconds(_) => todo!(),
-                            ExpiryOpt::KeepTtl => todo!(),
-                        })
-                    } else {
-                        None
-                    },
-                },
+                self.key.clone(),
+                DbValue::new(self.value.into(), expires_at),
             );
+            drop(db);
+            notify_keyspace_event(server, EventClass::String, "set", &self.key);
 
             if self.get {
-                match old_value {
-                    Some(db_value) => RespElement::BulkString(db_value.value.clone().into()),
-                    None => NullBulkString.into(),
+                match old_value.map(|old| old.value) {
+                    Some(Value::String(s)) => RespElement::BulkString(s.into()),
+                    _ => NullBulkString.into(),
                 }
             } else {
                 RespElement::SimpleString("OK".to_owned().into())
@@ -96,7 +97,7 @@ impl FromResp for SetCommand {
         Self: Sized,
     {
         if elements.len() < 3 {
-            return Err(CommandError::InvalidCommand);
+            return Err(CommandError::WrongArity("set"));
         }
 
         let key = elements[1].clone();
@@ -106,7 +107,8 @@ impl FromResp for SetCommand {
         };
         let value = elements[2].clone();
         let value = match value {
-            RespElement::BulkString(value) => value.unwrap(),
+            // Values are binary safe, so keep their exact bytes.
+            RespElement::BulkString(value) => value.into_bytes(),
             _ => return Err(CommandError::InvalidCommand),
         };
 
@@ -123,48 +125,39 @@ impl FromResp for SetCommand {
                     let arg = arg.to_uppercase();
 
                     match arg.as_str() {
-                        "NX" if only_if.is_none() => {
-                            only_if = Some(SetOnlyIf::DoesNotExists);
-                            idx += 1;
-                        }
-                        "XX" if only_if.is_none() => {
-                            only_if = Some(SetOnlyIf::AlreadyExists);
+                        "NX" | "XX" => {
+                            let condition = if arg == "NX" {
+                                SetOnlyIf::DoesNotExists
+                            } else {
+                                SetOnlyIf::AlreadyExists
+                            };
+                            // NX and XX contradict each other, but repeating either is harmless.
+                            if only_if.is_some_and(|only_if| only_if != condition) {
+                                return Err(CommandError::SyntaxError);
+                            }
+                            only_if = Some(condition);
                             idx += 1;
                         }
-                        "NX" | "XX" => return Err(CommandError::SyntaxError),
-                        "GET" if !get => {
+                        "GET" => {
                             get = true;
                             idx += 1;
                         }
-                        "GET" => return Err(CommandError::SyntaxError),
-                        "EX" if expiry.is_none() => {
-                            let value = elements.get(idx + 1).ok_or(CommandError::SyntaxError)?;
-                            expiry = Some(ExpiryOpt::Seconds(parse_int(value)?));
-                            idx += 2;
-                        }
-                        "PX" if expiry.is_none() => {
-                            let value = elements.get(idx + 1).ok_or(CommandError::SyntaxError)?;
-                            expiry = Some(ExpiryOpt::Milliseconds(parse_int(value)?));
-                            idx += 2;
-                        }
-                        "EXAT" if expiry.is_none() => {
-                            let value = elements.get(idx + 1).ok_or(CommandError::SyntaxError)?;
-                            expiry = Some(ExpiryOpt::TimestampSeconds(parse_int(value)?));
-                            idx += 2;
+                        "KEEPTTL" => {
+                            set_expiry(&mut expiry, ExpiryOpt::KeepTtl)?;
+                            idx += 1;
                         }
-                        "PXAT" if expiry.is_none() => {
+                        "EX" | "PX" | "EXAT" | "PXAT" => {
                             let value = elements.get(idx + 1).ok_or(CommandError::SyntaxError)?;
-                            expiry = Some(ExpiryOpt::TimestampMilliseconds(parse_int(value)?));
+                            let opt = match arg.as_str() {
+                                "EX" => ExpiryOpt::Seconds(parse_expiry(value, 1000)?),
+                                "PX" => ExpiryOpt::Milliseconds(parse_expiry(value, 1)?),
+                                "EXAT" => ExpiryOpt::TimestampSeconds(parse_expiry(value, 1000)?),
+                                _ => ExpiryOpt::TimestampMilliseconds(parse_expiry(value, 1)?),
+                            };
+                            set_expiry(&mut expiry, opt)?;
                             idx += 2;
                         }
-                        "KEEPTTL" if expiry.is_none() => {
-                            expiry = Some(ExpiryOpt::KeepTtl);
-                            idx += 1;
-                        }
-                        "EX" | "PX" | "EXAT" | "PXAT" | "KEEPTTL" => {
-                            return Err(CommandError::SyntaxError)
-                        }
-                        _ => return Err(CommandError::InvalidCommand),
+                        _ => return Err(CommandError::SyntaxError),
                     }
                 }
                 _ => return Err(CommandError::InvalidCommand),
@@ -180,6 +173,86 @@ impl FromResp for SetCommand {
     }
 }
 
+/// Set the expiry, which may be repeated but can't be combined with a different kind,
+/// such as EX with KEEPTTL.
+fn set_expiry(expiry: &mut Option<ExpiryOpt>, opt: ExpiryOpt) -> Result<(), CommandError> {
+    if expiry
+        .as_ref()
+        .is_some_and(|expiry| mem::discriminant(expiry) != mem::discriminant(&opt))
+    {
+        return Err(CommandError::SyntaxError);
+    }
+    *expiry = Some(opt);
+    Ok(())
+}
+
+/// Parse an expiry given in units of `unit_millis` milliseconds. Like Redis, it must be
+/// positive and still fit in an `i64` once converted to milliseconds.
+fn parse_expiry(element: &RespElement, unit_millis: i64) -> Result<u64, CommandError> {
+    let expiry = parse_signed_int(element)?;
+    if expiry <= 0 || expiry.checked_mul(unit_millis).is_none() {
+        return Err(CommandError::InvalidExpireTime("set"));
+    }
+    Ok(expiry as u64)
+}
+
+impl SetCommand {
+    /// The command with a relative expiry (`EX` or `PX`) made an absolute `PXAT`, so
+    /// replicas and the AOF give the key the same deadline however late they apply it.
+    /// `None` if the expiry is already absolute, or there isn't one.
+    pub(crate) fn rewrite_for_propagation(&self) -> Option<SetCommand> {
+        let expiry = self.expiry.as_ref()?;
+        if !matches!(expiry, ExpiryOpt::Seconds(_) | ExpiryOpt::Milliseconds(_)) {
+            return None;
+        }
+        let deadline = expiry::deadline_from(expiry)?;
+        let at = expiry::unix_from_deadline(deadline).as_millis() as u64;
+        Some(SetCommand {
+            expiry: Some(ExpiryOpt::TimestampMilliseconds(at)),
+            ..self.clone()
+        })
+    }
+}
+
+impl From<&SetCommand> for RespElement {
+    fn from(cmd: &SetCommand) -> Self {
+        let mut options = Vec::new();
+        match cmd.only_if {
+            Some(SetOnlyIf::DoesNotExists) => options.push("NX".to_owned()),
+            Some(SetOnlyIf::AlreadyExists) => options.push("XX".to_owned()),
+            None => {}
+        }
+        if cmd.get {
+            options.push("GET".to_owned());
+        }
+        match cmd.expiry {
+            Some(ExpiryOpt::Seconds(secs)) => options.extend(["EX".to_owned(), secs.to_string()]),
+            Some(ExpiryOpt::Milliseconds(millis)) => {
+                options.extend(["PX".to_owned(), millis.to_string()])
+            }
+            Some(ExpiryOpt::TimestampSeconds(secs)) => {
+                options.extend(["EXAT".to_owned(), secs.to_string()])
+            }
+            Some(ExpiryOpt::TimestampMilliseconds(millis)) => {
+                options.extend(["PXAT".to_owned(), millis.to_string()])
+            }
+            Some(ExpiryOpt::KeepTtl) => options.push("KEEPTTL".to_owned()),
+            None => {}
+        }
+        let mut args = vec![
+            RespElement::BulkString("SET".into()),
+            RespElement::BulkString(cmd.key.clone().into()),
+            RespElement::BulkString(cmd.value.clone().into()),
+        ];
+        args.extend(
+            options
+                .into_iter()
+                .map(|option| RespElement::BulkString(option.into())),
+        );
+        RespElement::Array(args)
+    }
+}
+
 impl From<SetCommand> for Command {
     fn from(cmd: SetCommand) -> Self {
         Command::Set(cmd)
@@ -188,6 +261,13 @@ impl From<SetCommand> for Command {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
+    use rstest::rstest;
+
+    use crate::commands::test_util::{command, execute};
+    use crate::parse::SimpleError;
+
     use super::*;
 
     #[test]
@@ -203,7 +283,7 @@ mod tests {
             assert_eq!(set_command.key, "key");
             assert_eq!(set_command.value, "value");
             assert_eq!(set_command.only_if, None);
-            assert_eq!(set_command.get, false);
+            assert!(!set_command.get);
             assert_eq!(set_command.expiry, None);
         } else {
             panic!("Expected SET command");
@@ -225,7 +305,7 @@ mod tests {
             assert_eq!(set_command.key, "key");
             assert_eq!(set_command.value, "value");
             assert_eq!(set_command.only_if, None);
-            assert_eq!(set_command.get, false);
+            assert!(!set_command.get);
             assert_eq!(set_command.expiry, Some(ExpiryOpt::Milliseconds(1000)));
         } else {
             panic!("Expected SET command");
@@ -247,7 +327,7 @@ mod tests {
             assert_eq!(set_command.key, "key");
             assert_eq!(set_command.value, "value");
             assert_eq!(set_command.only_if, None);
-            assert_eq!(set_command.get, false);
+            assert!(!set_command.get);
             assert_eq!(set_command.expiry, Some(ExpiryOpt::Milliseconds(1000)));
         } else {
             panic!("Expected SET command");
@@ -256,16 +336,70 @@ mod tests {
 
     #[test]
     fn test_execute_set_command_with_expiry() {
-        let db = Arc::new(Mutex::new(HashMap::new()));
+        let server = Arc::new(Server::new(HashMap::new()));
         let command = Command::Set(SetCommand {
             key: "key".to_owned(),
-            value: "value".to_owned(),
+            value: "value".into(),
             only_if: None,
             get: false,
             expiry: Some(ExpiryOpt::Seconds(1)),
         });
-        let resp = command.execute(&db, &Arc::new(HashMap::new()));
-        assert_eq!(db.lock().unwrap().get("key").unwrap().value, "value");
+        let resp = command.execute(&server);
+        assert_eq!(server.db.get_live("key").unwrap().value, "value".into());
         assert_eq!(resp, RespElement::SimpleString("OK".to_owned().into()));
     }
+
+    #[test]
+    fn test_set_keepttl_keeps_existing_deadline() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        execute(&server, &[b"SET", b"key", b"old", b"EX", b"100"]);
+        let deadline = server.db.get_live("key").unwrap().expires_at;
+        assert!(deadline.is_some());
+        execute(&server, &[b"SET", b"key", b"new", b"KEEPTTL"]);
+        let value = server.db.get_live("key").unwrap();
+        assert_eq!(value.value, "new".into());
+        assert_eq!(value.expires_at, deadline);
+
+        execute(&server, &[b"SET", b"key", b"newer"]);
+        assert_eq!(server.db.get_live("key").unwrap().expires_at, None);
+    }
+
+    #[rstest]
+    #[case(&["EX", "0"])]
+    #[case(&["PX", "-100"])]
+    #[case(&["EXAT", "0"])]
+    #[case(&["EX", "9223372036854775807"])]
+    fn test_set_rejects_invalid_expiry(#[case] expiry: &[&str]) {
+        let mut args: Vec<&[u8]> = vec![b"SET", b"key", b"value"];
+        args.extend(expiry.iter().map(|arg| arg.as_bytes()));
+        let err = command(&args).unwrap_err();
+        assert!(matches!(err, CommandError::InvalidExpireTime("set")));
+        assert_eq!(
+            err.to_simple_error(),
+            SimpleError::from("ERR invalid expire time in 'set' command".to_owned())
+        );
+    }
+
+    #[rstest]
+    #[case(&["EX", "10", "KEEPTTL"])]
+    #[case(&["KEEPTTL", "PX", "10"])]
+    #[case(&["EX", "10", "PXAT", "10"])]
+    #[case(&["NX", "XX"])]
+    #[case(&["XX", "GET", "NX"])]
+    fn test_set_rejects_conflicting_options(#[case] options: &[&str]) {
+        let mut args: Vec<&[u8]> = vec![b"SET", b"key", b"value"];
+        args.extend(options.iter().map(|arg| arg.as_bytes()));
+        assert!(matches!(command(&args), Err(CommandError::SyntaxError)));
+    }
+
+    #[test]
+    fn test_set_allows_repeated_options() {
+        let Ok(Command::Set(set_command)) = command(&[
+            b"SET", b"key", b"value", b"NX", b"EX", b"10", b"NX", b"EX", b"20",
+        ]) else {
+            panic!("Expected SET command");
+        };
+        assert_eq!(set_command.only_if, Some(SetOnlyIf::DoesNotExists));
+        assert_eq!(set_command.expiry, Some(ExpiryOpt::Seconds(20)));
+    }
 }
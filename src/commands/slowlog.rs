@@ -0,0 +1,102 @@
+use std::sync::Arc;
+
+use crate::{parse::RespElement, Server};
+
+use super::{parse_signed_int, Command, CommandError, CommandExecutor, FromResp};
+
+/// How many entries `SLOWLOG GET` returns without a count.
+const DEFAULT_GET_COUNT: usize = 10;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum SlowLogCommand {
+    /// The newest entries, or all of them for `None`.
+    Get(Option<usize>),
+    Len,
+    Reset,
+}
+
+impl CommandExecutor for SlowLogCommand {
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        match self {
+            Self::Get(count) => RespElement::Array(
+                server
+                    .slowlog
+                    .get(count)
+                    .iter()
+                    .map(RespElement::from)
+                    .collect(),
+            ),
+            Self::Len => RespElement::Integer(server.slowlog.len() as i64),
+            Self::Reset => {
+                server.slowlog.reset();
+                RespElement::SimpleString("OK".to_owned().into())
+            }
+        }
+    }
+}
+
+impl FromResp for SlowLogCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        let subcommand = match elements.get(1) {
+            Some(RespElement::BulkString(subcommand)) => subcommand.as_ref().to_uppercase(),
+            Some(_) => return Err(CommandError::SyntaxError),
+            None => return Err(CommandError::WrongArity("slowlog")),
+        };
+
+        match (subcommand.as_str(), elements.len()) {
+            ("GET", 2) => Ok(SlowLogCommand::Get(Some(DEFAULT_GET_COUNT))),
+            ("GET", 3) => match parse_signed_int(&elements[2])? {
+                -1 => Ok(SlowLogCommand::Get(None)),
+                count if count >= 0 => Ok(SlowLogCommand::Get(Some(count as usize))),
+                _ => Err(CommandError::NotAnInteger),
+            },
+            ("GET", _) => Err(CommandError::WrongArity("slowlog|get")),
+            ("LEN", 2) => Ok(SlowLogCommand::Len),
+            ("LEN", _) => Err(CommandError::WrongArity("slowlog|len")),
+            ("RESET", 2) => Ok(SlowLogCommand::Reset),
+            ("RESET", _) => Err(CommandError::WrongArity("slowlog|reset")),
+            _ => Err(CommandError::UnknownSubcommand("SLOWLOG", subcommand)),
+        }
+    }
+}
+
+impl From<SlowLogCommand> for Command {
+    fn from(cmd: SlowLogCommand) -> Self {
+        Command::SlowLog(cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use crate::commands::test_util::command;
+
+    use super::*;
+
+    #[rstest]
+    #[case(&["SLOWLOG", "GET"], SlowLogCommand::Get(Some(DEFAULT_GET_COUNT)))]
+    #[case(&["SLOWLOG", "get", "3"], SlowLogCommand::Get(Some(3)))]
+    #[case(&["SLOWLOG", "GET", "-1"], SlowLogCommand::Get(None))]
+    #[case(&["SLOWLOG", "LEN"], SlowLogCommand::Len)]
+    #[case(&["SLOWLOG", "RESET"], SlowLogCommand::Reset)]
+    fn test_parse(#[case] args: &[&str], #[case] expected: SlowLogCommand) {
+        let args: Vec<&[u8]> = args.iter().map(|arg| arg.as_bytes()).collect();
+        assert_eq!(command(&args).unwrap(), Command::SlowLog(expected));
+    }
+
+    #[rstest]
+    #[case(&["SLOWLOG"])]
+    #[case(&["SLOWLOG", "GET", "-2"])]
+    #[case(&["SLOWLOG", "LEN", "1"])]
+    #[case(&["SLOWLOG", "FLUSH"])]
+    fn test_parse_invalid(#[case] args: &[&str]) {
+        let args: Vec<&[u8]> = args.iter().map(|arg| arg.as_bytes()).collect();
+        assert!(command(&args).is_err());
+    }
+}
@@ -0,0 +1,923 @@
+use std::ops::Bound;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use crate::{
+    db::{self, DbValue, Value},
+    eviction,
+    parse::{NullArray, RespElement, SimpleError},
+    pubsub::{notify_keyspace_event, EventClass},
+    stream::{Stream, StreamId, Trim},
+    Server,
+};
+
+use super::{
+    parse_signed_int, wrong_type, Command, CommandError, CommandExecutor, FromResp, Propagation,
+};
+
+/// The ID requested for a new stream entry.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum XAddId {
+    /// `*`, generate the whole ID from the current time.
+    Auto,
+    /// `<ms>-*`, generate the sequence number for the given time.
+    AutoSeq(u64),
+    Explicit(StreamId),
+}
+
+impl XAddId {
+    fn parse(s: &str) -> Result<Self, CommandError> {
+        if s == "*" {
+            return Ok(XAddId::Auto);
+        }
+        if let Some(ms) = s.strip_suffix("-*") {
+            return ms
+                .parse()
+                .map(XAddId::AutoSeq)
+                .map_err(|_| CommandError::InvalidStreamId);
+        }
+        s.parse()
+            .map(XAddId::Explicit)
+            .map_err(|_| CommandError::InvalidStreamId)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct XAddCommand {
+    key: String,
+    id: XAddId,
+    fields: Vec<(Bytes, Bytes)>,
+}
+
+impl XAddCommand {
+    /// A generated ID would differ on each replica and AOF replay, so the command is
+    /// propagated with the ID it replied with instead. `None` for an explicit ID.
+    pub(crate) fn propagation(&self) -> Option<Propagation> {
+        if matches!(self.id, XAddId::Explicit(_)) {
+            return None;
+        }
+        let mut args = vec![
+            RespElement::BulkString("XADD".into()),
+            RespElement::BulkString(self.key.clone().into()),
+            // Replaced by the reply.
+            RespElement::BulkString("*".into()),
+        ];
+        for (field, value) in &self.fields {
+            args.push(RespElement::BulkString(field.clone().into()));
+            args.push(RespElement::BulkString(value.clone().into()));
+        }
+        Some(Propagation::WithReply { args, index: 2 })
+    }
+}
+
+impl CommandExecutor for XAddCommand {
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        if self.id == XAddId::Explicit(StreamId::MIN) {
+            return RespElement::SimpleError(SimpleError::from(
+                "ERR The ID specified in XADD must be greater than 0-0".to_owned(),
+            ));
+        }
+        if let Err(e) = eviction::make_room(server) {
+            return RespElement::SimpleError(e);
+        }
+
+        let mut db = server.db.write(&self.key);
+        if db::get_live(&mut db, &self.key).is_none() {
+            db.insert(
+                self.key.clone(),
+                DbValue::new(Stream::default().into(), None),
+            );
+        }
+        let mut entry = db.get_mut(&self.key).expect("the key was just created");
+        let Value::Stream(stream) = &mut entry.value else {
+            return wrong_type();
+        };
+
+        let id = match self.id {
+            XAddId::Auto => stream.next_auto_id(),
+            XAddId::AutoSeq(ms) => stream.next_id_at(ms),
+            XAddId::Explicit(id) => Some(id).filter(|&id| id > stream.last_id),
+        };
+        let Some(id) = id else {
+            // A new, empty stream accepts any ID but 0-0, so nothing was inserted above.
+            return RespElement::SimpleError(SimpleError::from(
+                "ERR The ID specified in XADD is equal or smaller than the target stream top item"
+                    .to_owned(),
+            ));
+        };
+        stream.add(id, self.fields);
+        drop(entry);
+        drop(db);
+        server.key_waiters.signal_write();
+
+        notify_keyspace_event(server, EventClass::Stream, "xadd", &self.key);
+        RespElement::BulkString(id.to_string().into())
+    }
+}
+
+impl FromResp for XAddCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        // The command name, key, ID and at least one field/value pair.
+        if elements.len() < 5 || elements.len() % 2 == 0 {
+            return Err(CommandError::WrongArity("xadd"));
+        }
+        let mut args = elements.into_iter().skip(1).map(|element| match element {
+            RespElement::BulkString(arg) => Ok(arg.as_ref().to_owned()),
+            _ => Err(CommandError::SyntaxError),
+        });
+        let key = args.next().unwrap()?;
+        let id = XAddId::parse(&args.next().unwrap()?)?;
+        let mut fields = Vec::new();
+        while let (Some(field), Some(value)) = (args.next(), args.next()) {
+            fields.push((Bytes::from(field?), Bytes::from(value?)));
+        }
+        Ok(XAddCommand { key, id, fields })
+    }
+}
+
+impl From<XAddCommand> for Command {
+    fn from(cmd: XAddCommand) -> Self {
+        Command::XAdd(cmd)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct XLenCommand(String);
+
+impl CommandExecutor for XLenCommand {
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        server
+            .db
+            .with_live(&self.0, |value| match &value.value {
+                Value::Stream(stream) => RespElement::Integer(stream.entries.len() as i64),
+                _ => wrong_type(),
+            })
+            .unwrap_or(RespElement::Integer(0))
+    }
+}
+
+impl FromResp for XLenCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        match elements.as_slice() {
+            [_, RespElement::BulkString(key)] => Ok(XLenCommand(key.as_ref().to_owned())),
+            [_, _] => Err(CommandError::SyntaxError),
+            _ => Err(CommandError::WrongArity("xlen")),
+        }
+    }
+}
+
+impl From<XLenCommand> for Command {
+    fn from(cmd: XLenCommand) -> Self {
+        Command::XLen(cmd)
+    }
+}
+
+/// Parse the ID of an existing entry, where a bare `<ms>` means `<ms>-0`.
+fn parse_entry_id(s: &str) -> Result<StreamId, CommandError> {
+    match s.parse::<u64>() {
+        Ok(ms) => Ok(StreamId::new(ms, 0)),
+        Err(_) => s.parse().map_err(|_| CommandError::InvalidStreamId),
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct XDelCommand {
+    key: String,
+    ids: Vec<StreamId>,
+}
+
+impl CommandExecutor for XDelCommand {
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        let mut db = server.db.write(&self.key);
+        let Some(mut entry) = db::get_live(&mut db, &self.key) else {
+            return RespElement::Integer(0);
+        };
+        let Value::Stream(stream) = &mut entry.value else {
+            return wrong_type();
+        };
+        // Removing entries never changes `last_id`, so IDs are never reused.
+        let deleted = self.ids.iter().filter(|id| stream.remove(id)).count();
+        drop(entry);
+        drop(db);
+
+        if deleted > 0 {
+            notify_keyspace_event(server, EventClass::Stream, "xdel", &self.key);
+        }
+        RespElement::Integer(deleted as i64)
+    }
+}
+
+impl FromResp for XDelCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        let [_, key, ids @ ..] = elements.as_slice() else {
+            return Err(CommandError::WrongArity("xdel"));
+        };
+        if ids.is_empty() {
+            return Err(CommandError::WrongArity("xdel"));
+        }
+        let RespElement::BulkString(key) = key else {
+            return Err(CommandError::SyntaxError);
+        };
+        let ids = ids
+            .iter()
+            .map(|id| match id {
+                RespElement::BulkString(id) => parse_entry_id(id.as_ref()),
+                _ => Err(CommandError::SyntaxError),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(XDelCommand {
+            key: key.as_ref().to_owned(),
+            ids,
+        })
+    }
+}
+
+impl From<XDelCommand> for Command {
+    fn from(cmd: XDelCommand) -> Self {
+        Command::XDel(cmd)
+    }
+}
+
+/// `XTRIM key MAXLEN|MINID [=|~] threshold`, where `~` trims exactly too.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct XTrimCommand {
+    key: String,
+    trim: Trim,
+}
+
+impl CommandExecutor for XTrimCommand {
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        let mut db = server.db.write(&self.key);
+        let Some(mut entry) = db::get_live(&mut db, &self.key) else {
+            return RespElement::Integer(0);
+        };
+        let Value::Stream(stream) = &mut entry.value else {
+            return wrong_type();
+        };
+        let trimmed = stream.trim(self.trim);
+        drop(entry);
+        drop(db);
+
+        if trimmed > 0 {
+            notify_keyspace_event(server, EventClass::Stream, "xtrim", &self.key);
+        }
+        RespElement::Integer(trimmed as i64)
+    }
+}
+
+impl FromResp for XTrimCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        let args = elements
+            .iter()
+            .skip(1)
+            .map(|element| match element {
+                RespElement::BulkString(arg) => Ok(arg.as_ref()),
+                _ => Err(CommandError::SyntaxError),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let (key, strategy, threshold) = match args.as_slice() {
+            [key, strategy, "=" | "~", threshold] | [key, strategy, threshold] => {
+                (key, strategy, threshold)
+            }
+            [_, _, _, ..] => return Err(CommandError::SyntaxError),
+            _ => return Err(CommandError::WrongArity("xtrim")),
+        };
+        let trim = match strategy.to_uppercase().as_str() {
+            "MAXLEN" => {
+                let max_len = threshold
+                    .parse::<i64>()
+                    .map_err(|_| CommandError::NotAnInteger)?;
+                let max_len = usize::try_from(max_len).map_err(|_| CommandError::NegativeMaxLen)?;
+                Trim::MaxLen(max_len)
+            }
+            "MINID" => Trim::MinId(parse_entry_id(threshold)?),
+            _ => return Err(CommandError::SyntaxError),
+        };
+        Ok(XTrimCommand {
+            key: key.to_string(),
+            trim,
+        })
+    }
+}
+
+impl From<XTrimCommand> for Command {
+    fn from(cmd: XTrimCommand) -> Self {
+        Command::XTrim(cmd)
+    }
+}
+
+/// Convert a stream entry to its `[id, [field, value, ...]]` reply.
+fn entry_to_resp(id: StreamId, fields: &[(Bytes, Bytes)]) -> RespElement {
+    RespElement::Array(vec![
+        RespElement::BulkString(id.to_string().into()),
+        RespElement::Array(
+            fields
+                .iter()
+                .flat_map(|(field, value)| [field, value])
+                .map(|s| RespElement::BulkString(s.clone().into()))
+                .collect(),
+        ),
+    ])
+}
+
+/// Parse an inclusive range bound, where `-` and `+` are the smallest and largest IDs
+/// and a bare `<ms>` covers every sequence number at that time.
+fn parse_range_bound(s: &str, is_start: bool) -> Result<StreamId, CommandError> {
+    match s {
+        "-" => Ok(StreamId::MIN),
+        "+" => Ok(StreamId::MAX),
+        _ if !s.contains('-') => {
+            let ms = s.parse().map_err(|_| CommandError::InvalidStreamId)?;
+            Ok(StreamId::new(ms, if is_start { 0 } else { u64::MAX }))
+        }
+        _ => s.parse().map_err(|_| CommandError::InvalidStreamId),
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct XRangeCommand {
+    key: String,
+    start: StreamId,
+    end: StreamId,
+    count: Option<usize>,
+    /// Whether this is `XREVRANGE`, returning the newest entries first.
+    rev: bool,
+}
+
+impl CommandExecutor for XRangeCommand {
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        server
+            .db
+            .with_live(&self.key, |value| match &value.value {
+                Value::Stream(stream) => self.range(stream),
+                _ => wrong_type(),
+            })
+            .unwrap_or_else(|| RespElement::Array(vec![]))
+    }
+}
+
+impl XRangeCommand {
+    /// The entries of `stream` in the range, newest first for `XREVRANGE`.
+    fn range(&self, stream: &Stream) -> RespElement {
+        if self.start > self.end {
+            return RespElement::Array(vec![]);
+        }
+
+        let range = stream.entries.range(self.start..=self.end);
+        let count = self.count.unwrap_or(usize::MAX);
+        let entries = if self.rev {
+            range
+                .rev()
+                .take(count)
+                .map(|(&id, fields)| entry_to_resp(id, fields))
+                .collect()
+        } else {
+            range
+                .take(count)
+                .map(|(&id, fields)| entry_to_resp(id, fields))
+                .collect()
+        };
+        RespElement::Array(entries)
+    }
+
+    pub(super) fn from_resp(elements: Vec<RespElement>, rev: bool) -> Result<Self, CommandError> {
+        let name = if rev { "xrevrange" } else { "xrange" };
+        let [_, key, first, second, options @ ..] = elements.as_slice() else {
+            return Err(CommandError::WrongArity(name));
+        };
+        let (
+            RespElement::BulkString(key),
+            RespElement::BulkString(first),
+            RespElement::BulkString(second),
+        ) = (key, first, second)
+        else {
+            return Err(CommandError::SyntaxError);
+        };
+        // XREVRANGE takes its range from the end to the start.
+        let (start, end) = if rev {
+            (second, first)
+        } else {
+            (first, second)
+        };
+
+        let count = match options {
+            [] => None,
+            [RespElement::BulkString(option), count]
+                if option.as_ref().eq_ignore_ascii_case("COUNT") =>
+            {
+                // Like Redis, a negative count returns nothing.
+                Some(parse_signed_int(count)?.max(0) as usize)
+            }
+            _ => return Err(CommandError::SyntaxError),
+        };
+
+        Ok(XRangeCommand {
+            key: key.as_ref().to_owned(),
+            start: parse_range_bound(start.as_ref(), true)?,
+            end: parse_range_bound(end.as_ref(), false)?,
+            count,
+            rev,
+        })
+    }
+}
+
+impl From<XRangeCommand> for Command {
+    fn from(cmd: XRangeCommand) -> Self {
+        Command::XRange(cmd)
+    }
+}
+
+/// Where to start reading a stream in `XREAD`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum XReadFrom {
+    /// Entries with IDs greater than this.
+    After(StreamId),
+    /// `$`, only entries added after the command started.
+    Last,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct XReadCommand {
+    streams: Vec<(String, XReadFrom)>,
+    count: Option<usize>,
+    /// How long to wait for new entries, where zero waits forever.
+    block: Option<Duration>,
+}
+
+impl XReadCommand {
+    /// Resolve `$` into the last ID of each stream now.
+    fn resolve(&self, server: &Server) -> Result<Vec<(String, StreamId)>, RespElement> {
+        let mut streams = Vec::with_capacity(self.streams.len());
+        for (key, from) in &self.streams {
+            let after = match from {
+                XReadFrom::After(id) => *id,
+                XReadFrom::Last => server
+                    .db
+                    .with_live(key, |value| match &value.value {
+                        Value::Stream(stream) => Ok(stream.last_id),
+                        _ => Err(wrong_type()),
+                    })
+                    .unwrap_or(Ok(StreamId::MIN))?,
+            };
+            streams.push((key.clone(), after));
+        }
+        Ok(streams)
+    }
+
+    /// Read the entries after each ID, or `None` if there aren't any in any stream.
+    fn read(
+        &self,
+        server: &Server,
+        streams: &[(String, StreamId)],
+    ) -> Result<Option<RespElement>, RespElement> {
+        let count = self.count.unwrap_or(usize::MAX);
+        let mut replies = Vec::new();
+        for (key, after) in streams {
+            let entries = server.db.with_live(key, |value| match &value.value {
+                Value::Stream(stream) => Ok(stream
+                    .entries
+                    .range((Bound::Excluded(after), Bound::Unbounded))
+                    .take(count)
+                    .map(|(&id, fields)| entry_to_resp(id, fields))
+                    .collect::<Vec<_>>()),
+                _ => Err(wrong_type()),
+            });
+            let Some(entries) = entries.transpose()? else {
+                continue;
+            };
+            if !entries.is_empty() {
+                replies.push(RespElement::Array(vec![
+                    RespElement::BulkString(key.as_str().into()),
+                    RespElement::Array(entries),
+                ]));
+            }
+        }
+        Ok((!replies.is_empty()).then_some(RespElement::Array(replies)))
+    }
+
+    /// Execute the command, waiting for new entries if it blocks and there are none yet.
+    pub(crate) async fn execute_blocking(self, server: &Arc<Server>) -> RespElement {
+        // Subscribe first, so entries added while reading aren't missed.
+        let mut writes = server.key_waiters.subscribe();
+        let streams = match self.resolve(server) {
+            Ok(streams) => streams,
+            Err(e) => return e,
+        };
+        let deadline = self
+            .block
+            .filter(|block| !block.is_zero())
+            .map(|block| tokio::time::Instant::now() + block);
+        loop {
+            match self.read(server, &streams) {
+                Ok(Some(reply)) => return reply,
+                Ok(None) if self.block.is_none() => return NullArray.into(),
+                Ok(None) => {}
+                Err(e) => return e,
+            }
+            let changed = match deadline {
+                Some(deadline) => tokio::time::timeout_at(deadline, writes.changed()).await,
+                None => Ok(writes.changed().await),
+            };
+            if !matches!(changed, Ok(Ok(()))) {
+                return NullArray.into();
+            }
+        }
+    }
+}
+
+impl CommandExecutor for XReadCommand {
+    /// Read without blocking, as there's no connection to block.
+    fn execute(self, server: &Arc<Server>) -> RespElement {
+        let read = self
+            .resolve(server)
+            .and_then(|streams| self.read(server, &streams));
+        match read {
+            Ok(reply) => reply.unwrap_or_else(|| NullArray.into()),
+            Err(e) => e,
+        }
+    }
+}
+
+/// Parse an `XREAD` ID, where a bare `<ms>` means the first ID at that time.
+fn parse_read_from(s: &str) -> Result<XReadFrom, CommandError> {
+    if s == "$" {
+        return Ok(XReadFrom::Last);
+    }
+    let id = match s.parse() {
+        Ok(ms) => StreamId::new(ms, 0),
+        Err(_) => s.parse().map_err(|_| CommandError::InvalidStreamId)?,
+    };
+    Ok(XReadFrom::After(id))
+}
+
+impl FromResp for XReadCommand {
+    type Resp = Vec<RespElement>;
+
+    fn from_resp(elements: Self::Resp) -> Result<Self, CommandError>
+    where
+        Self: Sized,
+    {
+        if elements.len() < 4 {
+            return Err(CommandError::WrongArity("xread"));
+        }
+        let mut args = elements[1..].iter();
+        let mut count = None;
+        let mut block = None;
+        loop {
+            let Some(RespElement::BulkString(option)) = args.next() else {
+                return Err(CommandError::SyntaxError);
+            };
+            match option.as_ref().to_ascii_uppercase().as_str() {
+                "COUNT" => {
+                    let n = parse_signed_int(args.next().ok_or(CommandError::SyntaxError)?)?;
+                    count = Some(n.max(0) as usize);
+                }
+                "BLOCK" => {
+                    let ms = parse_signed_int(args.next().ok_or(CommandError::SyntaxError)?)?;
+                    let ms = u64::try_from(ms).map_err(|_| CommandError::NegativeTimeout)?;
+                    block = Some(Duration::from_millis(ms));
+                }
+                "STREAMS" => break,
+                _ => return Err(CommandError::SyntaxError),
+            }
+        }
+
+        let rest = args.as_slice();
+        if rest.is_empty() || rest.len() % 2 != 0 {
+            return Err(CommandError::UnbalancedStreams("xread"));
+        }
+        let (keys, ids) = rest.split_at(rest.len() / 2);
+        let streams = keys
+            .iter()
+            .zip(ids)
+            .map(|(key, id)| match (key, id) {
+                (RespElement::BulkString(key), RespElement::BulkString(id)) => {
+                    Ok((key.as_ref().to_owned(), parse_read_from(id.as_ref())?))
+                }
+                _ => Err(CommandError::SyntaxError),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(XReadCommand {
+            streams,
+            count,
+            block,
+        })
+    }
+}
+
+impl From<XReadCommand> for Command {
+    fn from(cmd: XReadCommand) -> Self {
+        Command::XRead(cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::commands::test_util::{command, execute};
+
+    fn bulk(s: &str) -> RespElement {
+        RespElement::BulkString(s.into())
+    }
+
+    fn error(s: &str) -> RespElement {
+        RespElement::SimpleError(SimpleError::from(s.to_owned()))
+    }
+
+    #[test]
+    fn test_xadd_auto_id() {
+        let server = Arc::new(Server::new(HashMap::new()));
+
+        let RespElement::BulkString(first) = execute(&server, &[b"XADD", b"s", b"*", b"a", b"1"])
+        else {
+            panic!("Expected bulk string");
+        };
+        let first: StreamId = first.as_ref().parse().unwrap();
+        let RespElement::BulkString(second) = execute(&server, &[b"XADD", b"s", b"*", b"b", b"2"])
+        else {
+            panic!("Expected bulk string");
+        };
+        let second: StreamId = second.as_ref().parse().unwrap();
+        assert!(second > first);
+        assert_eq!(execute(&server, &[b"XLEN", b"s"]), RespElement::Integer(2));
+
+        let ms = second.ms + 1;
+        assert_eq!(
+            execute(
+                &server,
+                &[b"XADD", b"s", format!("{ms}-*").as_bytes(), b"c", b"3"]
+            ),
+            bulk(&format!("{ms}-0"))
+        );
+        assert_eq!(
+            execute(
+                &server,
+                &[b"XADD", b"s", format!("{ms}-*").as_bytes(), b"d", b"4"]
+            ),
+            bulk(&format!("{ms}-1"))
+        );
+    }
+
+    #[test]
+    fn test_xadd_monotonic_ids() {
+        let server = Arc::new(Server::new(HashMap::new()));
+
+        assert_eq!(
+            execute(&server, &[b"XADD", b"s", b"0-0", b"a", b"1"]),
+            error("ERR The ID specified in XADD must be greater than 0-0")
+        );
+        assert_eq!(execute(&server, &[b"XLEN", b"s"]), RespElement::Integer(0));
+        assert_eq!(
+            execute(&server, &[b"XADD", b"s", b"1-1", b"a", b"1"]),
+            bulk("1-1")
+        );
+
+        for id in ["1-1", "1-0", "0-5"] {
+            assert_eq!(
+                execute(&server, &[b"XADD", b"s", id.as_bytes(), b"a", b"1"]),
+                error("ERR The ID specified in XADD is equal or smaller than the target stream top item")
+            );
+        }
+        assert_eq!(
+            execute(&server, &[b"XADD", b"s", b"1-2", b"a", b"1"]),
+            bulk("1-2")
+        );
+        assert_eq!(execute(&server, &[b"XLEN", b"s"]), RespElement::Integer(2));
+    }
+
+    #[test]
+    fn test_xadd_parse_errors() {
+        assert!(matches!(
+            command(&[b"XADD", b"s", b"*", b"a"]),
+            Err(CommandError::WrongArity("xadd"))
+        ));
+        assert!(matches!(
+            command(&[b"XADD", b"s", b"one", b"a", b"1"]),
+            Err(CommandError::InvalidStreamId)
+        ));
+    }
+
+    #[test]
+    fn test_wrong_type() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        execute(&server, &[b"SET", b"string", b"value"]);
+        execute(&server, &[b"XADD", b"s", b"*", b"a", b"1"]);
+
+        assert_eq!(
+            execute(&server, &[b"XADD", b"string", b"*", b"a", b"1"]),
+            wrong_type()
+        );
+        assert_eq!(execute(&server, &[b"XLEN", b"string"]), wrong_type());
+        assert_eq!(execute(&server, &[b"GET", b"s"]), wrong_type());
+    }
+
+    fn entries(ids: &[&str]) -> RespElement {
+        RespElement::Array(
+            ids.iter()
+                .map(|&id| {
+                    RespElement::Array(vec![
+                        bulk(id),
+                        RespElement::Array(vec![bulk("id"), bulk(id)]),
+                    ])
+                })
+                .collect(),
+        )
+    }
+
+    fn populated_server() -> Arc<Server> {
+        let server = Arc::new(Server::new(HashMap::new()));
+        for id in ["1-0", "1-1", "2-0", "3-5"] {
+            execute(
+                &server,
+                &[b"XADD", b"s", id.as_bytes(), b"id", id.as_bytes()],
+            );
+        }
+        server
+    }
+
+    #[rstest::rstest]
+    #[case(&["XRANGE", "s", "-", "+"], &["1-0", "1-1", "2-0", "3-5"])]
+    #[case(&["XREVRANGE", "s", "+", "-"], &["3-5", "2-0", "1-1", "1-0"])]
+    #[case(&["XRANGE", "missing", "-", "+"], &[])]
+    #[case(&["XRANGE", "s", "+", "-"], &[])]
+    fn test_xrange_full(#[case] args: &[&str], #[case] expected: &[&str]) {
+        let args: Vec<&[u8]> = args.iter().map(|arg| arg.as_bytes()).collect();
+        let server = populated_server();
+        assert_eq!(execute(&server, &args), entries(expected));
+    }
+
+    #[rstest::rstest]
+    #[case(&["XRANGE", "s", "1-1", "3-0"], &["1-1", "2-0"])]
+    #[case(&["XRANGE", "s", "1", "1"], &["1-0", "1-1"])]
+    #[case(&["XRANGE", "s", "2", "+"], &["2-0", "3-5"])]
+    #[case(&["XREVRANGE", "s", "3", "1-1"], &["3-5", "2-0", "1-1"])]
+    fn test_xrange_bounded(#[case] args: &[&str], #[case] expected: &[&str]) {
+        let args: Vec<&[u8]> = args.iter().map(|arg| arg.as_bytes()).collect();
+        let server = populated_server();
+        assert_eq!(execute(&server, &args), entries(expected));
+    }
+
+    #[rstest::rstest]
+    #[case(&["XRANGE", "s", "-", "+", "COUNT", "2"], &["1-0", "1-1"])]
+    #[case(&["XREVRANGE", "s", "+", "-", "count", "3"], &["3-5", "2-0", "1-1"])]
+    #[case(&["XRANGE", "s", "-", "+", "COUNT", "0"], &[])]
+    fn test_xrange_count(#[case] args: &[&str], #[case] expected: &[&str]) {
+        let args: Vec<&[u8]> = args.iter().map(|arg| arg.as_bytes()).collect();
+        let server = populated_server();
+        assert_eq!(execute(&server, &args), entries(expected));
+    }
+
+    #[test]
+    fn test_xread_after_id() {
+        let server = populated_server();
+        execute(&server, &[b"XADD", b"other", b"5-0", b"id", b"5-0"]);
+
+        let reply = execute(
+            &server,
+            &[
+                b"XREAD", b"STREAMS", b"s", b"other", b"missing", b"1-1", b"0", b"0",
+            ],
+        );
+        assert_eq!(
+            reply,
+            RespElement::Array(vec![
+                RespElement::Array(vec![bulk("s"), entries(&["2-0", "3-5"])]),
+                RespElement::Array(vec![bulk("other"), entries(&["5-0"])]),
+            ])
+        );
+
+        let reply = execute(&server, &[b"XREAD", b"COUNT", b"1", b"STREAMS", b"s", b"1"]);
+        assert_eq!(
+            reply,
+            RespElement::Array(vec![RespElement::Array(vec![bulk("s"), entries(&["1-1"])])])
+        );
+        assert_eq!(
+            execute(&server, &[b"XREAD", b"STREAMS", b"s", b"$"]),
+            NullArray.into()
+        );
+    }
+
+    #[test]
+    fn test_xread_unbalanced() {
+        assert!(matches!(
+            command(&[b"XREAD", b"STREAMS", b"a", b"b", b"0"]),
+            Err(CommandError::UnbalancedStreams("xread"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_xread_block() {
+        let server = populated_server();
+        let Command::XRead(cmd) =
+            command(&[b"XREAD", b"BLOCK", b"0", b"STREAMS", b"s", b"$"]).unwrap()
+        else {
+            panic!("Expected XREAD");
+        };
+        let blocked = tokio::spawn({
+            let server = server.clone();
+            async move { cmd.execute_blocking(&server).await }
+        });
+        tokio::task::yield_now().await;
+        execute(&server, &[b"XADD", b"s", b"4-0", b"id", b"4-0"]);
+
+        assert_eq!(
+            blocked.await.unwrap(),
+            RespElement::Array(vec![RespElement::Array(vec![bulk("s"), entries(&["4-0"])])])
+        );
+
+        let Command::XRead(cmd) =
+            command(&[b"XREAD", b"BLOCK", b"10", b"STREAMS", b"s", b"$"]).unwrap()
+        else {
+            panic!("Expected XREAD");
+        };
+        assert_eq!(cmd.execute_blocking(&server).await, NullArray.into());
+    }
+
+    #[test]
+    fn test_xdel() {
+        let server = populated_server();
+        assert_eq!(
+            execute(&server, &[b"XDEL", b"s", b"1-1", b"2", b"9-9"]),
+            RespElement::Integer(2)
+        );
+        assert_eq!(
+            execute(&server, &[b"XRANGE", b"s", b"-", b"+"]),
+            entries(&["1-0", "3-5"])
+        );
+        assert_eq!(
+            execute(&server, &[b"XDEL", b"missing", b"1-0"]),
+            RespElement::Integer(0)
+        );
+
+        // Deleting the newest entry doesn't let its ID be reused.
+        execute(&server, &[b"XDEL", b"s", b"3-5"]);
+        assert_eq!(
+            execute(&server, &[b"XADD", b"s", b"3-5", b"id", b"3-5"]),
+            error(
+                "ERR The ID specified in XADD is equal or smaller than the target stream top item"
+            )
+        );
+        assert!(matches!(
+            command(&[b"XDEL", b"s", b"bad-id"]),
+            Err(CommandError::InvalidStreamId)
+        ));
+    }
+
+    #[rstest::rstest]
+    #[case(&["MAXLEN", "2"], 2, &["2-0", "3-5"])]
+    #[case(&["MAXLEN", "~", "1"], 3, &["3-5"])]
+    #[case(&["MAXLEN", "10"], 0, &["1-0", "1-1", "2-0", "3-5"])]
+    #[case(&["MINID", "=", "1-1"], 1, &["1-1", "2-0", "3-5"])]
+    #[case(&["minid", "3"], 3, &["3-5"])]
+    fn test_xtrim(#[case] args: &[&str], #[case] trimmed: i64, #[case] remaining: &[&str]) {
+        let server = populated_server();
+        let mut xtrim: Vec<&[u8]> = vec![b"XTRIM", b"s"];
+        xtrim.extend(args.iter().map(|arg| arg.as_bytes()));
+        assert_eq!(execute(&server, &xtrim), RespElement::Integer(trimmed));
+        assert_eq!(
+            execute(&server, &[b"XRANGE", b"s", b"-", b"+"]),
+            entries(remaining)
+        );
+    }
+
+    #[test]
+    fn test_xtrim_invalid() {
+        assert!(matches!(
+            command(&[b"XTRIM", b"s", b"MAXLEN", b"-1"]),
+            Err(CommandError::NegativeMaxLen)
+        ));
+        assert!(matches!(
+            command(&[b"XTRIM", b"s", b"MAXLEN", b"~", b"1", b"LIMIT", b"10"]),
+            Err(CommandError::SyntaxError)
+        ));
+        assert!(matches!(
+            command(&[b"XTRIM", b"s", b"MAXLEN"]),
+            Err(CommandError::WrongArity("xtrim"))
+        ));
+    }
+}
@@ -0,0 +1,754 @@
+use std::{
+    cell::Cell,
+    collections::{hash_map::RandomState, HashMap},
+    future::{poll_fn, Future},
+    hash::BuildHasher,
+    ops::{Deref, DerefMut},
+    pin::pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        LazyLock, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+
+use crate::{expiry::random_index, hll::HyperLogLog, stream::Stream};
+
+/// Number of independently locked shards the keyspace is split into.
+const SHARDS: usize = 16;
+
+/// How many expired keys `RANDOMKEY` removes while looking for a live one before
+/// giving up, so a keyspace of mostly expired keys can't stall it.
+const RANDOM_KEY_ATTEMPTS: usize = 100;
+
+/// Rough per-key overhead of the hash table entry and `DbValue`, used to estimate memory usage.
+const ENTRY_OVERHEAD: usize = 64;
+
+/// Fewest buckets a shard's table shrinks to.
+const MIN_BUCKETS: usize = 4;
+
+/// Source of the ticks recording when each value was last accessed.
+static LRU_CLOCK: AtomicU64 = AtomicU64::new(0);
+
+/// What access times are measured from, so they fit in an atomic.
+static CLOCK_START: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+thread_local! {
+    /// Set while running a command which shouldn't count as an access to the keys it reads.
+    static NO_TOUCH: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Puts back the previous `NO_TOUCH` setting once dropped, so it's restored even if the
+/// command panics.
+struct NoTouchGuard(bool);
+
+impl Drop for NoTouchGuard {
+    fn drop(&mut self) {
+        NO_TOUCH.set(self.0);
+    }
+}
+
+/// Run `f` without updating the access time of any key it reads, for `CLIENT NO-TOUCH`.
+pub(crate) fn without_touching<T>(f: impl FnOnce() -> T) -> T {
+    let _guard = NoTouchGuard(NO_TOUCH.replace(true));
+    f()
+}
+
+/// Run `fut` like `without_touching`. The flag is only set while it's being polled, since
+/// other tasks run on the same thread whenever it's waiting.
+pub(crate) async fn without_touching_async<F: Future>(fut: F) -> F::Output {
+    let mut fut = pin!(fut);
+    poll_fn(|cx| without_touching(|| fut.as_mut().poll(cx))).await
+}
+
+/// Record a read of `value`, for `OBJECT IDLETIME` and LRU eviction, unless running
+/// `without_touching`.
+fn touch_access(value: &DbValue) {
+    if !NO_TOUCH.get() {
+        value.touch();
+    }
+}
+
+/// The data stored under a key.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum Value {
+    String(Bytes),
+    HyperLogLog(HyperLogLog),
+    Stream(Stream),
+}
+
+/// Longest string Redis stores in the same allocation as its object header.
+const EMBSTR_MAX_LEN: usize = 44;
+
+impl Value {
+    /// The name of the value's type, as used by `SCAN TYPE`.
+    pub(crate) fn type_name(&self) -> &'static str {
+        match self {
+            // Redis stores HyperLogLogs as strings, so that's the type clients see.
+            Value::String(_) | Value::HyperLogLog(_) => "string",
+            Value::Stream(_) => "stream",
+        }
+    }
+
+    /// The internal encoding Redis would use for the value, as reported by `OBJECT ENCODING`.
+    pub(crate) fn encoding(&self) -> &'static str {
+        match self {
+            // Strings which round-trip through an integer are stored as one.
+            Value::String(s)
+                if s.len() <= 20
+                    && std::str::from_utf8(s)
+                        .ok()
+                        .and_then(|s| s.parse::<i64>().ok())
+                        .is_some_and(|i| i.to_string().as_bytes() == &s[..]) =>
+            {
+                "int"
+            }
+            Value::String(s) if s.len() <= EMBSTR_MAX_LEN => "embstr",
+            Value::String(_) | Value::HyperLogLog(_) => "raw",
+            Value::Stream(_) => "stream",
+        }
+    }
+
+    /// Approximate number of bytes used to store the value.
+    fn memory_usage(&self) -> usize {
+        self.sampled_memory_usage(0)
+    }
+
+    /// Approximate number of bytes used to store the value, estimating containers
+    /// from `samples` of their elements, or all of them if `samples` is zero.
+    fn sampled_memory_usage(&self, samples: usize) -> usize {
+        match self {
+            Value::String(s) => s.len(),
+            Value::HyperLogLog(_) => HyperLogLog::MEMORY_USAGE,
+            Value::Stream(stream) => stream.sampled_memory_usage(samples),
+        }
+    }
+}
+
+impl From<Bytes> for Value {
+    fn from(s: Bytes) -> Self {
+        Value::String(s)
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(s: Vec<u8>) -> Self {
+        Value::String(s.into())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s.into())
+    }
+}
+
+impl From<&'static str> for Value {
+    fn from(s: &'static str) -> Self {
+        Value::String(s.into())
+    }
+}
+
+impl From<HyperLogLog> for Value {
+    fn from(hll: HyperLogLog) -> Self {
+        Value::HyperLogLog(hll)
+    }
+}
+
+impl From<Stream> for Value {
+    fn from(stream: Stream) -> Self {
+        Value::Stream(stream)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct DbValue {
+    pub(crate) value: Value,
+    pub(crate) expires_at: Option<Instant>,
+    pub(crate) last_access: AccessTick,
+}
+
+impl DbValue {
+    pub(crate) fn new(value: Value, expires_at: Option<Instant>) -> Self {
+        DbValue {
+            value,
+            expires_at,
+            last_access: AccessTick::now(),
+        }
+    }
+
+    /// Record an access, for LRU eviction.
+    pub(crate) fn touch(&self) {
+        self.last_access.tick.store(next_tick(), Ordering::Relaxed);
+        self.last_access
+            .millis
+            .store(millis_since_start(), Ordering::Relaxed);
+    }
+
+    /// Approximate number of bytes used to store this value under `key`.
+    pub(crate) fn memory_usage(&self, key: &str) -> usize {
+        key.len() + self.value.memory_usage() + ENTRY_OVERHEAD
+    }
+
+    /// Like `memory_usage`, but estimating containers from `samples` of their elements.
+    pub(crate) fn sampled_memory_usage(&self, key: &str, samples: usize) -> usize {
+        key.len() + self.value.sampled_memory_usage(samples) + ENTRY_OVERHEAD
+    }
+
+    pub(crate) fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|at| at <= now)
+    }
+}
+
+fn next_tick() -> u64 {
+    LRU_CLOCK.fetch_add(1, Ordering::Relaxed)
+}
+
+fn millis_since_start() -> u64 {
+    CLOCK_START.elapsed().as_millis() as u64
+}
+
+/// When a value was last accessed, both relative to other values and in time.
+///
+/// The tick orders accesses exactly for LRU eviction, even several within a millisecond,
+/// while the time is for reporting how long the value has been idle.
+///
+/// Atomic so reads can update it while only holding a shard's read lock. It is metadata
+/// rather than part of the value, so it is ignored when comparing values.
+#[derive(Debug, Default)]
+pub(crate) struct AccessTick {
+    tick: AtomicU64,
+    millis: AtomicU64,
+}
+
+impl AccessTick {
+    fn now() -> Self {
+        AccessTick {
+            tick: AtomicU64::new(next_tick()),
+            millis: AtomicU64::new(millis_since_start()),
+        }
+    }
+
+    pub(crate) fn get(&self) -> u64 {
+        self.tick.load(Ordering::Relaxed)
+    }
+
+    /// How long since the value was last accessed.
+    pub(crate) fn idle_time(&self) -> Duration {
+        let millis = self.millis.load(Ordering::Relaxed);
+        Duration::from_millis(millis_since_start().saturating_sub(millis))
+    }
+}
+
+impl Clone for AccessTick {
+    fn clone(&self) -> Self {
+        AccessTick {
+            tick: AtomicU64::new(self.get()),
+            millis: AtomicU64::new(self.millis.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl PartialEq for AccessTick {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for AccessTick {}
+
+/// The keys and values in one part of the keyspace, along with the memory they use, which
+/// is kept up to date as they change so `maxmemory` can be checked without walking them.
+///
+/// Keys are kept in a power-of-two number of chained buckets, like Redis' dict, rather than
+/// a `HashMap`, so `SCAN` can resume from a bucket. Values are only changed through its own
+/// methods, which account for the change.
+#[derive(Debug)]
+pub(crate) struct Shard {
+    hasher: RandomState,
+    buckets: Vec<Vec<(String, DbValue)>>,
+    len: usize,
+    used_memory: usize,
+}
+
+impl Default for Shard {
+    fn default() -> Self {
+        Shard {
+            hasher: RandomState::new(),
+            buckets: Self::empty_buckets(MIN_BUCKETS),
+            len: 0,
+            used_memory: 0,
+        }
+    }
+}
+
+impl Shard {
+    fn empty_buckets(size: usize) -> Vec<Vec<(String, DbValue)>> {
+        (0..size).map(|_| Vec::new()).collect()
+    }
+
+    fn bucket_index(&self, key: &str) -> usize {
+        self.hasher.hash_one(key) as usize & (self.buckets.len() - 1)
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&DbValue> {
+        self.buckets[self.bucket_index(key)]
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&String, &DbValue)> {
+        self.buckets
+            .iter()
+            .flatten()
+            .map(|(key, value)| (key, value))
+    }
+
+    pub(crate) fn insert(&mut self, key: String, value: DbValue) -> Option<DbValue> {
+        let old = self.remove(&key);
+        self.used_memory += value.memory_usage(&key);
+        let idx = self.bucket_index(&key);
+        self.buckets[idx].push((key, value));
+        self.len += 1;
+        if self.len > self.buckets.len() {
+            self.resize(self.buckets.len() * 2);
+        }
+        old
+    }
+
+    pub(crate) fn remove(&mut self, key: &str) -> Option<DbValue> {
+        let idx = self.bucket_index(key);
+        let bucket = &mut self.buckets[idx];
+        let pos = bucket.iter().position(|(k, _)| k == key)?;
+        let (key, value) = bucket.swap_remove(pos);
+        self.used_memory -= value.memory_usage(&key);
+        self.len -= 1;
+        if self.buckets.len() > MIN_BUCKETS && self.len < self.buckets.len() / 8 {
+            self.resize(self.len.next_power_of_two().max(MIN_BUCKETS));
+        }
+        Some(value)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.buckets = Self::empty_buckets(MIN_BUCKETS);
+        self.len = 0;
+        self.used_memory = 0;
+    }
+
+    fn resize(&mut self, size: usize) {
+        let old = std::mem::replace(&mut self.buckets, Self::empty_buckets(size));
+        for (key, value) in old.into_iter().flatten() {
+            let idx = self.bucket_index(&key);
+            self.buckets[idx].push((key, value));
+        }
+    }
+
+    /// The value under `key`, to change in place.
+    pub(crate) fn get_mut(&mut self, key: &str) -> Option<ValueMut<'_>> {
+        let idx = self.bucket_index(key);
+        let (_, value) = self.buckets[idx].iter_mut().find(|(k, _)| k == key)?;
+        Some(ValueMut {
+            before: value.value.memory_usage(),
+            value,
+            used_memory: &mut self.used_memory,
+        })
+    }
+
+    /// Approximate memory used by the shard's keys and values.
+    pub(crate) fn used_memory(&self) -> usize {
+        self.used_memory
+    }
+
+    /// Pass every key in the bucket at `cursor` to `f`, returning the cursor of the next
+    /// bucket, or 0 once they've all been visited.
+    ///
+    /// Like Redis, buckets are visited in order of their reversed index, so a bucket's keys
+    /// always land in buckets later in the walk when the table grows or shrinks, and every
+    /// key present for the whole walk is visited however it's resized in between.
+    fn scan_bucket(&self, cursor: u64, mut f: impl FnMut(&String, &DbValue)) -> u64 {
+        let mask = self.buckets.len() as u64 - 1;
+        for (key, value) in &self.buckets[(cursor & mask) as usize] {
+            f(key, value);
+        }
+        ((cursor | !mask).reverse_bits().wrapping_add(1)).reverse_bits()
+    }
+}
+
+/// A value being changed in place, whose change in size is added to its shard's memory
+/// usage once it's dropped.
+#[derive(Debug)]
+pub(crate) struct ValueMut<'a> {
+    value: &'a mut DbValue,
+    used_memory: &'a mut usize,
+    /// The value's memory usage before it was borrowed.
+    before: usize,
+}
+
+impl Deref for ValueMut<'_> {
+    type Target = DbValue;
+
+    fn deref(&self) -> &Self::Target {
+        self.value
+    }
+}
+
+impl DerefMut for ValueMut<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.value
+    }
+}
+
+impl Drop for ValueMut<'_> {
+    fn drop(&mut self) {
+        *self.used_memory = *self.used_memory + self.value.value.memory_usage() - self.before;
+    }
+}
+
+/// The value under `key` if it hasn't expired, removing it from the shard if it has.
+pub(crate) fn get_live<'a>(shard: &'a mut Shard, key: &str) -> Option<ValueMut<'a>> {
+    if shard.get(key)?.is_expired(Instant::now()) {
+        shard.remove(key);
+        return None;
+    }
+    shard.get_mut(key)
+}
+
+/// The keyspace, split into shards which are each behind their own lock
+/// so commands on different keys don't serialise on a single mutex.
+///
+/// Shards are read-write locked, so read-only commands on the same shard can run concurrently
+/// and only writes take the exclusive lock.
+#[derive(Debug)]
+pub(crate) struct Db {
+    hasher: RandomState,
+    shards: Vec<RwLock<Shard>>,
+}
+
+impl Default for Db {
+    fn default() -> Self {
+        Db {
+            hasher: RandomState::new(),
+            shards: (0..SHARDS).map(|_| RwLock::new(Shard::default())).collect(),
+        }
+    }
+}
+
+impl Db {
+    fn shard_index(&self, key: &str) -> usize {
+        self.hasher.hash_one(key) as usize % self.shards.len()
+    }
+
+    fn shard_for(&self, key: &str) -> &RwLock<Shard> {
+        &self.shards[self.shard_index(key)]
+    }
+
+    /// Share the shard holding `key`, for read-only commands.
+    pub(crate) fn read(&self, key: &str) -> RwLockReadGuard<'_, Shard> {
+        self.shard_for(key).read().unwrap()
+    }
+
+    /// Lock the shard holding `key`, for commands which need to read and update it atomically.
+    pub(crate) fn write(&self, key: &str) -> RwLockWriteGuard<'_, Shard> {
+        self.shard_for(key).write().unwrap()
+    }
+
+    /// Lock every shard, in order, so nothing else can read or change the keyspace.
+    pub(crate) fn write_all(&self) -> Vec<RwLockWriteGuard<'_, Shard>> {
+        self.shards.iter().map(|s| s.write().unwrap()).collect()
+    }
+
+    /// Every shard, for operations which walk the whole keyspace.
+    pub(crate) fn shards(&self) -> impl Iterator<Item = &RwLock<Shard>> {
+        self.shards.iter()
+    }
+
+    /// Run `f` on the value under `key` while its shard is read locked, so nothing is
+    /// copied, unless it has expired, in which case it's removed so the memory is reclaimed
+    /// without waiting for the active expiry cycle.
+    pub(crate) fn with_live<T>(&self, key: &str, f: impl FnOnce(&DbValue) -> T) -> Option<T> {
+        {
+            let shard = self.read(key);
+            let value = shard.get(key)?;
+            if !value.is_expired(Instant::now()) {
+                touch_access(value);
+                return Some(f(value));
+            }
+        }
+        // Only take the write lock once the key is known to have expired. It may have
+        // been replaced in the meantime, so check again.
+        let mut shard = self.write(key);
+        let value = get_live(&mut shard, key)?;
+        touch_access(&value);
+        Some(f(&value))
+    }
+
+    /// A copy of the value under `key`, unless it has expired, as in `with_live`.
+    #[cfg(test)]
+    pub(crate) fn get_live(&self, key: &str) -> Option<DbValue> {
+        self.with_live(key, DbValue::clone)
+    }
+
+    pub(crate) fn insert(&self, key: String, value: DbValue) -> Option<DbValue> {
+        self.write(&key).insert(key, value)
+    }
+
+    pub(crate) fn extend(&self, entries: impl IntoIterator<Item = (String, DbValue)>) {
+        for (key, value) in entries {
+            self.insert(key, value);
+        }
+    }
+
+    /// Swap the whole keyspace for `entries`, such as when loading a snapshot from a master.
+    /// Every shard is locked first, so readers never see a mix of old and new keys.
+    pub(crate) fn replace(&self, entries: impl IntoIterator<Item = (String, DbValue)>) {
+        let mut shards = self.write_all();
+        for shard in shards.iter_mut() {
+            shard.clear();
+        }
+        for (key, value) in entries {
+            shards[self.shard_index(&key)].insert(key, value);
+        }
+    }
+
+    /// Approximate memory used by every key and value, for enforcing `maxmemory`.
+    pub(crate) fn used_memory(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.read().unwrap().used_memory())
+            .sum()
+    }
+
+    /// A random key which hasn't expired. Expired keys found along the way are removed,
+    /// up to `RANDOM_KEY_ATTEMPTS` of them before giving up.
+    pub(crate) fn random_key(&self) -> Option<String> {
+        for _ in 0..RANDOM_KEY_ATTEMPTS {
+            let start = random_index(self.shards.len());
+            let (key, expired) = (start..self.shards.len()).chain(0..start).find_map(|idx| {
+                let shard = self.shards[idx].read().unwrap();
+                if shard.is_empty() {
+                    return None;
+                }
+                let (key, value) = shard.iter().nth(random_index(shard.len()))?;
+                Some((key.clone(), value.is_expired(Instant::now())))
+            })?;
+            if !expired {
+                return Some(key);
+            }
+            get_live(&mut self.write(&key), &key);
+        }
+        None
+    }
+
+    /// Keys from the buckets at and after `cursor`, with their type names and the cursor to
+    /// continue from, which is 0 once every shard has been walked. Expired keys are removed
+    /// instead of being returned.
+    ///
+    /// The low bits of the cursor pick the shard and the rest the bucket within it. Buckets
+    /// are visited until at least `count` keys are found, or ten times that many buckets
+    /// turn out empty, so each call only touches about `count` keys however big the
+    /// keyspace is.
+    pub(crate) fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<(String, &'static str)>) {
+        let now = Instant::now();
+        let shards = self.shards.len() as u64;
+        let mut shard_idx = (cursor % shards) as usize;
+        let mut bucket = cursor / shards;
+        let mut visits = count.saturating_mul(10).max(1);
+        let mut live = Vec::new();
+        let mut expired = Vec::new();
+        let next = loop {
+            let shard = self.shards[shard_idx].read().unwrap();
+            loop {
+                bucket = shard.scan_bucket(bucket, |key, value| {
+                    if value.is_expired(now) {
+                        expired.push(key.clone());
+                    } else {
+                        live.push((key.clone(), value.value.type_name()));
+                    }
+                });
+                visits -= 1;
+                if bucket == 0 || live.len() >= count || visits == 0 {
+                    break;
+                }
+            }
+            drop(shard);
+            if bucket == 0 {
+                shard_idx += 1;
+                if shard_idx == self.shards.len() {
+                    break 0;
+                }
+            }
+            if live.len() >= count || visits == 0 {
+                break bucket * shards + shard_idx as u64;
+            }
+        };
+        for key in expired {
+            get_live(&mut self.write(&key), &key);
+        }
+        (next, live)
+    }
+
+    /// A consistent copy of the whole keyspace. Every shard is locked before any is copied.
+    pub(crate) fn snapshot(&self) -> HashMap<String, DbValue> {
+        let shards: Vec<_> = self.shards.iter().map(|s| s.read().unwrap()).collect();
+        shards
+            .iter()
+            .flat_map(|shard| shard.iter())
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{mpsc, Arc};
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        commands::{test_util::execute, Command},
+        parse::{NullBulkString, RespElement},
+        Server,
+    };
+
+    #[test]
+    fn test_concurrent_sets_on_disjoint_keys() {
+        const THREADS: usize = 8;
+        const KEYS_PER_THREAD: usize = 1000;
+
+        let server = Arc::new(Server::new(HashMap::new()));
+        let handles: Vec<_> = (0..THREADS)
+            .map(|thread| {
+                let server = server.clone();
+                std::thread::spawn(move || {
+                    for i in 0..KEYS_PER_THREAD {
+                        let key = format!("key:{thread}:{i}");
+                        let value = format!("value:{thread}:{i}");
+                        execute(&server, &[b"SET", key.as_bytes(), value.as_bytes()]);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(server.db.snapshot().len(), THREADS * KEYS_PER_THREAD);
+        for thread in 0..THREADS {
+            for i in 0..KEYS_PER_THREAD {
+                let value = server.db.get_live(&format!("key:{thread}:{i}")).unwrap();
+                assert_eq!(value.value, format!("value:{thread}:{i}").into());
+            }
+        }
+    }
+
+    #[test]
+    fn test_concurrent_readers() {
+        const READERS: usize = 16;
+
+        let server = Arc::new(Server::new(HashMap::new()));
+        for i in 0..100 {
+            server.db.insert(
+                format!("key:{i}"),
+                DbValue::new(format!("value:{i}").into(), None),
+            );
+        }
+
+        // Hold a read lock on a shard for the whole test. With an exclusive lock
+        // every GET against this shard would block until it was released.
+        let _guard = server.db.read("key:0");
+
+        let (tx, rx) = mpsc::channel();
+        for _ in 0..READERS {
+            let server = server.clone();
+            let tx = tx.clone();
+            std::thread::spawn(move || {
+                for i in 0..100 {
+                    let resp = execute(&server, &[b"GET", format!("key:{i}").as_bytes()]);
+                    assert_eq!(resp, RespElement::BulkString(format!("value:{i}").into()));
+                }
+                tx.send(()).unwrap();
+            });
+        }
+        for _ in 0..READERS {
+            rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_db_api() {
+        let db = Db::default();
+        let value = DbValue::new("value".into(), None);
+        assert_eq!(db.insert("key".to_owned(), value.clone()), None);
+        assert_eq!(db.get_live("key"), Some(value.clone()));
+        assert_eq!(db.insert("key".to_owned(), value.clone()), Some(value));
+        assert_eq!(db.get_live("missing"), None);
+    }
+
+    #[test]
+    fn test_without_touching_restored_after_panic() {
+        let result = std::panic::catch_unwind(|| without_touching(|| panic!("command failed")));
+        assert!(result.is_err());
+        assert!(!NO_TOUCH.get());
+    }
+
+    #[test]
+    fn test_get_removes_expired_key() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        server.db.insert(
+            "key".to_owned(),
+            DbValue::new("value".into(), Some(Instant::now())),
+        );
+
+        let resp = Command::Get("key".to_owned()).execute(&server);
+        assert_eq!(resp, NullBulkString.into());
+        assert!(server.db.read("key").get("key").is_none());
+    }
+
+    #[test]
+    fn test_used_memory_follows_writes() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        let recounted = |server: &Server| -> usize {
+            server
+                .db
+                .snapshot()
+                .iter()
+                .map(|(key, value)| value.memory_usage(key))
+                .sum()
+        };
+        let run = |args: &[&str]| {
+            let bytes: Vec<&[u8]> = args.iter().map(|arg| arg.as_bytes()).collect();
+            execute(&server, &bytes);
+            assert_eq!(server.db.used_memory(), recounted(&server), "{args:?}");
+        };
+
+        run(&["SET", "string", "value"]);
+        run(&["SET", "string", "a much longer value"]);
+        run(&["SETRANGE", "string", "100", "x"]);
+        run(&["SETRANGE", "grown", "3", "abc"]);
+        run(&["XADD", "stream", "1-1", "field", "value"]);
+        run(&["XADD", "stream", "1-2", "field", "another value"]);
+        run(&["XDEL", "stream", "1-1"]);
+        run(&["XTRIM", "stream", "MAXLEN", "0"]);
+        run(&["PFADD", "hll", "a", "b"]);
+        run(&["SET", "string", "v", "PX", "1"]);
+        std::thread::sleep(Duration::from_millis(5));
+        run(&["GET", "string"]);
+        server.db.write("grown").remove("grown");
+        assert_eq!(server.db.used_memory(), recounted(&server));
+        server
+            .db
+            .replace([("key".to_owned(), DbValue::new("value".into(), None))]);
+        assert_eq!(server.db.used_memory(), recounted(&server));
+    }
+}
@@ -0,0 +1,167 @@
+use crate::{
+    expiry::random_index,
+    parse::SimpleError,
+    pubsub::{notify_keyspace_event, EventClass},
+    Server,
+};
+
+/// How many keys are compared when picking one to evict, like Redis' `maxmemory-samples`.
+const EVICTION_SAMPLES: usize = 5;
+
+/// What to do when a write would take memory usage over `maxmemory`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum EvictionPolicy {
+    /// Refuse writes with an OOM error.
+    NoEviction,
+    /// Evict the least recently used keys.
+    AllKeysLru,
+    /// Evict the least recently used keys which have an expiry.
+    VolatileLru,
+}
+
+impl EvictionPolicy {
+    /// The `maxmemory-policy` values which are supported.
+    pub(crate) const NAMES: [&'static str; 3] = ["noeviction", "allkeys-lru", "volatile-lru"];
+
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "noeviction" => Some(Self::NoEviction),
+            "allkeys-lru" => Some(Self::AllKeysLru),
+            "volatile-lru" => Some(Self::VolatileLru),
+            _ => None,
+        }
+    }
+}
+
+/// Evict keys until memory usage is back under `maxmemory`, before running a command
+/// which may add to the dataset.
+///
+/// Like Redis, this only looks at usage before the write, so a single write can take usage
+/// over the limit until the next one.
+pub(crate) fn make_room(server: &Server) -> Result<(), SimpleError> {
+    let maxmemory = server.maxmemory();
+    if maxmemory == 0 {
+        return Ok(());
+    }
+
+    let policy = server.maxmemory_policy();
+    let mut used = server.db.used_memory();
+    while used > maxmemory {
+        let freed = match policy {
+            EvictionPolicy::NoEviction => None,
+            _ => evict_one(server, policy),
+        };
+        let Some(freed) = freed else {
+            return Err(SimpleError::from(
+                "OOM command not allowed when used memory > 'maxmemory'.".to_owned(),
+            ));
+        };
+        used = used.saturating_sub(freed);
+    }
+    Ok(())
+}
+
+/// Sample a few keys the policy allows evicting, starting from a random position, and remove
+/// the least recently used. Returns the memory freed, or `None` if there was nothing to evict.
+fn evict_one(server: &Server, policy: EvictionPolicy) -> Option<usize> {
+    let shards: Vec<_> = server.db.shards().collect();
+    let start = random_index(shards.len());
+    let mut oldest: Option<(u64, String, usize)> = None;
+    let mut sampled = 0;
+    for idx in (start..shards.len()).chain(0..start) {
+        let shard = shards[idx].read().unwrap();
+        if shard.is_empty() {
+            continue;
+        }
+        let skip = random_index(shard.len());
+        let candidates = shard
+            .iter()
+            .skip(skip)
+            .chain(shard.iter().take(skip))
+            .filter(|(_, value)| policy == EvictionPolicy::AllKeysLru || value.expires_at.is_some())
+            .take(EVICTION_SAMPLES - sampled);
+        for (key, value) in candidates {
+            sampled += 1;
+            let tick = value.last_access.get();
+            if oldest.as_ref().is_none_or(|(oldest, ..)| tick < *oldest) {
+                oldest = Some((tick, key.clone(), idx));
+            }
+        }
+        if sampled == EVICTION_SAMPLES {
+            break;
+        }
+    }
+
+    let (_, key, idx) = oldest?;
+    // Another client may have removed the key since it was sampled, which frees nothing.
+    let Some(removed) = shards[idx].write().unwrap().remove(&key) else {
+        return Some(0);
+    };
+    notify_keyspace_event(server, EventClass::Evicted, "evicted", &key);
+    Some(removed.memory_usage(&key))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{commands::test_util::execute, parse::RespElement, OptValue};
+
+    /// Memory used by each of the keys in these tests.
+    const ENTRY_SIZE: u64 = "key:0".len() as u64 + "value".len() as u64 + 64;
+
+    fn server(policy: &str) -> Arc<Server> {
+        let mut opts = HashMap::new();
+        opts.insert("maxmemory".to_owned(), OptValue::UInt(ENTRY_SIZE * 3));
+        opts.insert(
+            "maxmemory-policy".to_owned(),
+            OptValue::String(policy.to_owned().into()),
+        );
+        Arc::new(Server::new(opts))
+    }
+
+    #[test]
+    fn test_allkeys_lru_evicts_least_recently_used() {
+        let server = server("allkeys-lru");
+        for i in 0..3 {
+            execute(&server, &[b"SET", format!("key:{i}").as_bytes(), b"value"]);
+        }
+        execute(&server, &[b"GET", b"key:0"]);
+        for i in 3..6 {
+            let resp = execute(&server, &[b"SET", format!("key:{i}").as_bytes(), b"value"]);
+            assert_eq!(resp, RespElement::SimpleString("OK".to_owned().into()));
+        }
+
+        let mut keys: Vec<_> = server.db.snapshot().into_keys().collect();
+        keys.sort();
+        assert_eq!(keys, ["key:0", "key:3", "key:4", "key:5"]);
+    }
+
+    #[test]
+    fn test_noeviction_refuses_writes() {
+        let server = server("noeviction");
+        for i in 0..4 {
+            execute(&server, &[b"SET", format!("key:{i}").as_bytes(), b"value"]);
+        }
+
+        let resp = execute(&server, &[b"SET", b"key:4", b"value"]);
+        assert!(matches!(resp, RespElement::SimpleError(e) if e.as_str().starts_with("OOM")));
+        assert_eq!(server.db.snapshot().len(), 4);
+    }
+
+    #[test]
+    fn test_volatile_lru_only_evicts_keys_with_expiry() {
+        let server = server("volatile-lru");
+        execute(&server, &[b"SET", b"key:0", b"value", b"EX", b"100"]);
+        for i in 1..4 {
+            execute(&server, &[b"SET", format!("key:{i}").as_bytes(), b"value"]);
+        }
+
+        execute(&server, &[b"SET", b"key:4", b"value"]);
+        assert!(server.db.get_live("key:0").is_none());
+        let resp = execute(&server, &[b"SET", b"key:5", b"value"]);
+        assert!(matches!(resp, RespElement::SimpleError(_)));
+    }
+}
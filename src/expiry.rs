@@ -0,0 +1,226 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    commands::set::ExpiryOpt,
+    db::Shard,
+    pubsub::{notify_keyspace_event, EventClass},
+    Server,
+};
+
+/// How often the active expiry cycle runs.
+const ACTIVE_EXPIRE_INTERVAL: Duration = Duration::from_millis(100);
+/// How many keys with an expiry are sampled per round of the cycle.
+const ACTIVE_EXPIRE_BATCH: usize = 20;
+/// Upper bound on the time a single cycle may hold a shard's lock.
+const ACTIVE_EXPIRE_BUDGET: Duration = Duration::from_millis(25);
+
+/// The longest time until a deadline, so far-future expiries can't overflow `Instant`.
+/// A key with a TTL this long will never expire in practice.
+const MAX_TTL: Duration = Duration::from_secs(100 * 365 * 24 * 60 * 60);
+
+/// When a key given the expiry `opt` now should expire.
+///
+/// `None` for `KEEPTTL`, which has no deadline of its own: the caller keeps the key's
+/// existing one. Timestamps which have already passed give a deadline of now, so the key
+/// is already expired.
+pub(crate) fn deadline_from(opt: &ExpiryOpt) -> Option<Instant> {
+    match *opt {
+        ExpiryOpt::Seconds(secs) => Some(deadline_after(Duration::from_secs(secs))),
+        ExpiryOpt::Milliseconds(millis) => Some(deadline_after(Duration::from_millis(millis))),
+        ExpiryOpt::TimestampSeconds(secs) => Some(deadline_from_unix(Duration::from_secs(secs))),
+        ExpiryOpt::TimestampMilliseconds(millis) => {
+            Some(deadline_from_unix(Duration::from_millis(millis)))
+        }
+        ExpiryOpt::KeepTtl => None,
+    }
+}
+
+/// The deadline `ttl` from now, capped at `MAX_TTL`.
+fn deadline_after(ttl: Duration) -> Instant {
+    Instant::now() + ttl.min(MAX_TTL)
+}
+
+/// Convert a time since the UNIX epoch into a deadline, which is now if it has passed.
+pub(crate) fn deadline_from_unix(since_epoch: Duration) -> Instant {
+    let ttl = match UNIX_EPOCH.checked_add(since_epoch) {
+        Some(at) => at.duration_since(SystemTime::now()).unwrap_or_default(),
+        None => MAX_TTL,
+    };
+    deadline_after(ttl)
+}
+
+/// Convert a deadline into a time since the UNIX epoch, which is now if it has passed.
+pub(crate) fn unix_from_deadline(deadline: Instant) -> Duration {
+    let at = SystemTime::now() + remaining(deadline);
+    at.duration_since(UNIX_EPOCH).unwrap_or_default()
+}
+
+/// Time left until `deadline`, or zero if it has passed.
+pub(crate) fn remaining(deadline: Instant) -> Duration {
+    deadline.saturating_duration_since(Instant::now())
+}
+
+/// Periodically remove expired keys which are never accessed again.
+///
+/// Like Redis, each cycle samples a batch of keys with an expiry and deletes the expired ones,
+/// repeating while more than a quarter of the sample had expired.
+pub(crate) async fn active_expire(server: Arc<Server>) {
+    let mut interval = tokio::time::interval(ACTIVE_EXPIRE_INTERVAL);
+    loop {
+        interval.tick().await;
+        if !server.active_expire.load(Ordering::Relaxed) {
+            continue;
+        }
+        for shard in server.db.shards() {
+            let mut shard = shard.write().unwrap();
+            let started = Instant::now();
+            let mut removed = Vec::new();
+            loop {
+                let (sampled, expired) = expire_sample(&mut shard, ACTIVE_EXPIRE_BATCH);
+                let done = sampled == 0
+                    || expired.len() * 4 <= sampled
+                    || started.elapsed() > ACTIVE_EXPIRE_BUDGET;
+                removed.extend(expired);
+                if done {
+                    break;
+                }
+            }
+            drop(shard);
+            for key in &removed {
+                notify_keyspace_event(&server, EventClass::Expired, "expired", key);
+            }
+        }
+    }
+}
+
+/// Check up to `batch` keys with an expiry, starting from a random position,
+/// and remove those which have expired. Returns the number sampled and the keys removed.
+fn expire_sample(db: &mut Shard, batch: usize) -> (usize, Vec<String>) {
+    if db.is_empty() {
+        return (0, Vec::new());
+    }
+
+    let now = Instant::now();
+    let start = random_index(db.len());
+    let mut sampled = 0;
+    let expired: Vec<String> = db
+        .iter()
+        .skip(start)
+        .chain(db.iter().take(start))
+        .filter_map(|(key, value)| value.expires_at.map(|expires_at| (key, expires_at)))
+        .take(batch)
+        .filter_map(|(key, expires_at)| {
+            sampled += 1;
+            (expires_at <= now).then(|| key.clone())
+        })
+        .collect();
+
+    for key in &expired {
+        db.remove(key);
+    }
+    (sampled, expired)
+}
+
+pub(crate) fn random_index(len: usize) -> usize {
+    // Each `RandomState` is seeded differently, which is random enough for sampling keys.
+    let hasher = RandomState::new().build_hasher();
+    hasher.finish() as usize % len
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{commands::test_util::execute, db::DbValue};
+
+    #[tokio::test]
+    async fn test_active_expire_removes_untouched_keys() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        execute(&server, &[b"SET", b"short", b"value", b"PX", b"50"]);
+        execute(&server, &[b"SET", b"long", b"value", b"EX", b"100"]);
+        execute(&server, &[b"SET", b"forever", b"value"]);
+
+        let task = tokio::spawn(active_expire(server.clone()));
+        tokio::time::sleep(Duration::from_millis(50) + ACTIVE_EXPIRE_INTERVAL * 3).await;
+        task.abort();
+
+        assert!(server.db.read("short").get("short").is_none());
+        assert!(server.db.read("long").get("long").is_some());
+        assert!(server.db.read("forever").get("forever").is_some());
+    }
+
+    /// Whether `deadline` is within a second of `expected` from now, allowing for the
+    /// time taken by the test.
+    fn is_about(deadline: Instant, expected: Duration) -> bool {
+        let ttl = remaining(deadline);
+        ttl <= expected && ttl + Duration::from_secs(1) > expected
+    }
+
+    fn unix_now() -> Duration {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap()
+    }
+
+    #[test]
+    fn test_deadline_from_relative() {
+        let deadline = deadline_from(&ExpiryOpt::Seconds(100)).unwrap();
+        assert!(is_about(deadline, Duration::from_secs(100)));
+        let deadline = deadline_from(&ExpiryOpt::Milliseconds(100_000)).unwrap();
+        assert!(is_about(deadline, Duration::from_secs(100)));
+    }
+
+    #[test]
+    fn test_deadline_from_timestamp() {
+        let at = unix_now() + Duration::from_secs(100);
+        let deadline = deadline_from(&ExpiryOpt::TimestampSeconds(at.as_secs())).unwrap();
+        assert!(is_about(deadline, Duration::from_secs(100)));
+        let at_millis = at.as_millis() as u64;
+        let deadline = deadline_from(&ExpiryOpt::TimestampMilliseconds(at_millis)).unwrap();
+        assert!(is_about(deadline, Duration::from_secs(100)));
+        assert!(unix_from_deadline(deadline).abs_diff(at) < Duration::from_secs(1));
+
+        // Timestamps in the past have already expired.
+        let deadline = deadline_from(&ExpiryOpt::TimestampSeconds(1)).unwrap();
+        assert_eq!(remaining(deadline), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_deadline_from_saturates() {
+        for opt in [
+            ExpiryOpt::Seconds(u64::MAX),
+            ExpiryOpt::Milliseconds(u64::MAX),
+            ExpiryOpt::TimestampSeconds(u64::MAX),
+            ExpiryOpt::TimestampMilliseconds(u64::MAX),
+        ] {
+            let deadline = deadline_from(&opt).unwrap();
+            assert!(is_about(deadline, MAX_TTL), "{:?}", opt);
+        }
+    }
+
+    #[test]
+    fn test_deadline_from_keepttl() {
+        assert_eq!(deadline_from(&ExpiryOpt::KeepTtl), None);
+    }
+
+    #[test]
+    fn test_expire_sample_only_counts_volatile_keys() {
+        let mut db = Shard::default();
+        db.insert("persistent".to_owned(), DbValue::new("value".into(), None));
+        db.insert(
+            "expired".to_owned(),
+            DbValue::new("value".into(), Some(Instant::now())),
+        );
+
+        assert_eq!(
+            expire_sample(&mut db, ACTIVE_EXPIRE_BATCH),
+            (1, vec!["expired".to_owned()])
+        );
+        assert_eq!(db.len(), 1);
+        assert_eq!(expire_sample(&mut db, ACTIVE_EXPIRE_BATCH), (0, Vec::new()));
+    }
+}
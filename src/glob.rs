@@ -0,0 +1,123 @@
+/// Whether `string` matches the glob-style `pattern`, like Redis' `stringmatchlen`.
+///
+/// `*` matches any run of bytes and `?` any single byte. `[abc]` matches any byte in the
+/// set, which may include ranges like `a-z`, and `[^abc]` any byte not in it. A `\`
+/// matches the next byte literally, including inside a set.
+pub(crate) fn matches(pattern: &[u8], string: &[u8]) -> bool {
+    let (mut p, mut s) = (0, 0);
+    // Where to resume after the last `*` if the rest of the pattern fails to match,
+    // letting the star swallow one more byte.
+    let mut backtrack = None;
+    while s < string.len() {
+        if pattern.get(p) == Some(&b'*') {
+            p += 1;
+            backtrack = Some((p, s));
+            continue;
+        }
+        if let Some((len, true)) = match_byte(&pattern[p..], string[s]) {
+            p += len;
+            s += 1;
+            continue;
+        }
+        match backtrack {
+            Some((star_p, star_s)) => {
+                p = star_p;
+                s = star_s + 1;
+                backtrack = Some((star_p, s));
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Match the first element of `pattern` other than `*` against `byte`, returning how many
+/// bytes of the pattern it took up and whether it matched, or `None` if it's empty.
+fn match_byte(pattern: &[u8], byte: u8) -> Option<(usize, bool)> {
+    match *pattern {
+        [] => None,
+        [b'?', ..] => Some((1, true)),
+        [b'\\', c, ..] => Some((2, c == byte)),
+        [b'[', ..] => Some(match_set(pattern, byte)),
+        [c, ..] => Some((1, c == byte)),
+    }
+}
+
+/// Match a `[...]` set at the start of `pattern` against `byte`. A set missing its closing
+/// `]` runs to the end of the pattern, like in Redis.
+fn match_set(pattern: &[u8], byte: u8) -> (usize, bool) {
+    let mut i = 1;
+    let negate = pattern.get(i) == Some(&b'^');
+    if negate {
+        i += 1;
+    }
+    let mut matched = false;
+    while i < pattern.len() && pattern[i] != b']' {
+        match pattern[i..] {
+            [b'\\', c, ..] => {
+                matched |= c == byte;
+                i += 2;
+            }
+            [start, b'-', end, ..] => {
+                let (low, high) = (start.min(end), start.max(end));
+                matched |= (low..=high).contains(&byte);
+                i += 3;
+            }
+            [c, ..] => {
+                matched |= c == byte;
+                i += 1;
+            }
+            [] => unreachable!(),
+        }
+    }
+    // Include the closing `]`, if there is one.
+    let len = (i + 1).min(pattern.len());
+    (len, matched != negate)
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("*", "", true)]
+    #[case("*", "anything", true)]
+    #[case("h?llo", "hello", true)]
+    #[case("h?llo", "hllo", false)]
+    #[case("h*llo", "heeeello", true)]
+    #[case("h*llo", "hello world", false)]
+    #[case("*o*o*", "foo", true)]
+    #[case("a*b*c", "aXbYbZc", true)]
+    #[case("a*b*c", "aXbYbZ", false)]
+    #[case("h[ae]llo", "hallo", true)]
+    #[case("h[ae]llo", "hillo", false)]
+    #[case("h[^e]llo", "hallo", true)]
+    #[case("h[^e]llo", "hello", false)]
+    #[case("h[^a-d]llo", "hcllo", false)]
+    #[case("h[^a-d]llo", "hxllo", true)]
+    #[case("h[a-b]llo", "hbllo", true)]
+    #[case("h[z-a]llo", "hmllo", true)]
+    #[case("[\\]]", "]", true)]
+    #[case("[abc", "c", true)]
+    #[case("a\\*b", "a*b", true)]
+    #[case("a\\*b", "axb", false)]
+    #[case("\\?", "?", true)]
+    #[case("\\?", "x", false)]
+    #[case("", "", true)]
+    #[case("", "x", false)]
+    fn test_matches(#[case] pattern: &str, #[case] string: &str, #[case] expected: bool) {
+        assert_eq!(
+            matches(pattern.as_bytes(), string.as_bytes()),
+            expected,
+            "{pattern:?} against {string:?}"
+        );
+    }
+
+    #[test]
+    fn test_matches_binary() {
+        assert!(matches(b"\xff*\x00", b"\xff\x01\x02\x00"));
+        assert!(!matches(b"[^\x00]", b"\x00"));
+    }
+}
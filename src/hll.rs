@@ -0,0 +1,219 @@
+use std::fmt;
+
+/// Number of bits of the hash used to pick a register.
+const HLL_P: u32 = 14;
+/// Number of bits of the hash used to count leading zeroes.
+const HLL_Q: u32 = 64 - HLL_P;
+const HLL_REGISTERS: usize = 1 << HLL_P;
+const HLL_BITS: usize = 6;
+/// Seed Redis uses when hashing elements.
+const HLL_HASH_SEED: u64 = 0xadc83b19;
+
+const HLL_MAGIC: &[u8] = b"HYLL";
+const HLL_DENSE: u8 = 0;
+/// Magic, encoding, three unused bytes and the cached cardinality.
+const HLL_HEADER_LEN: usize = 16;
+const HLL_DENSE_LEN: usize = HLL_HEADER_LEN + (HLL_REGISTERS * HLL_BITS).div_ceil(8);
+
+/// A HyperLogLog cardinality estimator with the same parameters as Redis,
+/// so the same elements give the same estimates.
+///
+/// Registers are held one per byte, and only packed into Redis' dense encoding
+/// when written to an RDB file.
+#[derive(Clone, Eq, PartialEq)]
+pub(crate) struct HyperLogLog {
+    registers: Box<[u8]>,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        HyperLogLog {
+            registers: vec![0; HLL_REGISTERS].into_boxed_slice(),
+        }
+    }
+}
+
+impl fmt::Debug for HyperLogLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HyperLogLog").finish_non_exhaustive()
+    }
+}
+
+impl HyperLogLog {
+    /// Approximate number of bytes used by each HyperLogLog.
+    pub(crate) const MEMORY_USAGE: usize = HLL_REGISTERS;
+
+    /// Add an element, returning whether any register changed.
+    pub(crate) fn add(&mut self, element: &[u8]) -> bool {
+        let hash = murmurhash64a(element, HLL_HASH_SEED);
+        let index = hash as usize & (HLL_REGISTERS - 1);
+        // Setting a bit past the end of the hash caps the count at `HLL_Q + 1`.
+        let count = ((hash >> HLL_P) | (1 << HLL_Q)).trailing_zeros() as u8 + 1;
+        if count > self.registers[index] {
+            self.registers[index] = count;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Take the union of this and `other`.
+    pub(crate) fn merge(&mut self, other: &HyperLogLog) {
+        for (register, &theirs) in self.registers.iter_mut().zip(other.registers.iter()) {
+            *register = (*register).max(theirs);
+        }
+    }
+
+    /// Estimate the number of distinct elements added, using the estimator
+    /// from Otmar Ertl's "New cardinality estimation algorithms for HyperLogLog sketches"
+    /// as Redis does.
+    pub(crate) fn count(&self) -> u64 {
+        let m = HLL_REGISTERS as f64;
+        let mut histogram = [0u32; HLL_Q as usize + 2];
+        for &register in self.registers.iter() {
+            histogram[register as usize] += 1;
+        }
+
+        let mut z = m * tau((m - histogram[HLL_Q as usize + 1] as f64) / m);
+        for &count in histogram[1..=HLL_Q as usize].iter().rev() {
+            z += count as f64;
+            z *= 0.5;
+        }
+        z += m * sigma(histogram[0] as f64 / m);
+        (0.5 / std::f64::consts::LN_2 * m * m / z).round() as u64
+    }
+
+    /// Pack the registers into Redis' dense encoding.
+    pub(crate) fn to_dense_bytes(&self) -> Vec<u8> {
+        let mut out = vec![0; HLL_DENSE_LEN];
+        out[..HLL_MAGIC.len()].copy_from_slice(HLL_MAGIC);
+        out[4] = HLL_DENSE;
+        // The most significant bit of the cached cardinality marks it as stale.
+        out[HLL_HEADER_LEN - 1] = 1 << 7;
+
+        let registers = &mut out[HLL_HEADER_LEN..];
+        for (index, &value) in self.registers.iter().enumerate() {
+            let byte = index * HLL_BITS / 8;
+            let shift = index * HLL_BITS % 8;
+            registers[byte] |= value << shift;
+            if shift > 8 - HLL_BITS {
+                registers[byte + 1] |= value >> (8 - shift);
+            }
+        }
+        out
+    }
+
+    /// Unpack a value in Redis' dense encoding. Sparse encodings aren't supported.
+    pub(crate) fn from_dense_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != HLL_DENSE_LEN || !bytes.starts_with(HLL_MAGIC) || bytes[4] != HLL_DENSE {
+            return None;
+        }
+
+        let packed = &bytes[HLL_HEADER_LEN..];
+        let mut hll = HyperLogLog::default();
+        for (index, register) in hll.registers.iter_mut().enumerate() {
+            let byte = index * HLL_BITS / 8;
+            let shift = index * HLL_BITS % 8;
+            let next = packed.get(byte + 1).copied().unwrap_or(0) as u16;
+            let bits = (packed[byte] as u16 | next << 8) >> shift;
+            *register = bits as u8 & ((1 << HLL_BITS) - 1);
+        }
+        Some(hll)
+    }
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let mut y = 1.0;
+    let mut z = x;
+    loop {
+        x *= x;
+        let previous = z;
+        z += x * y;
+        y += y;
+        if previous == z {
+            return z;
+        }
+    }
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let mut y = 1.0;
+    let mut z = 1.0 - x;
+    loop {
+        x = x.sqrt();
+        let previous = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if previous == z {
+            return z / 3.0;
+        }
+    }
+}
+
+/// MurmurHash64A, the hash Redis uses for HyperLogLog elements.
+fn murmurhash64a(key: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4a7935bd1e995;
+    const R: u32 = 47;
+
+    let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+    let chunks = key.chunks_exact(8);
+    let tail = chunks.remainder();
+    for chunk in chunks {
+        let mut k = u64::from_le_bytes(chunk.try_into().unwrap());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+    if !tail.is_empty() {
+        for (i, &b) in tail.iter().enumerate() {
+            h ^= (b as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_within_error() {
+        let mut hll = HyperLogLog::default();
+        for i in 0..1000 {
+            hll.add(format!("element:{i}").as_bytes());
+        }
+        let estimate = hll.count() as f64;
+        assert!((estimate - 1000.0).abs() < 30.0, "estimate was {estimate}");
+    }
+
+    #[test]
+    fn test_add_reports_changes() {
+        let mut hll = HyperLogLog::default();
+        assert!(hll.add(b"foo"));
+        assert!(!hll.add(b"foo"));
+        assert_eq!(hll.count(), 1);
+    }
+
+    #[test]
+    fn test_dense_round_trip() {
+        let mut hll = HyperLogLog::default();
+        for i in 0..100 {
+            hll.add(format!("element:{i}").as_bytes());
+        }
+        let bytes = hll.to_dense_bytes();
+        assert_eq!(bytes.len(), HLL_DENSE_LEN);
+        assert_eq!(HyperLogLog::from_dense_bytes(&bytes), Some(hll));
+    }
+}
@@ -0,0 +1,242 @@
+use bytes::Bytes;
+
+/// Size of the header: the total number of bytes and the number of elements.
+const HEADER_LEN: usize = 6;
+const END: u8 = 0xFF;
+/// Stored as the number of elements when there are too many to count in the header.
+const UNKNOWN_LEN: u16 = u16::MAX;
+
+/// An element of a listpack, which Redis stores as an integer when it can.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum Element {
+    Int(i64),
+    String(Bytes),
+}
+
+impl Element {
+    /// The element as the bytes clients see, so integers are written in decimal.
+    pub(crate) fn into_bytes(self) -> Bytes {
+        match self {
+            Element::Int(i) => i.to_string().into(),
+            Element::String(s) => s,
+        }
+    }
+
+    /// The element as an integer, if it is one.
+    pub(crate) fn as_int(&self) -> Option<i64> {
+        match self {
+            Element::Int(i) => Some(*i),
+            Element::String(s) => std::str::from_utf8(s).ok()?.parse().ok(),
+        }
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        let start = out.len();
+        match self {
+            &Element::Int(i @ 0..=127) => out.push(i as u8),
+            &Element::Int(i @ -4096..=4095) => {
+                let i = i as u16 & 0x1FFF;
+                out.extend_from_slice(&[0xC0 | (i >> 8) as u8, i as u8]);
+            }
+            &Element::Int(i) if i16::try_from(i).is_ok() => {
+                out.push(0xF1);
+                out.extend_from_slice(&(i as i16).to_le_bytes());
+            }
+            &Element::Int(i @ -0x80_0000..=0x7F_FFFF) => {
+                out.push(0xF2);
+                out.extend_from_slice(&(i as i32).to_le_bytes()[..3]);
+            }
+            &Element::Int(i) if i32::try_from(i).is_ok() => {
+                out.push(0xF3);
+                out.extend_from_slice(&(i as i32).to_le_bytes());
+            }
+            &Element::Int(i) => {
+                out.push(0xF4);
+                out.extend_from_slice(&i.to_le_bytes());
+            }
+            Element::String(s) if s.len() < 1 << 6 => out.push(0x80 | s.len() as u8),
+            Element::String(s) if s.len() < 1 << 12 => {
+                out.extend_from_slice(&[0xE0 | (s.len() >> 8) as u8, s.len() as u8]);
+            }
+            Element::String(s) => {
+                out.push(0xF0);
+                out.extend_from_slice(&(s.len() as u32).to_le_bytes());
+            }
+        }
+        if let Element::String(s) = self {
+            out.extend_from_slice(s);
+        }
+        let len = out.len() - start;
+        write_backlen(out, len);
+    }
+}
+
+impl From<i64> for Element {
+    fn from(i: i64) -> Self {
+        Element::Int(i)
+    }
+}
+
+impl From<Bytes> for Element {
+    fn from(s: Bytes) -> Self {
+        Element::String(s)
+    }
+}
+
+/// Write the length of the element before it, so the listpack can be walked backwards.
+///
+/// Seven bits are stored in each byte, most significant first, with the top bit set on
+/// every byte but the first.
+fn write_backlen(out: &mut Vec<u8>, len: usize) {
+    let bytes = backlen_size(len);
+    for i in (0..bytes).rev() {
+        let byte = ((len >> (7 * i)) & 0x7F) as u8;
+        out.push(if i == bytes - 1 { byte } else { byte | 0x80 });
+    }
+}
+
+fn backlen_size(len: usize) -> usize {
+    match len {
+        0..=127 => 1,
+        128..=16382 => 2,
+        16383..=2097150 => 3,
+        2097151..=268435454 => 4,
+        _ => 5,
+    }
+}
+
+/// Encode `elements` as a listpack.
+pub(crate) fn encode(elements: &[Element]) -> Vec<u8> {
+    let mut out = vec![0; HEADER_LEN];
+    for element in elements {
+        element.encode(&mut out);
+    }
+    out.push(END);
+    let total = out.len() as u32;
+    let count = u16::try_from(elements.len()).unwrap_or(UNKNOWN_LEN);
+    out[..4].copy_from_slice(&total.to_le_bytes());
+    out[4..HEADER_LEN].copy_from_slice(&count.to_le_bytes());
+    out
+}
+
+/// Decode a listpack, or `None` if it's malformed.
+pub(crate) fn decode(listpack: &[u8]) -> Option<Vec<Element>> {
+    let total = u32::from_le_bytes(listpack.get(..4)?.try_into().unwrap()) as usize;
+    if total <= HEADER_LEN || total != listpack.len() || listpack.last() != Some(&END) {
+        return None;
+    }
+    let mut input = &listpack[HEADER_LEN..total - 1];
+    let mut elements = Vec::new();
+    while !input.is_empty() {
+        let (element, len) = decode_element(input)?;
+        elements.push(element);
+        input = input.get(len + backlen_size(len)..)?;
+    }
+    Some(elements)
+}
+
+/// Decode the element at the start of `input`, and how many bytes it takes, excluding
+/// its back length.
+fn decode_element(input: &[u8]) -> Option<(Element, usize)> {
+    let int = |len: usize| -> Option<i64> {
+        let bytes = input.get(1..=len)?;
+        let mut buf = [0; 8];
+        buf[..len].copy_from_slice(bytes);
+        // Sign-extend from the top bit of the stored bytes.
+        let shift = 64 - 8 * len as u32;
+        Some(i64::from_le_bytes(buf) << shift >> shift)
+    };
+    let string = |header: usize, len: usize| -> Option<(Element, usize)> {
+        let s = input.get(header..header + len)?;
+        Some((Element::String(Bytes::copy_from_slice(s)), header + len))
+    };
+    let first = *input.first()?;
+    match first {
+        0x00..=0x7F => Some((Element::Int(first.into()), 1)),
+        0x80..=0xBF => string(1, (first & 0x3F).into()),
+        0xC0..=0xDF => {
+            let i = (u16::from(first & 0x1F) << 8) | u16::from(*input.get(1)?);
+            // Sign-extend from 13 bits.
+            Some((Element::Int(i64::from((i << 3) as i16 >> 3)), 2))
+        }
+        0xE0..=0xEF => string(
+            2,
+            (usize::from(first & 0x0F) << 8) | usize::from(*input.get(1)?),
+        ),
+        0xF0 => string(
+            5,
+            u32::from_le_bytes(input.get(1..5)?.try_into().unwrap()) as usize,
+        ),
+        0xF1 => Some((Element::Int(int(2)?), 3)),
+        0xF2 => Some((Element::Int(int(3)?), 4)),
+        0xF3 => Some((Element::Int(int(4)?), 5)),
+        0xF4 => Some((Element::Int(int(8)?), 9)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case(0)]
+    #[case(127)]
+    #[case(128)]
+    #[case(-1)]
+    #[case(-4096)]
+    #[case(4095)]
+    #[case(-32768)]
+    #[case(32767)]
+    #[case(-8388608)]
+    #[case(8388607)]
+    #[case(i32::MIN.into())]
+    #[case(i32::MAX.into())]
+    #[case(i64::MIN)]
+    #[case(i64::MAX)]
+    fn test_int_round_trip(#[case] i: i64) {
+        let listpack = encode(&[Element::Int(i)]);
+        assert_eq!(decode(&listpack), Some(vec![Element::Int(i)]));
+    }
+
+    #[rstest]
+    #[case(0)]
+    #[case(63)]
+    #[case(64)]
+    #[case(4095)]
+    #[case(4096)]
+    #[case(20000)]
+    fn test_string_round_trip(#[case] len: usize) {
+        let elements = vec![
+            Element::String("x".repeat(len).into()),
+            Element::Int(7),
+            Element::String("y".into()),
+        ];
+        assert_eq!(decode(&encode(&elements)), Some(elements));
+    }
+
+    #[test]
+    fn test_encoding_matches_redis() {
+        // The listpack Redis builds for `RPUSH l a 1 -1000`.
+        let listpack = encode(&[
+            Element::String("a".into()),
+            Element::Int(1),
+            Element::Int(-1000),
+        ]);
+        assert_eq!(
+            listpack,
+            b"\x0f\x00\x00\x00\x03\x00\x81a\x02\x01\x01\xdc\x18\x02\xff"
+        );
+    }
+
+    #[rstest]
+    #[case(b"")]
+    #[case(b"\x05\x00\x00\x00\xff")]
+    #[case(b"\x07\x00\x00\x00\x00\x00\xff\xff")]
+    #[case(b"\x09\x00\x00\x00\x01\x00\x85a\xff")]
+    fn test_decode_malformed(#[case] listpack: &[u8]) {
+        assert_eq!(decode(listpack), None);
+    }
+}
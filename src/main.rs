@@ -1,82 +1,1534 @@
+use bytes::{Buf, Bytes, BytesMut};
 use clap::Parser;
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::io;
-use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
-use tokio::io::AsyncWriteExt;
-use tokio::net::{TcpListener, TcpStream};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64};
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter};
+use tokio::net::{TcpListener, TcpSocket, TcpStream, UnixListener, UnixStream};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{mpsc, watch, Semaphore};
+use tokio::task::JoinSet;
 
+mod aof;
+mod blocking;
+mod clients;
 mod commands;
+mod db;
+mod eviction;
+mod expiry;
+mod glob;
+mod hll;
+mod listpack;
+mod monitor;
 mod parse;
+mod pubsub;
+mod rdb;
+mod replication;
+mod slowlog;
+mod stream;
 
+use aof::Aof;
+use blocking::KeyWaiters;
+use clients::{ClientAddr, ClientRegistry, ClientState};
 use commands::*;
-use parse::RespSerialise;
+use db::Db;
+use eviction::EvictionPolicy;
+use monitor::Monitors;
+use parse::{RespElement, RespSerialise};
+use pubsub::PubSub;
+use replication::Replication;
+use slowlog::SlowLog;
 
 #[derive(Debug, Parser)]
 pub(crate) struct Opts {
-    #[clap(short, long, default_value = "6379")]
+    #[clap(short, long, default_value_t = DEFAULT_PORT)]
     port: u16,
+    /// Addresses of the interfaces to listen on.
+    #[clap(long, num_args = 1.., default_values_t = [DEFAULT_BIND])]
+    bind: Vec<IpAddr>,
+    /// Path of a Unix socket to listen on as well, or instead with a port of 0.
+    #[clap(long)]
+    unixsocket: Option<PathBuf>,
+    /// Whether to probe idle clients to check they are still there: `on`, or 0 not to.
+    ///
+    /// Unlike Redis this takes no number of seconds, as the idle time before probing is left
+    /// to the OS and can't be configured through the standard library.
+    #[clap(long, default_value = "on", value_parser = parse_tcp_keepalive, action = clap::ArgAction::Set)]
+    tcp_keepalive: bool,
+    /// Most connections waiting to be accepted on each TCP listener.
+    #[clap(long, default_value_t = DEFAULT_TCP_BACKLOG)]
+    tcp_backlog: u32,
     #[clap(short, long, default_value = "/tmp/redis-data")]
     dir: PathBuf,
-    #[clap(short, long, default_value = "rdbfile")]
+    /// Whether to rebuild the dataset from the append-only file rather than the RDB file.
+    #[clap(long, default_value = "no", value_parser = parse_yes_no, action = clap::ArgAction::Set)]
+    appendonly: bool,
+    // `-d` is already taken by `dir`.
+    #[clap(long, default_value = "rdbfile")]
     dbfilename: String,
+    #[clap(long, default_value_t = parse::DEFAULT_MAX_BULK_LEN)]
+    proto_max_bulk_len: u64,
+    /// Replies holding more elements than this, counting nested ones, are written one element
+    /// at a time rather than serialised whole.
+    #[clap(long, default_value_t = parse::DEFAULT_STREAMED_REPLY_LEN)]
+    streamed_reply_len: u64,
+    /// Memory limit for the dataset in bytes, or 0 for no limit.
+    #[clap(long, default_value_t = 0)]
+    maxmemory: u64,
+    #[clap(long, default_value = "noeviction", value_parser = EvictionPolicy::NAMES)]
+    maxmemory_policy: String,
+    #[clap(long, default_value_t = DEFAULT_MAX_CLIENTS)]
+    maxclients: u64,
+    /// Classes of keyspace event to publish, e.g. `KEA` for all of them.
+    #[clap(long, default_value = "", value_parser = parse_keyspace_events_opt)]
+    notify_keyspace_events: String,
+    /// Commands taking longer than this many microseconds are logged, or none if negative.
+    #[clap(long, default_value_t = DEFAULT_SLOWLOG_LOG_SLOWER_THAN, allow_negative_numbers = true)]
+    slowlog_log_slower_than: i64,
+    #[clap(long, default_value_t = DEFAULT_SLOWLOG_MAX_LEN)]
+    slowlog_max_len: u64,
+    /// Whether a replica refuses writes from its clients, so it can't diverge from its master.
+    #[clap(long, default_value = "yes", value_parser = parse_yes_no, action = clap::ArgAction::Set)]
+    replica_read_only: bool,
 }
 
+fn parse_yes_no(value: &str) -> Result<bool, String> {
+    match value {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => Err("expected yes or no".to_owned()),
+    }
+}
+
+fn parse_tcp_keepalive(value: &str) -> Result<bool, String> {
+    match value {
+        "on" => Ok(true),
+        "0" => Ok(false),
+        _ => Err("expected on or 0, as the idle time before probing can't be set".to_owned()),
+    }
+}
+
+fn parse_keyspace_events_opt(flags: &str) -> Result<String, String> {
+    match pubsub::parse_keyspace_events(flags) {
+        Some(_) => Ok(flags.to_owned()),
+        None => Err("expected any of the flags K, E, g, $, x, e, t and A".to_owned()),
+    }
+}
+
+/// The version of the server, as reported to clients.
+pub(crate) const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Default for `port`, the port clients connect to.
+const DEFAULT_PORT: u16 = 6379;
+
+/// Default for `bind`, so the server is only reachable from this host.
+const DEFAULT_BIND: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+/// Default for `tcp-backlog`, the most connections waiting to be accepted.
+const DEFAULT_TCP_BACKLOG: u32 = 511;
+
+/// Default for `maxclients`, the most connections served at once.
+const DEFAULT_MAX_CLIENTS: u64 = 10000;
+
+/// Default for `slowlog-log-slower-than`, in microseconds.
+const DEFAULT_SLOWLOG_LOG_SLOWER_THAN: i64 = 10000;
+
+/// Default for `slowlog-max-len`, the most slow commands logged.
+const DEFAULT_SLOWLOG_MAX_LEN: u64 = 128;
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let opts = Opts::parse();
-    let listener = TcpListener::bind(format!("127.0.0.1:{}", opts.port)).await?;
+    let mut listeners = Listeners::default();
+    if opts.port != 0 || opts.unixsocket.is_none() {
+        for &addr in &opts.bind {
+            let addr = SocketAddr::new(addr, opts.port);
+            let listener = bind_tcp(addr, opts.tcp_keepalive, opts.tcp_backlog)?;
+            listeners.tcp.push(listener);
+        }
+    }
+    let unixsocket = opts.unixsocket.clone();
+    if let Some(path) = &unixsocket {
+        // A socket left behind by a server which didn't shut down cleanly stops it binding.
+        let _ = std::fs::remove_file(path);
+        listeners.unix = Some((UnixListener::bind(path)?, Arc::from(path.as_path())));
+    }
+
+    // The append-only file is preferred, as it's more up to date than the last snapshot.
+    let aof_path = opts.dir.join(aof::FILENAME);
+    let appendonly = opts.appendonly;
+    let replay_aof = appendonly && aof_path.exists();
+    let db = if replay_aof {
+        HashMap::new()
+    } else {
+        rdb::load(&opts.dir.join(&opts.dbfilename))?
+    };
+    let server = Arc::new(Server::new(load_opts(opts)));
+    server.db.extend(db);
+    if replay_aof {
+        aof::replay(&aof_path, &server)?;
+    }
+    if appendonly {
+        server.aof.open(&aof_path)?;
+    }
+    tokio::spawn(expiry::active_expire(server.clone()));
+
+    let signal_server = server.clone();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        signal_server.shutdown.send_replace(true);
+    });
+
+    serve(listeners, server.clone()).await?;
+    if let Some(path) = unixsocket {
+        let _ = std::fs::remove_file(path);
+    }
+
+    if let Some(path) = server.rdb_path() {
+        if let Err(e) = rdb::save(&path, &server.db.snapshot()) {
+            eprintln!("Unable to save the database on shutdown: {}", e);
+        }
+    }
+    Ok(())
+}
+
+/// Listen on `addr` with room for `backlog` connections waiting to be accepted.
+/// Connections accepted from it inherit `SO_KEEPALIVE` when `keepalive` is set.
+fn bind_tcp(addr: SocketAddr, keepalive: bool, backlog: u32) -> std::io::Result<TcpListener> {
+    let socket = if addr.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+    // Like `TcpListener::bind`, so a restarted server can listen on the port straight away.
+    socket.set_reuseaddr(true)?;
+    socket.set_keepalive(keepalive)?;
+    socket.bind(addr)?;
+    socket.listen(backlog)
+}
+
+/// Initial size of each connection's buffer of unparsed input.
+const READ_BUFFER_SIZE: usize = 4096;
+
+/// How long in-flight connections get to finish once shutdown has started.
+const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+async fn shutdown_signal() {
+    let mut terminate = signal(SignalKind::terminate()).expect("Unable to listen for SIGTERM");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
 
-    let opts = Arc::new(load_opts(opts));
-    let db = Arc::new(Mutex::new(HashMap::new()));
+/// Every socket the server accepts connections on.
+#[derive(Debug, Default)]
+struct Listeners {
+    tcp: Vec<TcpListener>,
+    /// The Unix socket and its path, which is used as the address of its clients.
+    unix: Option<(UnixListener, Arc<Path>)>,
+}
 
+/// A connection accepted by one of the `Listeners`.
+enum Socket {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl Listeners {
+    /// Accept a connection from whichever of the listeners has one first.
+    async fn accept(&self) -> std::io::Result<(Socket, ClientAddr)> {
+        std::future::poll_fn(|cx| {
+            for listener in &self.tcp {
+                if let Poll::Ready(accepted) = listener.poll_accept(cx) {
+                    return Poll::Ready(
+                        accepted.map(|(socket, addr)| (Socket::Tcp(socket), addr.into())),
+                    );
+                }
+            }
+            if let Some((listener, path)) = &self.unix {
+                if let Poll::Ready(accepted) = listener.poll_accept(cx) {
+                    return Poll::Ready(accepted.map(|(socket, _)| {
+                        (Socket::Unix(socket), ClientAddr::Unix(path.clone()))
+                    }));
+                }
+            }
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+/// Accept connections until the server is shut down, then wait for open connections to finish.
+async fn serve(listeners: Listeners, server: Arc<Server>) -> anyhow::Result<()> {
+    let mut shutdown = server.shutdown.subscribe();
+    let mut connections = JoinSet::new();
+    let slots = Arc::new(Semaphore::new(server.maxclients()));
     loop {
-        let (socket, _) = listener.accept().await?;
-        let db = db.clone();
-        let opts = opts.clone();
-        tokio::spawn(async move { process(socket, db, opts).await });
+        tokio::select! {
+            accepted = listeners.accept() => match accepted? {
+                (Socket::Tcp(socket), addr) => {
+                    spawn_connection(&mut connections, &slots, socket, addr, &server)
+                }
+                (Socket::Unix(socket), addr) => {
+                    spawn_connection(&mut connections, &slots, socket, addr, &server)
+                }
+            },
+            // Reap finished connections so they don't accumulate in the set.
+            Some(_) = connections.join_next(), if !connections.is_empty() => {}
+            _ = shutdown.wait_for(|&stop| stop) => break,
+        }
     }
+    drop(listeners);
+
+    let drain = async { while connections.join_next().await.is_some() {} };
+    if tokio::time::timeout(SHUTDOWN_GRACE_PERIOD, drain)
+        .await
+        .is_err()
+    {
+        eprintln!("Timed out waiting for connections to close");
+    }
+    Ok(())
+}
+
+/// Serve a newly accepted connection, unless `maxclients` are already being served.
+fn spawn_connection<S>(
+    connections: &mut JoinSet<()>,
+    slots: &Arc<Semaphore>,
+    mut socket: S,
+    addr: ClientAddr,
+    server: &Arc<Server>,
+) where
+    S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    let Ok(permit) = slots.clone().try_acquire_owned() else {
+        eprintln!("Refusing client {}: max number of clients reached", addr);
+        connections.spawn(async move {
+            let err = parse::SimpleError::from("ERR max number of clients reached".to_owned());
+            let _ = socket.write_all(&err.serialise()).await;
+        });
+        return;
+    };
+    let server = server.clone();
+    connections.spawn(async move {
+        process(socket, addr, server).await;
+        drop(permit);
+    });
 }
 
-async fn process(
-    mut stream: TcpStream,
-    db: Arc<Mutex<HashMap<String, DbValue>>>,
-    opts: Arc<HashMap<String, OptValue>>,
-) {
-    let mut buf = [0; 512];
+/// State shared between every connection.
+pub(crate) struct Server {
+    pub(crate) db: Db,
+    pub(crate) opts: HashMap<String, OptValue>,
+    pub(crate) clients: ClientRegistry,
+    pub(crate) pubsub: PubSub,
+    pub(crate) key_waiters: KeyWaiters,
+    pub(crate) monitors: Monitors,
+    pub(crate) slowlog: SlowLog,
+    pub(crate) replication: Replication,
+    pub(crate) aof: Aof,
+    /// Reported by `CLUSTER MYID`, generated at startup.
+    pub(crate) node_id: String,
+    /// UNIX time of the last successful RDB save, in seconds.
+    pub(crate) last_save: AtomicU64,
+    pub(crate) bgsave_in_progress: AtomicBool,
+    /// Whether the active expiry cycle runs, toggled by `DEBUG SET-ACTIVE-EXPIRE`.
+    pub(crate) active_expire: AtomicBool,
+    /// Set to `true` to stop accepting connections and close the open ones.
+    pub(crate) shutdown: watch::Sender<bool>,
+}
+
+impl Server {
+    pub(crate) fn new(opts: HashMap<String, OptValue>) -> Self {
+        Server {
+            db: Db::default(),
+            opts,
+            clients: ClientRegistry::default(),
+            pubsub: PubSub::default(),
+            key_waiters: KeyWaiters::default(),
+            monitors: Monitors::default(),
+            slowlog: SlowLog::default(),
+            replication: Replication::default(),
+            aof: Aof::default(),
+            node_id: replication::random_id(),
+            last_save: AtomicU64::new(save::unix_time()),
+            bgsave_in_progress: AtomicBool::new(false),
+            active_expire: AtomicBool::new(true),
+            shutdown: watch::channel(false).0,
+        }
+    }
+
+    /// The port clients connect to, from the `port` option.
+    pub(crate) fn port(&self) -> u16 {
+        match self.opts.get("port") {
+            Some(OptValue::UInt(port)) => *port as u16,
+            _ => DEFAULT_PORT,
+        }
+    }
+
+    /// The largest bulk string clients may send, from the `proto-max-bulk-len` option.
+    pub(crate) fn proto_max_bulk_len(&self) -> u64 {
+        match self.opts.get("proto-max-bulk-len") {
+            Some(OptValue::UInt(len)) => *len,
+            _ => parse::DEFAULT_MAX_BULK_LEN,
+        }
+    }
+
+    /// How many elements a reply may hold before it is streamed, from the `streamed-reply-len`
+    /// option.
+    pub(crate) fn streamed_reply_len(&self) -> u64 {
+        match self.opts.get("streamed-reply-len") {
+            Some(OptValue::UInt(len)) => *len,
+            _ => parse::DEFAULT_STREAMED_REPLY_LEN,
+        }
+    }
+
+    /// The memory limit from the `maxmemory` option, or 0 for no limit.
+    pub(crate) fn maxmemory(&self) -> usize {
+        match self.opts.get("maxmemory") {
+            Some(OptValue::UInt(bytes)) => *bytes as usize,
+            _ => 0,
+        }
+    }
+
+    pub(crate) fn maxmemory_policy(&self) -> EvictionPolicy {
+        self.opts
+            .get("maxmemory-policy")
+            .and_then(OptValue::as_str)
+            .and_then(EvictionPolicy::from_name)
+            .unwrap_or(EvictionPolicy::NoEviction)
+    }
+
+    /// The most connections served at once, from the `maxclients` option.
+    pub(crate) fn maxclients(&self) -> usize {
+        match self.opts.get("maxclients") {
+            Some(OptValue::UInt(max)) => *max as usize,
+            _ => DEFAULT_MAX_CLIENTS as usize,
+        }
+    }
+
+    /// Which keyspace events to publish, parsed from the `notify-keyspace-events` option.
+    pub(crate) fn keyspace_events(&self) -> u8 {
+        self.opts
+            .get("notify-keyspace-events")
+            .and_then(OptValue::as_str)
+            .and_then(pubsub::parse_keyspace_events)
+            .unwrap_or(0)
+    }
+
+    /// How long a command must take to be logged as slow, from the
+    /// `slowlog-log-slower-than` option, or `None` if nothing is logged.
+    pub(crate) fn slowlog_log_slower_than(&self) -> Option<Duration> {
+        match self.opts.get("slowlog-log-slower-than") {
+            Some(OptValue::Int(micros)) => u64::try_from(*micros).ok().map(Duration::from_micros),
+            _ => Some(Duration::from_micros(
+                DEFAULT_SLOWLOG_LOG_SLOWER_THAN as u64,
+            )),
+        }
+    }
+
+    /// The most entries kept in the slow log, from the `slowlog-max-len` option.
+    pub(crate) fn slowlog_max_len(&self) -> usize {
+        match self.opts.get("slowlog-max-len") {
+            Some(OptValue::UInt(len)) => *len as usize,
+            _ => DEFAULT_SLOWLOG_MAX_LEN as usize,
+        }
+    }
+
+    /// Whether writes from clients are refused, because this is a replica and the
+    /// `replica-read-only` option is set.
+    pub(crate) fn is_read_only_replica(&self) -> bool {
+        self.replication.is_replica()
+            && self
+                .opts
+                .get("replica-read-only")
+                .and_then(OptValue::as_str)
+                != Some("no")
+    }
+
+    /// Where RDB snapshots are read from and written to, from the `dir` and `dbfilename` options.
+    pub(crate) fn rdb_path(&self) -> Option<PathBuf> {
+        match (
+            self.opts.get("dir"),
+            self.opts.get("dbfilename").and_then(OptValue::as_str),
+        ) {
+            (Some(OptValue::Path(dir, _)), Some(dbfilename)) => Some(dir.join(dbfilename)),
+            _ => None,
+        }
+    }
+}
+
+async fn process<S>(stream: S, addr: ClientAddr, server: Arc<Server>)
+where
+    S: AsyncRead + AsyncWrite + Send + Unpin,
+{
+    let (id, kill) = server.clients.register(addr.clone());
+    let (mut client, mut messages) = ClientState::new(id);
+    let mut shutdown = server.shutdown.subscribe();
+    let max_bulk_len = server.proto_max_bulk_len();
+    let (reader, writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(reader);
+    let mut writer = BufWriter::new(writer);
+    // Bytes read from the socket which don't yet form a complete command.
+    let mut pending = BytesMut::with_capacity(READ_BUFFER_SIZE);
     loop {
-        stream.readable().await.unwrap();
-        match stream.try_read(&mut buf) {
-            Ok(0) => break,
-            Ok(_n) => {
-                let (_, elem) = parse::parse_element(&buf).unwrap();
-                dbg!(&elem);
-                let cmd: Result<Command, CommandError> = elem.try_into();
-                let resp = match cmd {
-                    Ok(cmd) => cmd.execute(&db, &opts).serialise(),
-                    Err(_e) => {
-                        parse::SimpleError::from("Unable to parse input into command".to_owned())
-                            .serialise()
+        let message = tokio::select! {
+            read = reader.read_buf(&mut pending) => match read {
+                Ok(0) => break,
+                Ok(_) => None,
+                Err(e) => {
+                    eprintln!("Error reading from client {}: {}", addr, e);
+                    break;
+                }
+            },
+            Some(message) = messages.recv() => Some(message.serialise()),
+            Some(command) = recv_replica_feed(&mut client.replica_feed) => Some(command.to_vec()),
+            _ = kill.notified() => break,
+            _ = shutdown.wait_for(|&stop| stop) => break,
+        };
+        if let Some(message) = message {
+            let written = writer.write_all(&message).await;
+            if let Err(e) = written.and(writer.flush().await) {
+                eprintln!("Error writing to client {}: {}", addr, e);
+                break;
+            }
+            continue;
+        }
+
+        // Clients may pipeline several commands into one write, so execute every
+        // complete command in the buffer and flush the replies back together.
+        let mut quit = false;
+        let mut input = &pending[..];
+        let mut result = Ok(());
+        while !quit && result.is_ok() {
+            // Anything which isn't RESP is an inline command, as typed into telnet.
+            let inline = input.first().is_some_and(|&b| !parse::is_type_marker(b));
+            let parsed = if inline {
+                parse::parse_inline(input).map(|(rest, args)| (rest, RespElement::Array(args)))
+            } else {
+                parse::parse_element(input, max_bulk_len)
+            };
+            let (rest, elem) = match parsed {
+                Ok(parsed) => parsed,
+                // The rest of the command hasn't arrived yet.
+                Err(nom::Err::Incomplete(_)) => break,
+                // Like Redis, don't try to find where the next command starts after a
+                // malformed one, but give up on the connection.
+                Err(nom::Err::Error(e) | nom::Err::Failure(e)) => {
+                    let reason = match (inline, e.code) {
+                        (true, nom::error::ErrorKind::TooLarge) => "too big inline request",
+                        (true, _) => "unbalanced quotes in request",
+                        (false, nom::error::ErrorKind::Count) => "invalid multibulk length",
+                        (false, _) => "invalid bulk length",
+                    };
+                    result = writer
+                        .write_all(&protocol_error(&addr, reason.to_owned()))
+                        .await;
+                    quit = true;
+                    break;
+                }
+            };
+            // Like Redis, silently skip empty requests such as blank lines.
+            if matches!(&elem, RespElement::Array(args) if args.is_empty()) {
+                input = rest;
+                continue;
+            }
+            // Replicas are sent write commands in RESP, exactly as clients sent them.
+            let raw = if inline {
+                Cow::Owned(elem.serialise())
+            } else {
+                Cow::Borrowed(&input[..input.len() - rest.len()])
+            };
+            input = rest;
+            // Only copy the command for monitors when there are any.
+            let monitored = server.monitors.is_active().then(|| elem.clone());
+            // Only name the command for the error when it might be rejected.
+            let subscribed_name =
+                (!client.channels.is_empty() && client.resp_version == 2).then(|| match &elem {
+                    RespElement::Array(args) => match args.first() {
+                        Some(RespElement::BulkString(name)) => name.as_ref().to_lowercase(),
+                        _ => String::new(),
+                    },
+                    _ => String::new(),
+                });
+            let cmd: Result<Command, CommandError> = elem.try_into();
+            if let (Some(RespElement::Array(args)), Ok(cmd)) = (&monitored, &cmd) {
+                if !cmd.is_admin() {
+                    server.monitors.feed(&addr, args);
+                }
+            }
+            quit = matches!(cmd, Ok(Command::Quit));
+            let is_write = cmd.as_ref().is_ok_and(Command::is_write);
+            let can_block = cmd.as_ref().is_ok_and(Command::can_block);
+            // Relative expiries are made absolute before executing, and generated IDs are
+            // taken from the reply after, so that replicas and the AOF are sent what the
+            // command actually did.
+            let (cmd, rewritten) = match cmd {
+                Ok(cmd) if is_write => {
+                    let (cmd, propagation) = cmd.rewrite_for_propagation();
+                    (Ok(cmd), propagation)
+                }
+                cmd => (cmd, None),
+            };
+            let started = Instant::now();
+            let replies = match cmd {
+                Ok(cmd) if subscribed_name.is_some() && !cmd.allowed_while_subscribed() => {
+                    vec![RespElement::SimpleError(parse::SimpleError::from(format!(
+                        "ERR Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context",
+                        subscribed_name.unwrap_or_default()
+                    )))]
+                }
+                // Writes from the master are applied by `replication::replicate` instead.
+                Ok(_) if is_write && server.is_read_only_replica() => {
+                    vec![RespElement::SimpleError(parse::SimpleError::from(
+                        "READONLY You can't write against a read only replica.".to_owned(),
+                    ))]
+                }
+                // Blocking commands, and writes paused by a failover, can wait indefinitely,
+                // so still close the connection if it's killed or the server shuts down in
+                // the meantime.
+                Ok(cmd) => tokio::select! {
+                    replies = async {
+                        if is_write {
+                            server.replication.writes_resumed().await;
+                        }
+                        cmd.execute_blocking(&server, &mut client).await
+                    } => replies,
+                    _ = kill.notified() => {
+                        quit = true;
+                        break;
                     }
-                };
-                stream.write_all(&resp).await.unwrap();
+                    _ = shutdown.wait_for(|&stop| stop) => {
+                        quit = true;
+                        break;
+                    }
+                },
+                Err(e) => vec![RespElement::SimpleError(e.to_simple_error())],
+            };
+            let elapsed = started.elapsed();
+            if !can_block
+                && server
+                    .slowlog_log_slower_than()
+                    .is_some_and(|max| elapsed > max)
+            {
+                // Parse the command again, rather than copying every command in case it's slow.
+                if let Ok((_, RespElement::Array(args))) = parse::parse_element(&raw, u64::MAX) {
+                    server
+                        .slowlog
+                        .record(&args, elapsed, &addr, server.slowlog_max_len());
+                }
+            }
+            if is_write && !matches!(replies.first(), Some(RespElement::SimpleError(_))) {
+                let rewritten = rewritten.map(|propagation| propagation.serialise(replies.first()));
+                let propagated = rewritten.as_deref().unwrap_or(&raw);
+                server.replication.propagate(propagated);
+                if let Err(e) = server.aof.append(propagated) {
+                    eprintln!("Unable to write to the append-only file: {}", e);
+                }
+            }
+            for reply in replies {
+                result =
+                    parse::write_element(&mut writer, &reply, server.streamed_reply_len()).await;
+                if result.is_err() {
+                    break;
+                }
             }
-            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => continue,
-            Err(e) => panic!("{}", e),
         }
+        let consumed = pending.len() - input.len();
+        pending.advance(consumed);
+
+        if let Err(e) = result.and(writer.flush().await) {
+            eprintln!("Error writing to client {}: {}", addr, e);
+            break;
+        }
+        if quit {
+            break;
+        }
+    }
+    for channel in &client.channels {
+        server.pubsub.unsubscribe(channel, id);
     }
+    if client.monitoring {
+        server.monitors.remove(id);
+    }
+    if client.replica_feed.is_some() {
+        server.replication.detach_replica(id);
+    }
+    server.clients.unregister(id);
+}
+
+/// Wait for the next command to propagate to a replica, or forever if the client isn't one.
+async fn recv_replica_feed(feed: &mut Option<mpsc::UnboundedReceiver<Bytes>>) -> Option<Bytes> {
+    match feed {
+        Some(feed) => feed.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// The reply sent before closing a connection whose input can't be parsed.
+fn protocol_error(addr: &ClientAddr, reason: String) -> Vec<u8> {
+    eprintln!("Protocol error from client {}: {}", addr, reason);
+    parse::SimpleError::from(format!("ERR Protocol error: {}", reason)).serialise()
 }
 
-enum OptValue {
-    String(String),
-    UInt(u16),
-    Path(PathBuf),
+/// A configuration value, kept so that `CONFIG GET` can reply without copying it.
+pub(crate) enum OptValue {
+    String(Bytes),
+    UInt(u64),
+    Int(i64),
+    /// A path along with its exact bytes, which needn't be valid UTF-8.
+    Path(PathBuf, Bytes),
+}
+
+impl OptValue {
+    pub(crate) fn path(path: PathBuf) -> Self {
+        let bytes = Bytes::copy_from_slice(path.as_os_str().as_encoded_bytes());
+        OptValue::Path(path, bytes)
+    }
+
+    /// The value as text, if it's a string.
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            OptValue::String(s) => std::str::from_utf8(s).ok(),
+            _ => None,
+        }
+    }
 }
 
 fn load_opts(opts: Opts) -> HashMap<String, OptValue> {
     let mut map = HashMap::new();
-    map.insert("port".to_owned(), OptValue::UInt(opts.port));
-    map.insert("dir".to_owned(), OptValue::Path(opts.dir));
-    map.insert("dbfilename".to_owned(), OptValue::String(opts.dbfilename));
+    map.insert("port".to_owned(), OptValue::UInt(opts.port.into()));
+    let bind: Vec<String> = opts.bind.iter().map(IpAddr::to_string).collect();
+    map.insert("bind".to_owned(), OptValue::String(bind.join(" ").into()));
+    map.insert(
+        "tcp-keepalive".to_owned(),
+        OptValue::String(if opts.tcp_keepalive { "on" } else { "0" }.into()),
+    );
+    map.insert(
+        "tcp-backlog".to_owned(),
+        OptValue::UInt(opts.tcp_backlog.into()),
+    );
+    map.insert("dir".to_owned(), OptValue::path(opts.dir));
+    map.insert(
+        "appendonly".to_owned(),
+        OptValue::String(if opts.appendonly { "yes" } else { "no" }.into()),
+    );
+    map.insert(
+        "dbfilename".to_owned(),
+        OptValue::String(opts.dbfilename.into()),
+    );
+    map.insert(
+        "proto-max-bulk-len".to_owned(),
+        OptValue::UInt(opts.proto_max_bulk_len),
+    );
+    map.insert(
+        "streamed-reply-len".to_owned(),
+        OptValue::UInt(opts.streamed_reply_len),
+    );
+    if let Some(path) = opts.unixsocket {
+        map.insert("unixsocket".to_owned(), OptValue::path(path));
+    }
+    map.insert("maxmemory".to_owned(), OptValue::UInt(opts.maxmemory));
+    map.insert("maxclients".to_owned(), OptValue::UInt(opts.maxclients));
+    map.insert(
+        "notify-keyspace-events".to_owned(),
+        OptValue::String(opts.notify_keyspace_events.into()),
+    );
+    map.insert(
+        "maxmemory-policy".to_owned(),
+        OptValue::String(opts.maxmemory_policy.into()),
+    );
+    map.insert(
+        "slowlog-log-slower-than".to_owned(),
+        OptValue::Int(opts.slowlog_log_slower_than),
+    );
+    map.insert(
+        "slowlog-max-len".to_owned(),
+        OptValue::UInt(opts.slowlog_max_len),
+    );
+    map.insert(
+        "replica-read-only".to_owned(),
+        OptValue::String(if opts.replica_read_only { "yes" } else { "no" }.into()),
+    );
     map
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::test_util::execute;
+    use rstest::rstest;
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt};
+
+    /// Spawn a server on an ephemeral port, returning the address to connect to.
+    async fn spawn_server(server: Arc<Server>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let listeners = Listeners {
+            tcp: vec![listener],
+            ..Default::default()
+        };
+        tokio::spawn(serve(listeners, server));
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_quit_closes_connection() {
+        let addr = spawn_server(Arc::new(Server::new(HashMap::new()))).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        stream.write_all(b"*1\r\n$4\r\nQUIT\r\n").await.unwrap();
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"+OK\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_bind_to_every_interface() {
+        use std::net::Ipv6Addr;
+
+        let opts = Opts::parse_from(["redis", "--bind", "0.0.0.0", "::1", "--port", "0"]);
+        assert_eq!(
+            opts.bind,
+            [
+                IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                IpAddr::V6(Ipv6Addr::LOCALHOST)
+            ]
+        );
+
+        let listener = TcpListener::bind(SocketAddr::new(opts.bind[0], 0))
+            .await
+            .unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let listeners = Listeners {
+            tcp: vec![listener],
+            ..Default::default()
+        };
+        tokio::spawn(serve(listeners, Arc::new(Server::new(load_opts(opts)))));
+
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        stream.write_all(b"*1\r\n$4\r\nPING\r\n").await.unwrap();
+        let mut buf = [0; 7];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"+PONG\r\n");
+    }
+
+    #[rstest]
+    #[case::default(&[], false)]
+    #[case::separate(&["--appendonly", "yes"], true)]
+    #[case::equals(&["--appendonly=yes"], true)]
+    #[case::no(&["--appendonly", "no"], false)]
+    fn test_appendonly_opt(#[case] args: &[&str], #[case] expected: bool) {
+        let opts = Opts::try_parse_from(["redis"].iter().chain(args)).unwrap();
+        assert_eq!(opts.appendonly, expected);
+        assert!(Opts::try_parse_from(["redis", "--appendonly", "maybe"]).is_err());
+    }
+
+    #[rstest]
+    #[case::default(&[], true)]
+    #[case::separate(&["--replica-read-only", "no"], false)]
+    #[case::equals(&["--replica-read-only=no"], false)]
+    #[case::yes(&["--replica-read-only", "yes"], true)]
+    fn test_replica_read_only_opt(#[case] args: &[&str], #[case] expected: bool) {
+        let opts = Opts::try_parse_from(["redis"].iter().chain(args)).unwrap();
+        assert_eq!(opts.replica_read_only, expected);
+    }
+
+    #[tokio::test]
+    async fn test_tcp_keepalive_and_backlog() {
+        let opts = Opts::parse_from([
+            "redis",
+            "--tcp-keepalive",
+            "0",
+            "--tcp-backlog",
+            "128",
+            "--port",
+            "0",
+        ]);
+        assert_eq!((opts.tcp_keepalive, opts.tcp_backlog), (false, 128));
+        // The idle time before probing can't be applied, so isn't accepted.
+        assert!(Opts::try_parse_from(["redis", "--tcp-keepalive", "60"]).is_err());
+        assert!(Opts::parse_from(["redis"]).tcp_keepalive);
+
+        let listener = bind_tcp(SocketAddr::new(opts.bind[0], 0), true, opts.tcp_backlog).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let listeners = Listeners {
+            tcp: vec![listener],
+            ..Default::default()
+        };
+        tokio::spawn(serve(listeners, Arc::new(Server::new(load_opts(opts)))));
+
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        stream
+            .write_all(b"PING\r\nCONFIG GET tcp-keepalive tcp-backlog\r\n")
+            .await
+            .unwrap();
+        let expected =
+            b"+PONG\r\n*4\r\n$13\r\ntcp-keepalive\r\n$1\r\n0\r\n$11\r\ntcp-backlog\r\n:128\r\n";
+        let mut buf = [0; 62];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, expected);
+    }
+
+    #[tokio::test]
+    async fn test_unix_socket() {
+        let path = std::env::temp_dir().join(format!("redis-test-{}.sock", std::process::id()));
+        let listeners = Listeners {
+            unix: Some((
+                UnixListener::bind(&path).unwrap(),
+                Arc::from(path.as_path()),
+            )),
+            ..Default::default()
+        };
+        let server = Arc::new(Server::new(HashMap::new()));
+        tokio::spawn(serve(listeners, server.clone()));
+
+        let mut stream = UnixStream::connect(&path).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        stream.write_all(b"*1\r\n$4\r\nPING\r\n").await.unwrap();
+        let mut buf = [0; 7];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"+PONG\r\n");
+        assert!(server
+            .clients
+            .list()
+            .contains(&format!("addr={}:0", path.display())));
+    }
+
+    #[tokio::test]
+    async fn test_pipelined_commands() {
+        let addr = spawn_server(Arc::new(Server::new(HashMap::new()))).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        stream
+            .write_all(b"*1\r\n$4\r\nPING\r\n*1\r\n$4\r\nPING\r\n*1\r\n$4\r\nQUIT\r\n")
+            .await
+            .unwrap();
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"+PONG\r\n+PONG\r\n+OK\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_pipelined_replies_flushed_without_closing() {
+        let addr = spawn_server(Arc::new(Server::new(HashMap::new()))).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        let commands = b"*2\r\n$4\r\nECHO\r\n$5\r\nhello\r\n".repeat(100);
+        stream.write_all(&commands).await.unwrap();
+        let expected = b"$5\r\nhello\r\n".repeat(100);
+        let mut buf = vec![0; expected.len()];
+        tokio::time::timeout(Duration::from_secs(5), stream.read_exact(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(buf, expected);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_stops_accept_loop() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let listeners = Listeners {
+            tcp: vec![listener],
+            ..Default::default()
+        };
+        let serving = tokio::spawn(serve(listeners, server.clone()));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"*1\r\n$4\r\nPING\r\n").await.unwrap();
+        let mut buf = [0; 7];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"+PONG\r\n");
+
+        server.shutdown.send_replace(true);
+        tokio::time::timeout(Duration::from_secs(1), serving)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+
+        // The open connection is closed and no new ones are accepted.
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await.unwrap();
+        assert!(buf.is_empty());
+        assert!(TcpStream::connect(addr).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_garbage_input_closes_only_that_connection() {
+        let addr = spawn_server(Arc::new(Server::new(HashMap::new()))).await;
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"\"garbage\r\n").await.unwrap();
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(
+            buf,
+            b"-ERR Protocol error: unbalanced quotes in request\r\n"
+        );
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"*1\r\n$4\r\nPING\r\n").await.unwrap();
+        let mut buf = [0; 7];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"+PONG\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_inline_commands() {
+        let addr = spawn_server(Arc::new(Server::new(HashMap::new()))).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        stream
+            .write_all(b"PING\r\n\r\nSET foo bar\r\nGET foo\nQUIT\r\n")
+            .await
+            .unwrap();
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"+PONG\r\n+OK\r\n$3\r\nbar\r\n+OK\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_wrong_arity_error() {
+        let addr = spawn_server(Arc::new(Server::new(HashMap::new()))).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        stream
+            .write_all(b"*1\r\n$3\r\nGET\r\n*2\r\n$6\r\nCONFIG\r\n$3\r\nGET\r\nQUIT\r\n")
+            .await
+            .unwrap();
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(
+            buf,
+            b"-ERR wrong number of arguments for 'get' command\r\n\
+              -ERR wrong number of arguments for 'config|get' command\r\n\
+              +OK\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_command_errors() {
+        let addr = spawn_server(Arc::new(Server::new(HashMap::new()))).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        stream
+            .write_all(b"FOO bar\r\nSET foo bar NX XX\r\nCLIENT FOO\r\nQUIT\r\n")
+            .await
+            .unwrap();
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(
+            buf,
+            b"-ERR unknown command 'FOO'\r\n\
+              -ERR syntax error\r\n\
+              -ERR unknown subcommand 'FOO'. Try CLIENT HELP.\r\n\
+              +OK\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_maxclients_refuses_extra_connections() {
+        let mut opts = HashMap::new();
+        opts.insert("maxclients".to_owned(), OptValue::UInt(2));
+        let addr = spawn_server(Arc::new(Server::new(opts))).await;
+
+        let mut connected = Vec::new();
+        for _ in 0..2 {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream.write_all(b"PING\r\n").await.unwrap();
+            let mut buf = [0; 7];
+            stream.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"+PONG\r\n");
+            connected.push(stream);
+        }
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"-ERR max number of clients reached\r\n");
+
+        // Closing a connection frees its slot.
+        drop(connected.pop());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"PING\r\n").await.unwrap();
+        let mut buf = [0; 7];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"+PONG\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_and_publish() {
+        let addr = spawn_server(Arc::new(Server::new(HashMap::new()))).await;
+        let mut subscriber = TcpStream::connect(addr).await.unwrap();
+        subscriber.write_all(b"SUBSCRIBE news\r\n").await.unwrap();
+        let expected = b"*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n";
+        let mut buf = vec![0; expected.len()];
+        subscriber.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, expected);
+
+        let mut publisher = TcpStream::connect(addr).await.unwrap();
+        publisher
+            .write_all(b"PUBLISH news hello\r\nQUIT\r\n")
+            .await
+            .unwrap();
+        let mut buf = Vec::new();
+        publisher.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b":1\r\n+OK\r\n");
+
+        let expected = b"*3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$5\r\nhello\r\n";
+        let mut buf = vec![0; expected.len()];
+        tokio::time::timeout(Duration::from_secs(5), subscriber.read_exact(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(buf, expected);
+    }
+
+    #[tokio::test]
+    async fn test_command_split_across_writes() {
+        let addr = spawn_server(Arc::new(Server::new(HashMap::new()))).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        stream
+            .write_all(b"*2\r\n$4\r\nECHO\r\n$5\r\nhel")
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        stream
+            .write_all(b"lo\r\n*1\r\n$4\r\nQUIT\r\n")
+            .await
+            .unwrap();
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"$5\r\nhello\r\n+OK\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_mid_command() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        let addr = spawn_server(server.clone()).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        stream
+            .write_all(b"*2\r\n$4\r\nECHO\r\n$5\r\nhe")
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(server.clients.list().lines().count(), 1);
+        drop(stream);
+
+        // The connection should notice the EOF and finish, rather than waiting for the
+        // rest of the command forever.
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !server.clients.list().is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_bulk_string_over_proto_max_bulk_len() {
+        let mut opts = HashMap::new();
+        opts.insert("proto-max-bulk-len".to_owned(), OptValue::UInt(16));
+        let addr = spawn_server(Arc::new(Server::new(opts))).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        stream
+            .write_all(b"*2\r\n$4\r\nECHO\r\n$999999999999\r\n")
+            .await
+            .unwrap();
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"-ERR Protocol error: invalid bulk length\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_huge_multibulk_length() {
+        let addr = spawn_server(Arc::new(Server::new(HashMap::new()))).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        stream.write_all(b"*4294967295\r\n").await.unwrap();
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"-ERR Protocol error: invalid multibulk length\r\n");
+    }
+
+    #[rstest]
+    #[case::wrong_length(b"*1\r\n$3\r\nPINGX\r\nPING\r\n")]
+    #[case::not_a_length(b"*1\r\n$abc\r\nPING\r\n")]
+    #[tokio::test]
+    async fn test_malformed_frame_closes_connection(#[case] input: &[u8]) {
+        let addr = spawn_server(Arc::new(Server::new(HashMap::new()))).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        stream.write_all(input).await.unwrap();
+        let mut buf = Vec::new();
+        tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(buf, b"-ERR Protocol error: invalid bulk length\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_writes_propagate_to_replica() {
+        let master = Arc::new(Server::new(HashMap::new()));
+        let addr = spawn_server(master.clone()).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"*3\r\n$3\r\nSET\r\n$6\r\nbefore\r\n$1\r\n1\r\n")
+            .await
+            .unwrap();
+        let mut buf = [0; 5];
+        stream.read_exact(&mut buf).await.unwrap();
+
+        let replica = Arc::new(Server::new(HashMap::new()));
+        replication::replicate_from(&replica, addr.ip().to_string(), addr.port());
+        let wait_for = |key: &'static str| {
+            let replica = replica.clone();
+            async move {
+                let synced = async {
+                    while replica.db.get_live(key).is_none() {
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                    }
+                };
+                tokio::time::timeout(Duration::from_secs(5), synced)
+                    .await
+                    .expect("Timed out waiting for the replica");
+            }
+        };
+        wait_for("before").await;
+
+        // Inline commands are propagated too, encoded as RESP.
+        stream.write_all(b"SET after 2\r\n").await.unwrap();
+        wait_for("after").await;
+        assert_eq!(replica.db.get_live("after").unwrap().value, "2".into());
+        assert!(master.replication.offset() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_read_only_replica_rejects_client_writes() {
+        let master = Arc::new(Server::new(HashMap::new()));
+        let master_addr = spawn_server(master).await;
+        let replica = Arc::new(Server::new(HashMap::new()));
+        let replica_addr = spawn_server(replica.clone()).await;
+        replication::replicate_from(&replica, master_addr.ip().to_string(), master_addr.port());
+
+        let mut client = TcpStream::connect(replica_addr).await.unwrap();
+        client.write_all(b"SET foo replica\r\n").await.unwrap();
+        let expected = b"-READONLY You can't write against a read only replica.\r\n";
+        let mut buf = vec![0; expected.len()];
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, expected);
+
+        let mut stream = TcpStream::connect(master_addr).await.unwrap();
+        stream.write_all(b"SET foo master\r\n").await.unwrap();
+        let synced = async {
+            while replica.db.get_live("foo").is_none() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), synced)
+            .await
+            .expect("Timed out waiting for the replica");
+        assert_eq!(replica.db.get_live("foo").unwrap().value, "master".into());
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_replica_ack() {
+        let master = Arc::new(Server::new(HashMap::new()));
+        let addr = spawn_server(master.clone()).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        // There are no writes for replicas to acknowledge yet.
+        stream.write_all(b"WAIT 1 100\r\n").await.unwrap();
+        let mut buf = [0; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b":0\r\n");
+
+        let replica = Arc::new(Server::new(HashMap::new()));
+        replication::replicate_from(&replica, addr.ip().to_string(), addr.port());
+        let attached = async {
+            while master.replication.acked_replicas(0) == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), attached)
+            .await
+            .expect("Timed out waiting for the replica");
+
+        stream
+            .write_all(b"SET foo bar\r\nWAIT 1 5000\r\n")
+            .await
+            .unwrap();
+        let mut buf = [0; 9];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"+OK\r\n:1\r\n");
+        assert_eq!(replica.db.get_live("foo").unwrap().value, "bar".into());
+    }
+
+    #[tokio::test]
+    async fn test_info_replication() {
+        let info = |server: &Arc<Server>| match execute(server, &[b"INFO", b"replication"]) {
+            RespElement::BulkString(info) => info.as_ref().to_owned(),
+            reply => panic!("Expected a bulk string, got {:?}", reply),
+        };
+        let master = Arc::new(Server::new(HashMap::new()));
+        let addr = spawn_server(master.clone()).await;
+        assert!(info(&master).contains("role:master\r\nconnected_slaves:0\r\n"));
+
+        let mut opts = HashMap::new();
+        opts.insert("port".to_owned(), OptValue::UInt(6380));
+        let replica = Arc::new(Server::new(opts));
+        replication::replicate_from(&replica, addr.ip().to_string(), addr.port());
+        let linked = async {
+            while !info(&replica).contains("master_link_status:up") {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), linked)
+            .await
+            .expect("Timed out waiting for the replica");
+
+        let master_info = info(&master);
+        assert!(master_info.contains("connected_slaves:1\r\n"));
+        assert!(master_info.contains("slave0:ip=127.0.0.1,port=6380,state=online,"));
+        let replica_info = info(&replica);
+        assert!(replica_info.contains("role:slave\r\n"));
+        assert!(replica_info.contains(&format!("master_port:{}\r\n", addr.port())));
+    }
+
+    #[tokio::test]
+    async fn test_waitaof() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        let addr = spawn_server(server.clone()).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        stream.write_all(b"WAITAOF 1 0 0\r\n").await.unwrap();
+        let mut reader = BufReader::new(&mut stream);
+        let mut line = String::new();
+        reader.read_line(&mut line).await.unwrap();
+        assert!(line.starts_with("-ERR WAITAOF cannot be used when numlocal is set"));
+
+        let path = std::env::temp_dir().join(format!("redis-waitaof-{}.aof", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        server.aof.open(&path).unwrap();
+        stream
+            .write_all(b"SET foo bar\r\nWAITAOF 1 0 0\r\n")
+            .await
+            .unwrap();
+        let mut buf = [0; 17];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"+OK\r\n*2\r\n:1\r\n:0\r\n");
+
+        let contents = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(contents, b"*3\r\n$3\r\nSET\r\n$3\r\nfoo\r\n$3\r\nbar\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_relative_expiry_propagated_as_pxat() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        let path = std::env::temp_dir().join(format!("redis-pxat-{}.aof", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        server.aof.open(&path).unwrap();
+        let addr = spawn_server(server.clone()).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        let unix_millis = || {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as u64
+        };
+        let before = unix_millis();
+        stream.write_all(b"SET foo bar EX 100\r\n").await.unwrap();
+        let mut buf = [0; 5];
+        stream.read_exact(&mut buf).await.unwrap();
+        let after = unix_millis();
+
+        let contents = String::from_utf8(std::fs::read(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let prefix = "*5\r\n$3\r\nSET\r\n$3\r\nfoo\r\n$3\r\nbar\r\n$4\r\nPXAT\r\n";
+        assert!(contents.starts_with(prefix), "{contents:?}");
+        let at: u64 = contents[prefix.len()..]
+            .lines()
+            .nth(1)
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((before + 100_000..=after + 100_000).contains(&at));
+    }
+
+    #[tokio::test]
+    async fn test_xadd_generated_id_propagated() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        let path = std::env::temp_dir().join(format!("redis-xadd-{}.aof", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        server.aof.open(&path).unwrap();
+        let addr = spawn_server(server.clone()).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        stream
+            .write_all(b"XADD s 1-* f v\r\nXADD s * g w\r\nXADD s 0-1 h x\r\nQUIT\r\n")
+            .await
+            .unwrap();
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await.unwrap();
+        let replies = String::from_utf8(buf).unwrap();
+        let ids: Vec<&str> = replies.lines().skip(1).step_by(2).take(2).collect();
+        assert_eq!(ids[0], "1-0");
+
+        let contents = String::from_utf8(std::fs::read(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // The rejected XADD isn't propagated at all.
+        let expected: String = [(ids[0], "f", "v"), (ids[1], "g", "w")]
+            .iter()
+            .map(|(id, field, value)| {
+                format!(
+                    "*5\r\n$4\r\nXADD\r\n$1\r\ns\r\n${}\r\n{id}\r\n$1\r\n{field}\r\n$1\r\n{value}\r\n",
+                    id.len()
+                )
+            })
+            .collect();
+        assert_eq!(contents, expected);
+    }
+
+    #[tokio::test]
+    async fn test_subscribed_commands_are_restricted() {
+        let addr = spawn_server(Arc::new(Server::new(HashMap::new()))).await;
+        let mut resp2 = TcpStream::connect(addr).await.unwrap();
+        resp2
+            .write_all(b"SUBSCRIBE news\r\nGET foo\r\nPING\r\nQUIT\r\n")
+            .await
+            .unwrap();
+        let mut buf = Vec::new();
+        resp2.read_to_end(&mut buf).await.unwrap();
+        let replies = String::from_utf8(buf).unwrap();
+        assert!(replies.contains(
+            "-ERR Can't execute 'get': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context\r\n"
+        ));
+        assert!(
+            replies.ends_with("*2\r\n$4\r\npong\r\n$0\r\n\r\n+OK\r\n"),
+            "{replies:?}"
+        );
+
+        let mut resp3 = TcpStream::connect(addr).await.unwrap();
+        resp3
+            .write_all(b"HELLO 3\r\nSUBSCRIBE news\r\nGET foo\r\nQUIT\r\n")
+            .await
+            .unwrap();
+        let mut buf = Vec::new();
+        resp3.read_to_end(&mut buf).await.unwrap();
+        let replies = String::from_utf8(buf).unwrap();
+        assert!(!replies.contains("-ERR"), "{replies:?}");
+    }
+
+    #[tokio::test]
+    async fn test_global_debug_sleep_stalls_other_clients() {
+        let addr = spawn_server(Arc::new(Server::new(HashMap::new()))).await;
+        for (command, stalled) in [
+            (&b"DEBUG SLEEP 0.5\r\n"[..], false),
+            (b"DEBUG SLEEP 0.5 GLOBAL\r\n", true),
+        ] {
+            let mut sleeper = TcpStream::connect(addr).await.unwrap();
+            sleeper.write_all(command).await.unwrap();
+            // Give the sleep time to start.
+            tokio::time::sleep(Duration::from_millis(100)).await;
+
+            let mut client = TcpStream::connect(addr).await.unwrap();
+            let started = Instant::now();
+            client.write_all(b"SET foo bar\r\n").await.unwrap();
+            let mut buf = [0; 5];
+            client.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"+OK\r\n");
+            let elapsed = started.elapsed();
+            assert_eq!(elapsed > Duration::from_millis(200), stalled, "{elapsed:?}");
+
+            let mut buf = [0; 5];
+            sleeper.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"+OK\r\n");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_monitor_sees_other_connections() {
+        let addr = spawn_server(Arc::new(Server::new(HashMap::new()))).await;
+        let mut monitor = TcpStream::connect(addr).await.unwrap();
+        monitor.write_all(b"MONITOR\r\n").await.unwrap();
+        let mut buf = [0; 5];
+        monitor.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"+OK\r\n");
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client
+            .write_all(b"DEBUG SLEEP 0\r\nSET foo bar\r\nQUIT\r\n")
+            .await
+            .unwrap();
+        let mut buf = Vec::new();
+        client.read_to_end(&mut buf).await.unwrap();
+
+        let mut reader = BufReader::new(monitor);
+        let mut line = String::new();
+        tokio::time::timeout(Duration::from_secs(5), reader.read_line(&mut line))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(line.starts_with('+'), "{line:?}");
+        // DEBUG is an admin command, so the SET is the first one shown.
+        assert!(line.ends_with("] \"SET\" \"foo\" \"bar\"\r\n"), "{line:?}");
+    }
+
+    #[tokio::test]
+    async fn test_slowlog_records_slow_commands() {
+        let mut opts = HashMap::new();
+        opts.insert("slowlog-log-slower-than".to_owned(), OptValue::Int(50_000));
+        let addr = spawn_server(Arc::new(Server::new(opts))).await;
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client
+            .write_all(b"PING\r\nDEBUG SLEEP 0.1\r\nSLOWLOG LEN\r\nSLOWLOG GET\r\nQUIT\r\n")
+            .await
+            .unwrap();
+        let mut buf = Vec::new();
+        client.read_to_end(&mut buf).await.unwrap();
+
+        let mut input = buf.as_slice();
+        let mut replies = Vec::new();
+        while !input.is_empty() {
+            let (rest, reply) = parse::parse_element(input, u64::MAX).unwrap();
+            replies.push(reply);
+            input = rest;
+        }
+        assert_eq!(replies[2], RespElement::Integer(1));
+        let RespElement::Array(entries) = &replies[3] else {
+            panic!("Expected an array, got {:?}", replies[3]);
+        };
+        let [RespElement::Array(entry)] = entries.as_slice() else {
+            panic!("Expected one entry, got {:?}", entries);
+        };
+        let RespElement::Integer(duration) = entry[2] else {
+            panic!("Expected a duration, got {:?}", entry[2]);
+        };
+        assert!(duration >= 100_000);
+        assert_eq!(
+            entry[3],
+            RespElement::Array(vec![
+                RespElement::BulkString("DEBUG".into()),
+                RespElement::BulkString("SLEEP".into()),
+                RespElement::BulkString("0.1".into()),
+            ])
+        );
+    }
+}
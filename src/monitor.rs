@@ -0,0 +1,116 @@
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    sync::RwLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use tokio::sync::mpsc;
+
+use crate::{
+    clients::{ClientAddr, ClientState},
+    parse::{RespElement, SimpleString},
+};
+
+/// Every connection which has run `MONITOR`, by client id.
+#[derive(Debug, Default)]
+pub(crate) struct Monitors {
+    clients: RwLock<HashMap<u64, mpsc::UnboundedSender<RespElement>>>,
+}
+
+impl Monitors {
+    pub(crate) fn add(&self, client: &ClientState) {
+        self.clients
+            .write()
+            .unwrap()
+            .insert(client.id, client.messages.clone());
+    }
+
+    pub(crate) fn remove(&self, id: u64) {
+        self.clients.write().unwrap().remove(&id);
+    }
+
+    /// Whether any connection is monitoring, so commands need to be fed to them.
+    pub(crate) fn is_active(&self) -> bool {
+        !self.clients.read().unwrap().is_empty()
+    }
+
+    /// Send a command run by the client at `addr` to every monitor.
+    pub(crate) fn feed(&self, addr: &ClientAddr, args: &[RespElement]) {
+        let clients = self.clients.read().unwrap();
+        if clients.is_empty() {
+            return;
+        }
+        let line = RespElement::SimpleString(monitor_line(SystemTime::now(), addr, args));
+        for tx in clients.values() {
+            let _ = tx.send(line.clone());
+        }
+    }
+}
+
+/// Format a command like Redis does for monitors:
+/// `<timestamp> [<db> <addr>] "<command>" "<arg>" ...`.
+fn monitor_line(now: SystemTime, addr: &ClientAddr, args: &[RespElement]) -> SimpleString {
+    let since_epoch = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let mut line = format!(
+        "{}.{:06} [0 {}]",
+        since_epoch.as_secs(),
+        since_epoch.subsec_micros(),
+        addr
+    );
+    for arg in args {
+        line.push(' ');
+        match arg {
+            RespElement::BulkString(arg) => quote(&mut line, arg.as_ref()),
+            RespElement::Integer(i) => quote(&mut line, &i.to_string()),
+            arg => quote(&mut line, &format!("{:?}", arg)),
+        }
+    }
+    line.into()
+}
+
+/// Append `s` in double quotes, escaping anything which isn't printable.
+fn quote(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\x{:02x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_monitor_line() {
+        let now = UNIX_EPOCH + Duration::from_micros(1_339_518_083_107_412);
+        let args = [
+            RespElement::BulkString("SET".into()),
+            RespElement::BulkString("key".into()),
+            RespElement::BulkString("say \"hi\"\r\n".into()),
+        ];
+        assert_eq!(
+            monitor_line(
+                now,
+                &ClientAddr::Tcp("127.0.0.1:60866".parse().unwrap()),
+                &args
+            ),
+            SimpleString::from(
+                r#"1339518083.107412 [0 127.0.0.1:60866] "SET" "key" "say \"hi\"\r\n""#.to_owned()
+            )
+        );
+    }
+}
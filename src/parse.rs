@@ -1,20 +1,36 @@
 use bytes::Bytes;
 use nom::branch::alt;
-use nom::bytes::complete::{is_not, tag};
-use nom::character::complete::{crlf, i64 as i64_parser, u32 as u32_parser};
-use nom::combinator::map;
-use nom::IResult;
+use nom::bytes::complete::is_not;
+use nom::bytes::streaming::{tag, take};
+use nom::character::streaming::{crlf, i64 as i64_parser, u32 as u32_parser, u64 as u64_parser};
+use nom::combinator::{fail, map};
+use nom::{Err, IResult, Needed};
+use std::io;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// Default for `proto-max-bulk-len`, the largest bulk string a client may send.
+pub(crate) const DEFAULT_MAX_BULK_LEN: u64 = 512 * 1024 * 1024;
+/// Longest inline command accepted, as in Redis.
+const MAX_INLINE_LEN: usize = 64 * 1024;
+/// Most elements an array, push or map may declare, like the limit Redis puts on
+/// unauthenticated clients, so a header alone can't make the server run out of memory.
+const MAX_MULTIBULK_LEN: u32 = 1024 * 1024;
 
 pub(crate) trait RespSerialise {
     fn serialise(&self) -> Vec<u8>;
 }
 
+/// Text up to the end of the line, or of the input if the line hasn't fully arrived, in
+/// which case the `crlf` after it asks for more.
 fn parse_string(input: &[u8]) -> IResult<&[u8], String> {
     if input.is_empty() || input == b"\r\n" {
         Ok((input, "".to_owned()))
     } else {
-        let (input, s) = is_not("\r\n")(input)?;
-        Ok((input, std::str::from_utf8(s).unwrap().to_owned()))
+        let (rest, s) = is_not("\r\n")(input)?;
+        match std::str::from_utf8(s) {
+            Ok(s) => Ok((rest, s.to_owned())),
+            Err(_) => fail(input),
+        }
     }
 }
 
@@ -26,6 +42,7 @@ fn parse_string(input: &[u8]) -> IResult<&[u8], String> {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) struct SimpleString(String);
 
+#[allow(dead_code)]
 impl SimpleString {
     pub(crate) fn as_str(&self) -> &str {
         &self.0
@@ -67,6 +84,7 @@ fn parse_simple_string(input: &[u8]) -> IResult<&[u8], SimpleString> {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) struct SimpleError(String);
 
+#[allow(dead_code)]
 impl SimpleError {
     pub(crate) fn as_str(&self) -> &str {
         &self.0
@@ -118,55 +136,142 @@ impl RespSerialise for i64 {
 /// A bulk string represents a single binary string.
 /// The string can be of any size, but by default,
 /// Redis limits it to 512 MB (see the proto-max-bulk-len configuration directive).
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub(crate) struct BulkString(String);
+///
+/// Most commands treat their arguments as text, so that's how the string is usually
+/// read. Strings which aren't valid UTF-8, such as values written by BITOP or `DUMP`
+/// payloads, also keep their exact bytes for the commands which accept binary data.
+#[derive(Debug, Clone)]
+pub(crate) struct BulkString(Repr);
+
+#[derive(Debug, Clone)]
+enum Repr {
+    Text(String),
+    /// Bytes which are valid UTF-8, shared rather than copied into a `String`.
+    Utf8(Bytes),
+    /// Bytes which aren't valid UTF-8, along with their text with invalid sequences replaced.
+    Binary {
+        bytes: Bytes,
+        text: String,
+    },
+}
 
 impl BulkString {
     pub(crate) fn unwrap(self) -> String {
-        self.0
+        match self.0 {
+            Repr::Text(text) | Repr::Binary { text, .. } => text,
+            Repr::Utf8(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+        }
+    }
+
+    /// The exact bytes of the string, even if they aren't valid UTF-8.
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        match &self.0 {
+            Repr::Text(text) => text.as_bytes(),
+            Repr::Utf8(bytes) | Repr::Binary { bytes, .. } => bytes,
+        }
+    }
+
+    pub(crate) fn into_bytes(self) -> Bytes {
+        match self.0 {
+            Repr::Text(text) => text.into(),
+            Repr::Utf8(bytes) | Repr::Binary { bytes, .. } => bytes,
+        }
+    }
+}
+
+impl PartialEq for BulkString {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_bytes() == other.as_bytes()
     }
 }
 
+impl Eq for BulkString {}
+
 impl From<&str> for BulkString {
     fn from(s: &str) -> Self {
-        BulkString(s.to_owned())
+        s.to_owned().into()
     }
 }
 
 impl From<String> for BulkString {
     fn from(s: String) -> Self {
-        BulkString(s)
+        BulkString(Repr::Text(s))
     }
 }
 
 impl From<Bytes> for BulkString {
-    fn from(b: Bytes) -> Self {
-        BulkString(String::from_utf8(b.to_vec()).unwrap())
+    fn from(bytes: Bytes) -> Self {
+        match std::str::from_utf8(&bytes) {
+            Ok(_) => BulkString(Repr::Utf8(bytes)),
+            Err(_) => BulkString(Repr::Binary {
+                text: String::from_utf8_lossy(&bytes).into_owned(),
+                bytes,
+            }),
+        }
+    }
+}
+
+impl From<Vec<u8>> for BulkString {
+    fn from(b: Vec<u8>) -> Self {
+        Bytes::from(b).into()
     }
 }
 
 impl AsRef<str> for BulkString {
     fn as_ref(&self) -> &str {
-        &self.0
+        match &self.0 {
+            Repr::Text(text) | Repr::Binary { text, .. } => text,
+            Repr::Utf8(bytes) => {
+                std::str::from_utf8(bytes).expect("checked when the string was made")
+            }
+        }
     }
 }
 
 impl RespSerialise for BulkString {
     fn serialise(&self) -> Vec<u8> {
-        format!("${}\r\n{}\r\n", self.0.len(), self.0).into_bytes()
+        let bytes = self.as_bytes();
+        let mut out = format!("${}\r\n", bytes.len()).into_bytes();
+        out.extend_from_slice(bytes);
+        out.extend_from_slice(b"\r\n");
+        out
     }
 }
 
-fn parse_bulk_string(input: &[u8]) -> IResult<&[u8], BulkString> {
-    let (input, _) = tag(b"$")(input)?;
-    let (input, len) = u32_parser(input)?;
-    let (input, _) = crlf(input)?;
-    let (s, input) = input.split_at(len.try_into().unwrap());
+/// Parse a length-prefixed blob starting with `marker`, as used by bulk strings
+/// and the other RESP3 types with a binary payload.
+///
+/// Fails outright if the declared length exceeds `max_len`.
+fn parse_blob<'a>(input: &'a [u8], marker: &[u8], max_len: u64) -> IResult<&'a [u8], &'a [u8]> {
+    let (input, _) = tag(marker)(input)?;
+    let (input, len) = u64_parser(input)?;
     let (input, _) = crlf(input)?;
-    Ok((
-        input,
-        BulkString(std::str::from_utf8(s).unwrap().to_owned()),
-    ))
+    if len > max_len {
+        return Err(Err::Failure(nom::error::Error::new(
+            input,
+            nom::error::ErrorKind::TooLarge,
+        )));
+    }
+    let Ok(len) = usize::try_from(len) else {
+        return fail(input);
+    };
+    // The rest of the string may not have arrived yet.
+    if input.len() < len {
+        return Err(Err::Incomplete(Needed::new(len - input.len())));
+    }
+    let (rest, blob) = take(len)(input)?;
+    let (rest, _) = crlf(rest)?;
+    Ok((rest, blob))
+}
+
+fn parse_bulk_string(input: &[u8], max_len: u64) -> IResult<&[u8], BulkString> {
+    let (rest, s) = parse_blob(input, b"$", max_len)?;
+    // Arguments are mostly read as text, so keep them as one when possible.
+    let s = match std::str::from_utf8(s) {
+        Ok(text) => text.into(),
+        Err(_) => Bytes::copy_from_slice(s).into(),
+    };
+    Ok((rest, s))
 }
 
 /// Booleans
@@ -191,8 +296,13 @@ pub(crate) enum RespElement {
     BulkString(BulkString),
     Array(Vec<RespElement>),
     NullElement(NullBulkString),
+    NullArray(NullArray),
     Boolean(bool),
     Null,
+    Map(Vec<(RespElement, RespElement)>),
+    Verbatim { format: [u8; 3], data: Bytes },
+    Push(Vec<RespElement>),
+    BulkError(Bytes),
 }
 
 impl RespSerialise for RespElement {
@@ -204,21 +314,64 @@ impl RespSerialise for RespElement {
             RespElement::BulkString(bs) => bs.serialise(),
             RespElement::Array(a) => a.serialise(),
             RespElement::NullElement(n) => n.serialise(),
+            RespElement::NullArray(n) => n.serialise(),
             RespElement::Boolean(b) => b.serialise(),
             RespElement::Null => Null.serialise(),
+            RespElement::Map(m) => m.serialise(),
+            RespElement::Push(p) => serialise_sequence(b'>', p),
+            RespElement::BulkError(e) => {
+                let mut out = format!("!{}\r\n", e.len()).into_bytes();
+                out.extend_from_slice(e);
+                out.extend_from_slice(b"\r\n");
+                out
+            }
+            RespElement::Verbatim { format, data } => {
+                let mut out = format!("={}\r\n", format.len() + 1 + data.len()).into_bytes();
+                out.extend_from_slice(format);
+                out.push(b':');
+                out.extend_from_slice(data);
+                out.extend_from_slice(b"\r\n");
+                out
+            }
         }
     }
 }
 
-pub(crate) fn parse_element(input: &[u8]) -> IResult<&[u8], RespElement> {
+/// Whether `byte` starts one of the RESP types understood by `parse_element`.
+pub(crate) fn is_type_marker(byte: u8) -> bool {
+    matches!(
+        byte,
+        b'+' | b'-' | b':' | b'$' | b'*' | b'#' | b'_' | b'%' | b'=' | b'>' | b'!'
+    )
+}
+
+/// Parse a single RESP element.
+///
+/// Input which is only the start of an element produces `Err::Incomplete`, so callers
+/// should wait for more. Anything which can't become an element however much more arrives
+/// produces an `Err::Error`, and bulk strings longer than `max_bulk_len` or sequences longer
+/// than `MAX_MULTIBULK_LEN` an `Err::Failure`, which callers should treat as protocol errors.
+pub(crate) fn parse_element(input: &[u8], max_bulk_len: u64) -> IResult<&[u8], RespElement> {
     alt((
         map(parse_simple_string, RespElement::SimpleString),
         map(parse_simple_error, RespElement::SimpleError),
         map(parse_integer, RespElement::Integer),
-        map(parse_bulk_string, RespElement::BulkString),
-        map(parse_array, RespElement::Array),
+        map(
+            |input| parse_bulk_string(input, max_bulk_len),
+            RespElement::BulkString,
+        ),
+        map(|input| parse_array(input, max_bulk_len), RespElement::Array),
         map(parse_null_bulk_string, RespElement::NullElement),
+        map(parse_null_array, RespElement::NullArray),
         map(parse_boolean, RespElement::Boolean),
+        map(parse_null, |_| RespElement::Null),
+        map(|input| parse_map(input, max_bulk_len), RespElement::Map),
+        |input| parse_verbatim(input, max_bulk_len),
+        map(|input| parse_push(input, max_bulk_len), RespElement::Push),
+        map(
+            |input| parse_blob(input, b"!", max_bulk_len),
+            |e| RespElement::BulkError(Bytes::copy_from_slice(e)),
+        ),
     ))(input)
 }
 
@@ -227,15 +380,30 @@ pub(crate) fn parse_element(input: &[u8]) -> IResult<&[u8], RespElement> {
 /// Clients send commands to the Redis server as RESP arrays.
 /// Similarly, some Redis commands that return collections of elements use arrays as their replies.
 /// An example is the LRANGE command that returns elements of a list.
-fn parse_array(input: &[u8]) -> IResult<&[u8], Vec<RespElement>> {
-    let (input, _) = tag(b"*")(input)?;
-    let (input, len) = u32_parser(input)?;
-    let (input, _) = crlf(input)?;
+fn parse_array(input: &[u8], max_bulk_len: u64) -> IResult<&[u8], Vec<RespElement>> {
+    parse_sequence(input, b"*", max_bulk_len)
+}
+
+/// Pushes
+///
+/// Encoded like an array but with a greater-than (>) character. Servers use them under RESP3
+/// to send data the client didn't ask for, such as pub-sub messages.
+fn parse_push(input: &[u8], max_bulk_len: u64) -> IResult<&[u8], Vec<RespElement>> {
+    parse_sequence(input, b">", max_bulk_len)
+}
+
+fn parse_sequence<'a>(
+    input: &'a [u8],
+    marker: &[u8],
+    max_bulk_len: u64,
+) -> IResult<&'a [u8], Vec<RespElement>> {
+    let (input, len) = parse_multibulk_len(input, marker)?;
 
     let mut rest = input;
-    let mut elements = Vec::with_capacity(len as usize);
+    // Only grow as elements actually arrive, rather than trusting the header.
+    let mut elements = Vec::new();
     for _ in 0..len {
-        let (r, element) = parse_element(rest)?;
+        let (r, element) = parse_element(rest, max_bulk_len)?;
         elements.push(element);
         rest = r;
     }
@@ -243,24 +411,183 @@ fn parse_array(input: &[u8]) -> IResult<&[u8], Vec<RespElement>> {
     Ok((rest, elements))
 }
 
+/// Parse the header of an array, push or map starting with `marker`, giving the number of
+/// elements or entries which follow.
+///
+/// Fails outright with `ErrorKind::Count` if there are more than `MAX_MULTIBULK_LEN`.
+fn parse_multibulk_len<'a>(input: &'a [u8], marker: &[u8]) -> IResult<&'a [u8], u32> {
+    let (input, _) = tag(marker)(input)?;
+    let (input, len) = u32_parser(input)?;
+    let (input, _) = crlf(input)?;
+    if len > MAX_MULTIBULK_LEN {
+        return Err(Err::Failure(nom::error::Error::new(
+            input,
+            nom::error::ErrorKind::Count,
+        )));
+    }
+    Ok((input, len))
+}
+
 impl RespSerialise for Vec<RespElement> {
     fn serialise(&self) -> Vec<u8> {
-        let mut s = format!("*{}\r\n", self.len());
-        for element in self {
-            s.push_str(
-                &element
-                    .serialise()
-                    .iter()
-                    .map(|&b| b as char)
-                    .collect::<String>(),
-            );
+        serialise_sequence(b'*', self)
+    }
+}
+
+fn serialise_sequence(marker: u8, elements: &[RespElement]) -> Vec<u8> {
+    let mut out = vec![marker];
+    out.extend(format!("{}\r\n", elements.len()).into_bytes());
+    for element in elements {
+        out.extend(element.serialise());
+    }
+    out
+}
+
+/// Default for `streamed-reply-len`: replies holding more elements than this, counting those
+/// in nested arrays, pushes and maps, are written one element at a time.
+pub(crate) const DEFAULT_STREAMED_REPLY_LEN: u64 = 1024;
+
+/// Write `element` as RESP, without serialising replies of more than `streamed_len` elements
+/// into one buffer first, so replies such as a whole keyspace don't need another copy of it in
+/// memory.
+pub(crate) async fn write_element<W>(
+    writer: &mut W,
+    element: &RespElement,
+    streamed_len: u64,
+) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut sizes = Vec::new();
+    nested_sizes(element, &mut sizes);
+    let mut next_size = 0;
+    // Nested sequences are walked with a stack, as async functions can't recurse.
+    let mut stack: Vec<Children<'_>> = vec![Box::new(std::iter::once(element))];
+    while let Some(elements) = stack.last_mut() {
+        let Some(element) = elements.next() else {
+            stack.pop();
+            continue;
+        };
+        let (marker, len, children) = match element {
+            RespElement::Array(sequence) => (b'*', sequence.len(), children(element)),
+            RespElement::Push(sequence) => (b'>', sequence.len(), children(element)),
+            RespElement::Map(entries) => (b'%', entries.len(), children(element)),
+            element => {
+                writer.write_all(&element.serialise()).await?;
+                continue;
+            }
+        };
+        let size = sizes[next_size];
+        if size.len as u64 <= streamed_len {
+            writer.write_all(&element.serialise()).await?;
+            next_size = size.end;
+            continue;
         }
-        s.into_bytes()
+        next_size += 1;
+        let header = format!("{}{len}\r\n", marker as char);
+        writer.write_all(header.as_bytes()).await?;
+        stack.push(children);
+    }
+    Ok(())
+}
+
+/// The elements directly inside an array, push or map, with map keys and values alternating.
+type Children<'a> = Box<dyn Iterator<Item = &'a RespElement> + Send + 'a>;
+
+fn children(element: &RespElement) -> Children<'_> {
+    match element {
+        RespElement::Array(sequence) | RespElement::Push(sequence) => Box::new(sequence.iter()),
+        RespElement::Map(entries) => Box::new(entries.iter().flat_map(|(key, value)| [key, value])),
+        _ => Box::new(std::iter::empty()),
+    }
+}
+
+/// How many elements an array, push or map holds, counting nested ones, and the index in the
+/// list of sizes just past those of the sequences nested inside it.
+#[derive(Debug, Clone, Copy)]
+struct NestedSize {
+    len: usize,
+    end: usize,
+}
+
+/// Record the size of every array, push and map in `element`, in the order `write_element`
+/// reaches them, so each is counted once however deeply it is nested. Returns how many
+/// elements are nested in `element`.
+fn nested_sizes(element: &RespElement, sizes: &mut Vec<NestedSize>) -> usize {
+    if !matches!(
+        element,
+        RespElement::Array(_) | RespElement::Push(_) | RespElement::Map(_)
+    ) {
+        return 0;
+    }
+    let index = sizes.len();
+    sizes.push(NestedSize { len: 0, end: 0 });
+    let len = children(element)
+        .map(|child| 1 + nested_sizes(child, sizes))
+        .sum();
+    sizes[index] = NestedSize {
+        len,
+        end: sizes.len(),
+    };
+    len
+}
+
+/// Verbatim strings
+///
+/// Like a bulk string, but the payload starts with a three character format such as `txt`
+/// or `mkd` and a colon, telling the client how the rest should be displayed.
+fn parse_verbatim(input: &[u8], max_len: u64) -> IResult<&[u8], RespElement> {
+    let (rest, blob) = parse_blob(input, b"=", max_len)?;
+    match blob {
+        [a, b, c, b':', data @ ..] => Ok((
+            rest,
+            RespElement::Verbatim {
+                format: [*a, *b, *c],
+                data: Bytes::copy_from_slice(data),
+            },
+        )),
+        _ => fail(input),
+    }
+}
+
+/// Maps
+///
+/// RESP3 maps are encoded as a percent (%) character, followed by the number of entries,
+/// and then each entry's key and value in turn.
+fn parse_map(input: &[u8], max_bulk_len: u64) -> IResult<&[u8], Vec<(RespElement, RespElement)>> {
+    let (input, len) = parse_multibulk_len(input, b"%")?;
+
+    let mut rest = input;
+    let mut entries = Vec::new();
+    for _ in 0..len {
+        let (r, key) = parse_element(rest, max_bulk_len)?;
+        let (r, value) = parse_element(r, max_bulk_len)?;
+        entries.push((key, value));
+        rest = r;
+    }
+
+    Ok((rest, entries))
+}
+
+impl RespSerialise for Vec<(RespElement, RespElement)> {
+    fn serialise(&self) -> Vec<u8> {
+        let mut out = format!("%{}\r\n", self.len()).into_bytes();
+        for (key, value) in self {
+            out.extend(key.serialise());
+            out.extend(value.serialise());
+        }
+        out
     }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
-struct NullArray;
+pub(crate) struct NullArray;
+
+impl From<NullArray> for RespElement {
+    fn from(value: NullArray) -> Self {
+        RespElement::NullArray(value)
+    }
+}
 
 impl RespSerialise for NullArray {
     fn serialise(&self) -> Vec<u8> {
@@ -307,6 +634,98 @@ fn parse_null(input: &[u8]) -> IResult<&[u8], Null> {
     Ok((input, Null))
 }
 
+/// Inline commands
+///
+/// A line of whitespace separated arguments, as typed into a telnet session, e.g. `SET foo bar`.
+/// Arguments may be wrapped in double quotes, which support backslash escapes,
+/// or single quotes, to include whitespace.
+///
+/// Lines longer than `MAX_INLINE_LEN` fail with `ErrorKind::TooLarge`
+/// and unbalanced quotes with `ErrorKind::Verify`.
+pub(crate) fn parse_inline(input: &[u8]) -> IResult<&[u8], Vec<RespElement>> {
+    let too_large = || {
+        Err(Err::Failure(nom::error::Error::new(
+            input,
+            nom::error::ErrorKind::TooLarge,
+        )))
+    };
+    let Some(end) = input.iter().position(|&b| b == b'\n') else {
+        return if input.len() > MAX_INLINE_LEN {
+            too_large()
+        } else {
+            Err(Err::Incomplete(Needed::Unknown))
+        };
+    };
+    if end > MAX_INLINE_LEN {
+        return too_large();
+    }
+
+    let line = &input[..end];
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let Some(args) = split_inline_args(line) else {
+        return Err(Err::Failure(nom::error::Error::new(
+            input,
+            nom::error::ErrorKind::Verify,
+        )));
+    };
+    let args = args
+        .into_iter()
+        .map(|arg| RespElement::BulkString(arg.into()))
+        .collect();
+    Ok((&input[end + 1..], args))
+}
+
+/// Split an inline command into its arguments, or `None` if the quoting is unbalanced.
+fn split_inline_args(line: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let mut args = Vec::new();
+    let mut bytes = line.iter().copied().peekable();
+    loop {
+        while bytes.next_if(u8::is_ascii_whitespace).is_some() {}
+        let Some(&first) = bytes.peek() else {
+            return Some(args);
+        };
+
+        let mut arg = Vec::new();
+        match first {
+            b'"' => {
+                bytes.next();
+                loop {
+                    match bytes.next()? {
+                        b'"' => break,
+                        b'\\' => arg.push(match bytes.next()? {
+                            b'n' => b'\n',
+                            b'r' => b'\r',
+                            b't' => b'\t',
+                            b => b,
+                        }),
+                        b => arg.push(b),
+                    }
+                }
+            }
+            b'\'' => {
+                bytes.next();
+                loop {
+                    match bytes.next()? {
+                        b'\'' => break,
+                        b'\\' if bytes.peek() == Some(&b'\'') => arg.push(bytes.next()?),
+                        b => arg.push(b),
+                    }
+                }
+            }
+            _ => {
+                while let Some(b) = bytes.next_if(|b| !b.is_ascii_whitespace()) {
+                    arg.push(b);
+                }
+            }
+        }
+        // A closing quote must end the argument.
+        if bytes.peek().is_some_and(|b| !b.is_ascii_whitespace()) {
+            return None;
+        }
+        args.push(arg);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -324,6 +743,11 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_parse_string_invalid_utf8() {
+        assert!(parse_string(b"\xff\xfe").is_err());
+    }
+
     #[rstest]
     #[case(b"+Ok\r\n", "Ok")]
     #[case(b"+\r\n", "")]
@@ -381,12 +805,72 @@ mod tests {
         #[case] bytes: &'a [u8],
         #[case] expected: &'a str,
     ) -> TestResult<'a> {
-        let (rest, bs) = parse_bulk_string(bytes)?;
+        let (rest, bs) = parse_bulk_string(bytes, DEFAULT_MAX_BULK_LEN)?;
         assert_eq!(rest, b"");
         assert_eq!(bs.as_ref(), expected);
         Ok(())
     }
 
+    #[test]
+    fn test_parse_bulk_string_overrunning_length() {
+        assert_eq!(
+            parse_bulk_string(b"$10\r\nhello\r\n", DEFAULT_MAX_BULK_LEN),
+            Err(Err::Incomplete(Needed::new(3)))
+        );
+    }
+
+    #[rstest]
+    #[case(b"*2\r\n$3\r\nfoo\r\n")]
+    #[case(b"*1\r\n$3\r\nfo")]
+    #[case(b"$12")]
+    #[case(b":1")]
+    #[case(b"+OK\r")]
+    fn test_parse_element_incomplete(#[case] bytes: &[u8]) {
+        assert!(matches!(
+            parse_element(bytes, DEFAULT_MAX_BULK_LEN),
+            Err(Err::Incomplete(_))
+        ));
+    }
+
+    #[rstest]
+    #[case(b"$3\r\nfoox\r\n")]
+    #[case(b"*1\r\n$abc\r\n")]
+    #[case(b"*x\r\n")]
+    #[case(b"@foo\r\n")]
+    fn test_parse_element_malformed(#[case] bytes: &[u8]) {
+        assert!(matches!(
+            parse_element(bytes, DEFAULT_MAX_BULK_LEN),
+            Err(Err::Error(_))
+        ));
+    }
+
+    #[rstest]
+    #[case(b"$6\r\nhello!\r\n")]
+    #[case(b"*1\r\n$999999999999\r\n")]
+    fn test_parse_element_exceeding_max_bulk_len(#[case] bytes: &[u8]) {
+        assert!(matches!(parse_element(bytes, 5), Err(Err::Failure(_))));
+    }
+
+    #[rstest]
+    #[case(b"*4294967295\r\n")]
+    #[case(b"%4294967295\r\n")]
+    #[case(b">1048577\r\n")]
+    #[case(b"*1\r\n*1048577\r\n")]
+    fn test_parse_element_exceeding_max_multibulk_len(#[case] bytes: &[u8]) {
+        assert!(matches!(
+            parse_element(bytes, DEFAULT_MAX_BULK_LEN),
+            Err(Err::Failure(e)) if e.code == nom::error::ErrorKind::Count
+        ));
+    }
+
+    #[rstest]
+    #[case(b"$99999999999999999999\r\nhello\r\n")]
+    #[case(b"$5\r\nhel\xfflo\r\n")]
+    #[case(b"$5\r\nhelloworld\r\n")]
+    fn test_parse_bulk_string_invalid(#[case] bytes: &[u8]) {
+        assert!(parse_bulk_string(bytes, DEFAULT_MAX_BULK_LEN).is_err());
+    }
+
     #[rstest]
     #[case(b"+Ok\r\n", RespElement::SimpleString(SimpleString("Ok".into())))]
     #[case(
@@ -394,12 +878,12 @@ mod tests {
         RespElement::SimpleError(SimpleError("ERR unknown command 'asdf'".into()))
     )]
     #[case(b":0\r\n", RespElement::Integer(0))]
-    #[case(b"$5\r\nhello\r\n", RespElement::BulkString(BulkString("hello".into())))]
+    #[case(b"$5\r\nhello\r\n", RespElement::BulkString("hello".into()))]
     fn test_parse_element<'a>(
         #[case] bytes: &'a [u8],
         #[case] expected: RespElement,
     ) -> TestResult<'a> {
-        let (rest, element) = parse_element(bytes)?;
+        let (rest, element) = parse_element(bytes, DEFAULT_MAX_BULK_LEN)?;
         assert_eq!(rest, b"");
         assert_eq!(element, expected);
         Ok(())
@@ -410,8 +894,8 @@ mod tests {
     #[case(
         b"*2\r\n$5\r\nhello\r\n$5\r\nworld\r\n",
         vec![
-            RespElement::BulkString(BulkString("hello".into())),
-            RespElement::BulkString(BulkString("world".into()))
+            RespElement::BulkString("hello".into()),
+            RespElement::BulkString("world".into())
         ]
     )]
     #[case(
@@ -425,7 +909,7 @@ mod tests {
             RespElement::Integer(2),
             RespElement::Integer(3),
             RespElement::Integer(4),
-            RespElement::BulkString(BulkString("hello".into()))
+            RespElement::BulkString("hello".into())
         ]
     )]
     #[case(
@@ -446,12 +930,85 @@ mod tests {
         #[case] bytes: &'a [u8],
         #[case] expected: Vec<RespElement>,
     ) -> TestResult<'a> {
-        let (rest, elements) = parse_array(bytes)?;
+        let (rest, elements) = parse_array(bytes, DEFAULT_MAX_BULK_LEN)?;
         assert_eq!(rest, b"");
         assert_eq!(elements, expected);
         Ok(())
     }
 
+    #[rstest]
+    fn test_parse_nested_map<'a>() -> TestResult<'a> {
+        let bytes = b"%2\r\n+first\r\n:1\r\n$6\r\nnested\r\n%1\r\n+key\r\n#t\r\n";
+        let (rest, element) = parse_element(bytes, DEFAULT_MAX_BULK_LEN)?;
+        assert_eq!(rest, b"");
+        let expected = RespElement::Map(vec![
+            (
+                RespElement::SimpleString(SimpleString("first".into())),
+                RespElement::Integer(1),
+            ),
+            (
+                RespElement::BulkString("nested".into()),
+                RespElement::Map(vec![(
+                    RespElement::SimpleString(SimpleString("key".into())),
+                    RespElement::Boolean(true),
+                )]),
+            ),
+        ]);
+        assert_eq!(element, expected);
+        assert_eq!(expected.serialise(), bytes);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(b"=15\r\ntxt:Some string\r\n", b"txt", "Some string")]
+    #[case(b"=11\r\nmkd:# Title\r\n", b"mkd", "# Title")]
+    fn test_parse_verbatim<'a>(
+        #[case] bytes: &'a [u8],
+        #[case] format: &[u8; 3],
+        #[case] data: &'static str,
+    ) -> TestResult<'a> {
+        let (rest, element) = parse_element(bytes, DEFAULT_MAX_BULK_LEN)?;
+        assert_eq!(rest, b"");
+        let expected = RespElement::Verbatim {
+            format: *format,
+            data: Bytes::from_static(data.as_bytes()),
+        };
+        assert_eq!(element, expected);
+        assert_eq!(expected.serialise(), bytes);
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_parse_push<'a>() -> TestResult<'a> {
+        let bytes = b">3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$5\r\nhello\r\n";
+        let (rest, element) = parse_element(bytes, DEFAULT_MAX_BULK_LEN)?;
+        assert_eq!(rest, b"");
+        let expected = RespElement::Push(vec![
+            RespElement::BulkString("message".into()),
+            RespElement::BulkString("news".into()),
+            RespElement::BulkString("hello".into()),
+        ]);
+        assert_eq!(element, expected);
+        assert_eq!(expected.serialise(), bytes);
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_parse_bulk_error<'a>() -> TestResult<'a> {
+        let bytes = b"!21\r\nSYNTAX invalid\nsyntax\r\n";
+        let (rest, element) = parse_element(bytes, DEFAULT_MAX_BULK_LEN)?;
+        assert_eq!(rest, b"");
+        let expected = RespElement::BulkError(Bytes::from_static(b"SYNTAX invalid\nsyntax"));
+        assert_eq!(element, expected);
+        assert_eq!(expected.serialise(), bytes);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_verbatim_without_format() {
+        assert!(parse_element(b"=2\r\nhi\r\n", DEFAULT_MAX_BULK_LEN).is_err());
+    }
+
     #[rstest]
     fn test_parse_null_array<'a>() -> TestResult<'a> {
         let (rest, _) = parse_null_array(b"*-1\r\n")?;
@@ -468,14 +1025,17 @@ mod tests {
 
     #[rstest]
     fn test_parse_null_element_in_array<'a>() -> TestResult<'a> {
-        let (rest, elements) = parse_array(b"*3\r\n$5\r\nhello\r\n$-1\r\n$5\r\nworld\r\n")?;
+        let (rest, elements) = parse_array(
+            b"*3\r\n$5\r\nhello\r\n$-1\r\n$5\r\nworld\r\n",
+            DEFAULT_MAX_BULK_LEN,
+        )?;
         assert_eq!(rest, b"");
         assert_eq!(
             elements,
             vec![
-                RespElement::BulkString(BulkString("hello".into())),
+                RespElement::BulkString("hello".into()),
                 RespElement::NullElement(NullBulkString),
-                RespElement::BulkString(BulkString("world".into()))
+                RespElement::BulkString("world".into())
             ]
         );
         Ok(())
@@ -497,4 +1057,160 @@ mod tests {
         assert_eq!(b, expected);
         Ok(())
     }
+
+    #[test]
+    fn test_binary_bulk_string_round_trips() {
+        let input = b"$3\r\n\xff\x00a\r\n";
+        let (rest, element) = parse_element(input, DEFAULT_MAX_BULK_LEN).unwrap();
+        assert_eq!(rest, b"");
+        let RespElement::BulkString(s) = &element else {
+            panic!("Expected a bulk string, got {:?}", element);
+        };
+        assert_eq!(s.as_bytes(), b"\xff\x00a");
+        assert_eq!(s.as_ref(), "\u{fffd}\0a");
+        assert_eq!(element.serialise(), input);
+    }
+
+    #[test]
+    fn test_serialise_array_preserves_non_ascii_bytes() {
+        let elements = vec![RespElement::BulkString("\u{80}".into())];
+        assert_eq!(elements.serialise(), b"*1\r\n$2\r\n\xC2\x80\r\n");
+    }
+
+    #[rstest]
+    #[case(b"PING\r\n", &["PING"])]
+    #[case(b"SET foo bar\r\n", &["SET", "foo", "bar"])]
+    #[case(b"  SET   foo\tbar \n", &["SET", "foo", "bar"])]
+    #[case(b"SET \"hello world\" 'it\\'s'\r\n", &["SET", "hello world", "it's"])]
+    #[case(b"ECHO \"a\\nb\"\r\n", &["ECHO", "a\nb"])]
+    #[case(b"\r\n", &[])]
+    fn test_parse_inline<'a>(#[case] bytes: &'a [u8], #[case] expected: &[&str]) -> TestResult<'a> {
+        let (rest, args) = parse_inline(bytes)?;
+        assert_eq!(rest, b"");
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|&arg| RespElement::BulkString(arg.into()))
+            .collect();
+        assert_eq!(args, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_inline_incomplete() {
+        assert_eq!(
+            parse_inline(b"SET foo"),
+            Err(Err::Incomplete(Needed::Unknown))
+        );
+    }
+
+    #[rstest]
+    #[case(b"SET \"foo bar\r\n")]
+    #[case(b"SET 'foo\r\n")]
+    #[case(b"SET \"foo\"bar\r\n")]
+    fn test_parse_inline_unbalanced_quotes(#[case] bytes: &[u8]) {
+        assert!(matches!(parse_inline(bytes), Err(Err::Failure(_))));
+    }
+
+    /// Collects everything written, remembering the largest single write.
+    #[derive(Default)]
+    struct RecordingWriter {
+        written: Vec<u8>,
+        largest_write: usize,
+    }
+
+    impl AsyncWrite for RecordingWriter {
+        fn poll_write(
+            mut self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            buf: &[u8],
+        ) -> std::task::Poll<io::Result<usize>> {
+            self.written.extend_from_slice(buf);
+            self.largest_write = self.largest_write.max(buf.len());
+            std::task::Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_write_element_streams_large_arrays() {
+        let keys: Vec<_> = (0..100_000)
+            .map(|i| RespElement::BulkString(format!("key:{i}").into()))
+            .collect();
+        let reply = RespElement::Array(vec![
+            RespElement::BulkString("0".into()),
+            RespElement::Array(keys),
+        ]);
+
+        let mut writer = RecordingWriter::default();
+        write_element(&mut writer, &reply, DEFAULT_STREAMED_REPLY_LEN)
+            .await
+            .unwrap();
+        assert_eq!(writer.written, reply.serialise());
+        assert!(writer.largest_write < 32, "{}", writer.largest_write);
+
+        let small = RespElement::Array(vec![RespElement::Integer(1), RespElement::Integer(2)]);
+        let mut writer = RecordingWriter::default();
+        write_element(&mut writer, &small, DEFAULT_STREAMED_REPLY_LEN)
+            .await
+            .unwrap();
+        assert_eq!(writer.written, small.serialise());
+        assert_eq!(writer.largest_write, writer.written.len());
+    }
+
+    #[tokio::test]
+    async fn test_write_element_streams_large_maps() {
+        let entries: Vec<_> = (0..10_000)
+            .map(|i| {
+                (
+                    RespElement::BulkString(format!("field:{i}").into()),
+                    RespElement::Array(vec![RespElement::Integer(i)]),
+                )
+            })
+            .collect();
+        let reply = RespElement::Push(vec![RespElement::Map(entries)]);
+
+        let mut writer = RecordingWriter::default();
+        write_element(&mut writer, &reply, DEFAULT_STREAMED_REPLY_LEN)
+            .await
+            .unwrap();
+        assert_eq!(writer.written, reply.serialise());
+        assert!(writer.largest_write < 32, "{}", writer.largest_write);
+    }
+
+    #[rstest]
+    #[case(0, 4)]
+    #[case(3, 16)]
+    #[case(5, 24)]
+    #[tokio::test]
+    async fn test_write_element_threshold(#[case] streamed_len: u64, #[case] largest: usize) {
+        // 5 elements in all: an integer, and an array holding three more.
+        let reply = RespElement::Array(vec![
+            RespElement::Integer(1),
+            RespElement::Array(vec![
+                RespElement::Integer(2),
+                RespElement::Integer(3),
+                RespElement::Integer(4),
+            ]),
+        ]);
+
+        let mut writer = RecordingWriter::default();
+        write_element(&mut writer, &reply, streamed_len)
+            .await
+            .unwrap();
+        assert_eq!(writer.written, reply.serialise());
+        assert_eq!(writer.largest_write, largest);
+    }
 }
@@ -0,0 +1,186 @@
+use std::{collections::HashMap, sync::RwLock};
+
+use tokio::sync::mpsc;
+
+use crate::{clients::ClientState, parse::RespElement, Server};
+
+type Subscribers = HashMap<u64, mpsc::UnboundedSender<RespElement>>;
+
+/// Every pub-sub channel with at least one subscriber.
+#[derive(Debug, Default)]
+pub(crate) struct PubSub {
+    channels: RwLock<HashMap<String, Subscribers>>,
+}
+
+impl PubSub {
+    pub(crate) fn subscribe(&self, channel: &str, client: &ClientState) {
+        self.channels
+            .write()
+            .unwrap()
+            .entry(channel.to_owned())
+            .or_default()
+            .insert(client.id, client.messages.clone());
+    }
+
+    pub(crate) fn unsubscribe(&self, channel: &str, id: u64) {
+        let mut channels = self.channels.write().unwrap();
+        if let Some(subscribers) = channels.get_mut(channel) {
+            subscribers.remove(&id);
+            if subscribers.is_empty() {
+                channels.remove(channel);
+            }
+        }
+    }
+
+    /// Send `message` to every subscriber of `channel`, returning how many received it.
+    pub(crate) fn publish(&self, channel: &str, message: &str) -> usize {
+        let channels = self.channels.read().unwrap();
+        let Some(subscribers) = channels.get(channel) else {
+            return 0;
+        };
+        let message = RespElement::Array(vec![
+            RespElement::BulkString("message".into()),
+            RespElement::BulkString(channel.into()),
+            RespElement::BulkString(message.into()),
+        ]);
+        subscribers
+            .values()
+            .filter(|tx| tx.send(message.clone()).is_ok())
+            .count()
+    }
+}
+
+/// Publish events to `__keyspace@<db>__:<key>` channels.
+const NOTIFY_KEYSPACE: u8 = 1 << 0;
+/// Publish events to `__keyevent@<db>__:<event>` channels.
+const NOTIFY_KEYEVENT: u8 = 1 << 1;
+
+/// The classes of keyspace event which can be enabled with `notify-keyspace-events`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum EventClass {
+    /// Type-independent commands, flag `g`.
+    Generic = 1 << 2,
+    /// String commands, flag `$`.
+    String = 1 << 3,
+    /// Keys removed because they expired, flag `x`.
+    Expired = 1 << 4,
+    /// Keys removed by `maxmemory` eviction, flag `e`.
+    Evicted = 1 << 5,
+    /// Stream commands, flag `t`.
+    Stream = 1 << 6,
+}
+
+/// Parse a `notify-keyspace-events` value such as `KEA`, or `None` if it has unknown flags.
+pub(crate) fn parse_keyspace_events(flags: &str) -> Option<u8> {
+    flags.chars().try_fold(0, |parsed, flag| {
+        let flag = match flag {
+            'K' => NOTIFY_KEYSPACE,
+            'E' => NOTIFY_KEYEVENT,
+            'g' => EventClass::Generic as u8,
+            '$' => EventClass::String as u8,
+            'x' => EventClass::Expired as u8,
+            'e' => EventClass::Evicted as u8,
+            't' => EventClass::Stream as u8,
+            'A' => {
+                EventClass::Generic as u8
+                    | EventClass::String as u8
+                    | EventClass::Expired as u8
+                    | EventClass::Evicted as u8
+                    | EventClass::Stream as u8
+            }
+            _ => return None,
+        };
+        Some(parsed | flag)
+    })
+}
+
+/// Publish a keyspace notification for `event` on `key`, if its class is enabled.
+///
+/// There's only a single database, so events are always published for db 0.
+pub(crate) fn notify_keyspace_event(server: &Server, class: EventClass, event: &str, key: &str) {
+    let flags = server.keyspace_events();
+    if flags & class as u8 == 0 {
+        return;
+    }
+    if flags & NOTIFY_KEYSPACE != 0 {
+        server
+            .pubsub
+            .publish(&format!("__keyspace@0__:{}", key), event);
+    }
+    if flags & NOTIFY_KEYEVENT != 0 {
+        server
+            .pubsub
+            .publish(&format!("__keyevent@0__:{}", event), key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        commands::test_util::{command, execute},
+        OptValue,
+    };
+
+    fn message(channel: &str, payload: &str) -> RespElement {
+        RespElement::Array(vec![
+            RespElement::BulkString("message".into()),
+            RespElement::BulkString(channel.into()),
+            RespElement::BulkString(payload.into()),
+        ])
+    }
+
+    #[test]
+    fn test_keyevent_notification_on_set() {
+        let mut opts = HashMap::new();
+        opts.insert(
+            "notify-keyspace-events".to_owned(),
+            OptValue::String("KE$".into()),
+        );
+        let server = Arc::new(Server::new(opts));
+        let (mut client, mut messages) = ClientState::new(1);
+        let replies = command(&[b"SUBSCRIBE", b"__keyevent@0__:set", b"__keyspace@0__:foo"])
+            .unwrap()
+            .execute_for(&server, &mut client);
+        assert_eq!(replies.len(), 2);
+
+        execute(&server, &[b"SET", b"foo", b"bar"]);
+        assert_eq!(
+            messages.try_recv().unwrap(),
+            message("__keyspace@0__:foo", "set")
+        );
+        assert_eq!(
+            messages.try_recv().unwrap(),
+            message("__keyevent@0__:set", "foo")
+        );
+        assert!(messages.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_notifications_filtered_by_class() {
+        let mut opts = HashMap::new();
+        opts.insert(
+            "notify-keyspace-events".to_owned(),
+            OptValue::String("Eg".into()),
+        );
+        let server = Arc::new(Server::new(opts));
+        let (mut client, mut messages) = ClientState::new(1);
+        command(&[b"SUBSCRIBE", b"__keyevent@0__:set"])
+            .unwrap()
+            .execute_for(&server, &mut client);
+
+        execute(&server, &[b"SET", b"foo", b"bar"]);
+        assert!(messages.try_recv().is_err());
+    }
+
+    #[rstest::rstest]
+    #[case("", Some(0))]
+    #[case("KEA", Some(0b1111111))]
+    #[case("E$", Some(0b1010))]
+    #[case("Z", None)]
+    fn test_parse_keyspace_events(#[case] flags: &str, #[case] expected: Option<u8>) {
+        assert_eq!(parse_keyspace_events(flags), expected);
+    }
+}
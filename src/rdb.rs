@@ -0,0 +1,666 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use nom::bytes::complete::{tag, take};
+use nom::combinator::fail;
+use nom::number::complete::{be_u32, be_u64, be_u8, le_i16, le_i32, le_i8, le_u32, le_u64};
+use nom::IResult;
+
+use crate::{
+    db::{DbValue, Value},
+    expiry,
+    hll::HyperLogLog,
+    listpack::{self, Element},
+    stream::{Stream, StreamFields, StreamId},
+};
+
+const OP_AUX: u8 = 0xFA;
+const OP_RESIZEDB: u8 = 0xFB;
+const OP_EXPIRETIME_MS: u8 = 0xFC;
+const OP_EXPIRETIME: u8 = 0xFD;
+const OP_SELECTDB: u8 = 0xFE;
+const OP_EOF: u8 = 0xFF;
+const TYPE_STRING: u8 = 0;
+/// Streams as Redis 7.2 stores them, which is the only stream encoding read.
+const TYPE_STREAM_LISTPACKS_3: u8 = 21;
+
+/// Most entries in each listpack of a stream, like Redis' default `stream-node-max-entries`.
+const STREAM_NODE_MAX_ENTRIES: usize = 100;
+const STREAM_ITEM_FLAG_DELETED: i64 = 1 << 0;
+/// The entry has the same fields as the node's first entry, so only its values are stored.
+const STREAM_ITEM_FLAG_SAMEFIELDS: i64 = 1 << 1;
+
+const MAGIC: &[u8] = b"REDIS0011";
+
+/// The version of the RDB format written, which is the newest that can be restored.
+const RDB_VERSION: u16 = 11;
+
+/// Load the RDB file at `path`, starting with an empty database if it does not exist.
+pub(crate) fn load(path: &Path) -> anyhow::Result<HashMap<String, DbValue>> {
+    let contents = match std::fs::read(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let (_, db) = parse_rdb(&contents)
+        .map_err(|e| anyhow::anyhow!("Unable to parse RDB file {}: {:?}", path.display(), e))?;
+    Ok(db)
+}
+
+/// Write a snapshot of `db` to `path`.
+///
+/// The snapshot is written to a temporary file first and renamed into place,
+/// so a failed save never leaves a truncated file behind.
+pub(crate) fn save(path: &Path, db: &HashMap<String, DbValue>) -> io::Result<()> {
+    let tmp_path = path.with_extension(format!("tmp-{}", std::process::id()));
+    let mut file = std::fs::File::create(&tmp_path)?;
+    file.write_all(&serialise(db))?;
+    file.sync_all()?;
+    std::fs::rename(&tmp_path, path)
+}
+
+/// Length encoding
+///
+/// The two most significant bits of the first byte say how the length is stored.
+/// `11` means the following object is a specially encoded string instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Length {
+    Len(usize),
+    Special(u8),
+}
+
+fn parse_length(input: &[u8]) -> IResult<&[u8], Length> {
+    let (input, first) = be_u8(input)?;
+    match first >> 6 {
+        0b00 => Ok((input, Length::Len((first & 0x3F) as usize))),
+        0b01 => {
+            let (input, second) = be_u8(input)?;
+            Ok((
+                input,
+                Length::Len((((first & 0x3F) as usize) << 8) | second as usize),
+            ))
+        }
+        0b10 => match first {
+            0x80 => {
+                let (input, len) = be_u32(input)?;
+                Ok((input, Length::Len(len as usize)))
+            }
+            0x81 => {
+                let (input, len) = be_u64(input)?;
+                Ok((input, Length::Len(len as usize)))
+            }
+            _ => fail(input),
+        },
+        _ => Ok((input, Length::Special(first & 0x3F))),
+    }
+}
+
+fn write_length(out: &mut Vec<u8>, len: usize) {
+    if len < 1 << 6 {
+        out.push(len as u8);
+    } else if len < 1 << 14 {
+        out.push(0x40 | (len >> 8) as u8);
+        out.push(len as u8);
+    } else if len <= u32::MAX as usize {
+        out.push(0x80);
+        out.extend_from_slice(&(len as u32).to_be_bytes());
+    } else {
+        out.push(0x81);
+        out.extend_from_slice(&(len as u64).to_be_bytes());
+    }
+}
+
+/// Strings
+///
+/// Either length-prefixed raw bytes, or an integer stored in 8, 16 or 32 bits.
+/// LZF compressed strings are not supported.
+fn parse_rdb_string(input: &[u8]) -> IResult<&[u8], Bytes> {
+    let (input, len) = parse_length(input)?;
+    match len {
+        Length::Len(len) => {
+            let (input, s) = take(len)(input)?;
+            Ok((input, Bytes::copy_from_slice(s)))
+        }
+        Length::Special(0) => {
+            let (input, i) = le_i8(input)?;
+            Ok((input, i.to_string().into()))
+        }
+        Length::Special(1) => {
+            let (input, i) = le_i16(input)?;
+            Ok((input, i.to_string().into()))
+        }
+        Length::Special(2) => {
+            let (input, i) = le_i32(input)?;
+            Ok((input, i.to_string().into()))
+        }
+        Length::Special(_) => fail(input),
+    }
+}
+
+fn write_string(out: &mut Vec<u8>, s: &[u8]) {
+    write_length(out, s.len());
+    out.extend_from_slice(s);
+}
+
+/// A length which must not be a specially encoded string.
+fn parse_plain_length(input: &[u8]) -> IResult<&[u8], usize> {
+    match parse_length(input)? {
+        (rest, Length::Len(len)) => Ok((rest, len)),
+        (_, Length::Special(_)) => fail(input),
+    }
+}
+
+fn parse_key(input: &[u8]) -> IResult<&[u8], String> {
+    let (rest, key) = parse_rdb_string(input)?;
+    match String::from_utf8(key.to_vec()) {
+        Ok(key) => Ok((rest, key)),
+        Err(_) => fail(input),
+    }
+}
+
+/// The type a value is stored as in an RDB file.
+fn value_type(value: &Value) -> u8 {
+    match value {
+        Value::String(_) | Value::HyperLogLog(_) => TYPE_STRING,
+        Value::Stream(_) => TYPE_STREAM_LISTPACKS_3,
+    }
+}
+
+/// Serialise a value as it's stored in an RDB file, after its `value_type`.
+pub(crate) fn serialise_value(value: &Value) -> Vec<u8> {
+    let mut out = Vec::new();
+    match value {
+        Value::String(s) => write_string(&mut out, s),
+        // Like Redis, HyperLogLogs are stored as strings in their dense encoding.
+        Value::HyperLogLog(hll) => write_string(&mut out, &hll.to_dense_bytes()),
+        Value::Stream(stream) => write_stream(&mut out, stream),
+    }
+    out
+}
+
+/// Parse a value of the given type, as written by `serialise_value`.
+fn parse_value(value_type: u8, input: &[u8]) -> IResult<&[u8], Value> {
+    match value_type {
+        TYPE_STRING => {
+            let (rest, value) = parse_rdb_string(input)?;
+            let value = match HyperLogLog::from_dense_bytes(&value) {
+                Some(hll) => Value::HyperLogLog(hll),
+                None => Value::String(value),
+            };
+            Ok((rest, value))
+        }
+        TYPE_STREAM_LISTPACKS_3 => {
+            let (rest, stream) = parse_stream(input)?;
+            Ok((rest, Value::Stream(stream)))
+        }
+        _ => fail(input),
+    }
+}
+
+/// Streams
+///
+/// The entries are split into nodes keyed by their first ID, each a listpack which starts
+/// with the number of entries, how many are deleted and the first entry's fields. Every
+/// entry then has its flags, its ID relative to the node's, its fields and values, and the
+/// number of listpack elements it took. After the nodes come the stream's length, its last,
+/// first and largest deleted IDs, how many entries were ever added and its consumer groups.
+fn write_stream(out: &mut Vec<u8>, stream: &Stream) {
+    let entries: Vec<_> = stream.entries.iter().collect();
+    let nodes = entries.chunks(STREAM_NODE_MAX_ENTRIES);
+    write_length(out, nodes.len());
+    for node in nodes {
+        let (&master_id, master_fields) = node[0];
+        let mut elements: Vec<Element> = vec![
+            (node.len() as i64).into(),
+            0.into(),
+            (master_fields.len() as i64).into(),
+        ];
+        elements.extend(master_fields.iter().map(|(field, _)| field.clone().into()));
+        // Marks the end of the node's fields.
+        elements.push(0.into());
+
+        for &(id, fields) in node {
+            let same_fields = fields.len() == master_fields.len()
+                && fields
+                    .iter()
+                    .zip(master_fields)
+                    .all(|((field, _), (master, _))| field == master);
+            elements.push(
+                if same_fields {
+                    STREAM_ITEM_FLAG_SAMEFIELDS
+                } else {
+                    0
+                }
+                .into(),
+            );
+            elements.push((id.ms.wrapping_sub(master_id.ms) as i64).into());
+            elements.push((id.seq.wrapping_sub(master_id.seq) as i64).into());
+            let count = if same_fields {
+                elements.extend(fields.iter().map(|(_, value)| value.clone().into()));
+                fields.len() + 3
+            } else {
+                elements.push((fields.len() as i64).into());
+                for (field, value) in fields {
+                    elements.push(field.clone().into());
+                    elements.push(value.clone().into());
+                }
+                fields.len() * 2 + 4
+            };
+            elements.push((count as i64).into());
+        }
+
+        let mut key = master_id.ms.to_be_bytes().to_vec();
+        key.extend_from_slice(&master_id.seq.to_be_bytes());
+        write_string(out, &key);
+        write_string(out, &listpack::encode(&elements));
+    }
+
+    let first_id = stream
+        .entries
+        .first_key_value()
+        .map_or(StreamId::MIN, |(&id, _)| id);
+    write_length(out, stream.entries.len());
+    for id in [stream.last_id, first_id, StreamId::MIN] {
+        write_length(out, id.ms as usize);
+        write_length(out, id.seq as usize);
+    }
+    // How many entries were ever added isn't tracked, so the stream's length stands in.
+    write_length(out, stream.entries.len());
+    // Consumer groups aren't supported.
+    write_length(out, 0);
+}
+
+fn parse_stream(input: &[u8]) -> IResult<&[u8], Stream> {
+    let (mut input, nodes) = parse_plain_length(input)?;
+    let mut stream = Stream::default();
+    for _ in 0..nodes {
+        let (rest, key) = parse_rdb_string(input)?;
+        let (rest, node) = parse_rdb_string(rest)?;
+        let Ok(key) = <[u8; 16]>::try_from(&key[..]) else {
+            return fail(input);
+        };
+        let master_id = StreamId::new(
+            u64::from_be_bytes(key[..8].try_into().unwrap()),
+            u64::from_be_bytes(key[8..].try_into().unwrap()),
+        );
+        let Some(entries) = parse_stream_node(master_id, &node) else {
+            return fail(input);
+        };
+        for (id, fields) in entries {
+            // Entries are stored in order, so anything else means the file is corrupt.
+            if id <= stream.last_id {
+                return fail(input);
+            }
+            stream.add(id, fields);
+        }
+        input = rest;
+    }
+
+    let (rest, _len) = parse_plain_length(input)?;
+    let (rest, last_ms) = parse_plain_length(rest)?;
+    let (rest, last_seq) = parse_plain_length(rest)?;
+    let (rest, _first_ms) = parse_plain_length(rest)?;
+    let (rest, _first_seq) = parse_plain_length(rest)?;
+    let (rest, _max_deleted_ms) = parse_plain_length(rest)?;
+    let (rest, _max_deleted_seq) = parse_plain_length(rest)?;
+    let (rest, _entries_added) = parse_plain_length(rest)?;
+    let (rest, groups) = parse_plain_length(rest)?;
+    if groups != 0 {
+        return fail(input);
+    }
+    stream.last_id = StreamId::new(last_ms as u64, last_seq as u64);
+    Ok((rest, stream))
+}
+
+/// The live entries in a node of a stream, as written by `write_stream`.
+fn parse_stream_node(master_id: StreamId, node: &[u8]) -> Option<Vec<(StreamId, StreamFields)>> {
+    let mut elements = listpack::decode(node)?.into_iter();
+    let next_int = |elements: &mut std::vec::IntoIter<Element>| elements.next()?.as_int();
+    let _count = next_int(&mut elements)?;
+    let _deleted = next_int(&mut elements)?;
+    let master_len = usize::try_from(next_int(&mut elements)?).ok()?;
+    let master_fields = (0..master_len)
+        .map(|_| elements.next().map(Element::into_bytes))
+        .collect::<Option<Vec<_>>>()?;
+    if next_int(&mut elements)? != 0 {
+        return None;
+    }
+
+    let mut entries = Vec::new();
+    while let Some(flags) = elements.next() {
+        let flags = flags.as_int()?;
+        let ms = master_id.ms.wrapping_add(next_int(&mut elements)? as u64);
+        let seq = master_id.seq.wrapping_add(next_int(&mut elements)? as u64);
+        let fields: StreamFields = if flags & STREAM_ITEM_FLAG_SAMEFIELDS != 0 {
+            master_fields
+                .iter()
+                .map(|field| Some((field.clone(), elements.next()?.into_bytes())))
+                .collect::<Option<_>>()?
+        } else {
+            let len = usize::try_from(next_int(&mut elements)?).ok()?;
+            (0..len)
+                .map(|_| {
+                    let field = elements.next()?.into_bytes();
+                    Some((field, elements.next()?.into_bytes()))
+                })
+                .collect::<Option<_>>()?
+        };
+        let _count = next_int(&mut elements)?;
+        if flags & STREAM_ITEM_FLAG_DELETED == 0 {
+            entries.push((StreamId::new(ms, seq), fields));
+        }
+    }
+    Some(entries)
+}
+
+/// Serialise a value for `DUMP`: its type and encoding as in an RDB file, followed
+/// by the RDB version and a CRC64 of everything before it.
+pub(crate) fn dump_value(value: &Value) -> Vec<u8> {
+    let mut out = vec![value_type(value)];
+    out.extend(serialise_value(value));
+    out.extend_from_slice(&RDB_VERSION.to_le_bytes());
+    let crc = crc64(&out);
+    out.extend_from_slice(&crc.to_le_bytes());
+    out
+}
+
+/// Why a `DUMP` payload couldn't be restored.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, thiserror::Error)]
+pub(crate) enum RestoreError {
+    #[error("DUMP payload version or checksum are wrong")]
+    VersionOrChecksum,
+    #[error("Bad data format")]
+    BadFormat,
+}
+
+/// Parse a payload written by `dump_value`.
+pub(crate) fn restore_value(payload: &[u8]) -> Result<Value, RestoreError> {
+    let footer = payload
+        .len()
+        .checked_sub(10)
+        .ok_or(RestoreError::VersionOrChecksum)?;
+    let (body, crc) = payload.split_at(payload.len() - 8);
+    let version = u16::from_le_bytes([payload[footer], payload[footer + 1]]);
+    let crc = u64::from_le_bytes(crc.try_into().unwrap());
+    if version > RDB_VERSION || crc64(body) != crc {
+        return Err(RestoreError::VersionOrChecksum);
+    }
+    let data = &payload[..footer];
+    let (&value_type, data) = data.split_first().ok_or(RestoreError::BadFormat)?;
+    match parse_value(value_type, data) {
+        Ok((&[], value)) => Ok(value),
+        _ => Err(RestoreError::BadFormat),
+    }
+}
+
+/// The CRC-64/Jones checksum Redis uses for `DUMP` payloads and RDB files.
+fn crc64(data: &[u8]) -> u64 {
+    // The Jones polynomial, bit-reversed as the checksum is calculated LSB first.
+    const POLY: u64 = 0x95ac_9329_ac4b_c9b5;
+    let mut crc = 0;
+    for &byte in data {
+        crc ^= u64::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLY
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+pub(crate) fn serialise(db: &HashMap<String, DbValue>) -> Vec<u8> {
+    let now = Instant::now();
+    let live: Vec<_> = db
+        .iter()
+        .filter(|(_, value)| !value.is_expired(now))
+        .collect();
+    let mut out = MAGIC.to_vec();
+
+    out.push(OP_AUX);
+    write_string(&mut out, b"redis-ver");
+    write_string(&mut out, b"7.2.0");
+
+    out.push(OP_SELECTDB);
+    write_length(&mut out, 0);
+    out.push(OP_RESIZEDB);
+    write_length(&mut out, live.len());
+    write_length(
+        &mut out,
+        live.iter()
+            .filter(|(_, value)| value.expires_at.is_some())
+            .count(),
+    );
+
+    for (key, value) in live {
+        if let Some(expires_at) = value.expires_at {
+            let millis = expiry::unix_from_deadline(expires_at).as_millis() as u64;
+            out.push(OP_EXPIRETIME_MS);
+            out.extend_from_slice(&millis.to_le_bytes());
+        }
+        out.push(value_type(&value.value));
+        write_string(&mut out, key.as_bytes());
+        out.extend(serialise_value(&value.value));
+    }
+
+    out.push(OP_EOF);
+    // A zero checksum tells readers that checksumming is disabled.
+    out.extend_from_slice(&[0; 8]);
+    out
+}
+
+pub(crate) fn parse_rdb(input: &[u8]) -> IResult<&[u8], HashMap<String, DbValue>> {
+    let (input, _) = tag(&MAGIC[..5])(input)?;
+    let (mut input, _version) = take(4usize)(input)?;
+
+    let mut db = HashMap::new();
+    let mut expiry_millis = None;
+    loop {
+        let (rest, opcode) = be_u8(input)?;
+        input = rest;
+        match opcode {
+            OP_EOF => break,
+            OP_AUX => {
+                let (rest, _key) = parse_rdb_string(input)?;
+                let (rest, _value) = parse_rdb_string(rest)?;
+                input = rest;
+            }
+            OP_SELECTDB => {
+                let (rest, _db_number) = parse_length(input)?;
+                input = rest;
+            }
+            OP_RESIZEDB => {
+                let (rest, _db_size) = parse_length(input)?;
+                let (rest, _expires_size) = parse_length(rest)?;
+                input = rest;
+            }
+            OP_EXPIRETIME_MS => {
+                let (rest, millis) = le_u64(input)?;
+                expiry_millis = Some(millis);
+                input = rest;
+            }
+            OP_EXPIRETIME => {
+                let (rest, secs) = le_u32(input)?;
+                expiry_millis = Some(secs as u64 * 1000);
+                input = rest;
+            }
+            TYPE_STRING | TYPE_STREAM_LISTPACKS_3 => {
+                let (rest, key) = parse_key(input)?;
+                let (rest, value) = parse_value(opcode, rest)?;
+                input = rest;
+
+                let expires_at = match expiry_millis.take() {
+                    Some(millis) => {
+                        let deadline = expiry::deadline_from_unix(Duration::from_millis(millis));
+                        // Already expired, so don't bother loading it.
+                        if deadline <= Instant::now() {
+                            continue;
+                        }
+                        Some(deadline)
+                    }
+                    None => None,
+                };
+                db.insert(key, DbValue::new(value, expires_at));
+            }
+            _ => return fail(input),
+        }
+    }
+
+    // Version 5+ files end with a CRC64 checksum, which isn't verified.
+    Ok((input, db))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{commands::test_util::execute, parse::RespElement, Server};
+
+    // A small database with a header, metadata, a plain key, a key stored as an integer,
+    // a key that expires far in the future and one that has already expired.
+    const FIXTURE: &[u8] = b"REDIS0011\
+        \xFA\x09redis-ver\x057.2.0\
+        \xFE\x00\
+        \xFB\x04\x02\
+        \x00\x05hello\x05world\
+        \x00\x06number\xC0\x7B\
+        \xFC\x00\xE0\x4C\xDA\x8F\x1D\x00\x00\x00\x06future\x03yes\
+        \xFC\x0C\x28\x8A\xC7\x01\x00\x00\x00\x00\x04past\x02no\
+        \xFF\x00\x00\x00\x00\x00\x00\x00\x00";
+
+    #[test]
+    fn test_parse_rdb() {
+        let (rest, db) = parse_rdb(FIXTURE).unwrap();
+        assert_eq!(rest, b"\x00\x00\x00\x00\x00\x00\x00\x00");
+        assert_eq!(db.len(), 3);
+        assert_eq!(db["hello"].value, "world".into());
+        assert_eq!(db["hello"].expires_at, None);
+        assert_eq!(db["number"].value, "123".into());
+        assert_eq!(db["future"].value, "yes".into());
+        assert!(db["future"].expires_at.is_some());
+        assert!(!db.contains_key("past"));
+    }
+
+    #[rstest::rstest]
+    #[case(b"\x0A", Length::Len(10))]
+    #[case(b"\x42\xBC", Length::Len(700))]
+    #[case(b"\x80\x00\x00\x42\x68", Length::Len(17000))]
+    #[case(b"\xC0", Length::Special(0))]
+    fn test_parse_length(#[case] bytes: &[u8], #[case] expected: Length) {
+        let (rest, len) = parse_length(bytes).unwrap();
+        assert_eq!(rest, b"");
+        assert_eq!(len, expected);
+    }
+
+    #[test]
+    fn test_load_rdb_file() {
+        let dir = std::env::temp_dir().join(format!("redis-rdb-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("dump.rdb");
+        std::fs::write(&path, FIXTURE).unwrap();
+
+        let server = Arc::new(Server::new(HashMap::new()));
+        server.db.extend(load(&path).unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            execute(&server, &[b"GET", b"hello"]),
+            RespElement::BulkString("world".into())
+        );
+    }
+
+    #[rstest::rstest]
+    #[case(10)]
+    #[case(700)]
+    #[case(17000)]
+    fn test_length_round_trip(#[case] len: usize) {
+        let mut out = Vec::new();
+        write_length(&mut out, len);
+        let (rest, parsed) = parse_length(&out).unwrap();
+        assert_eq!(rest, b"");
+        assert_eq!(parsed, Length::Len(len));
+    }
+
+    #[test]
+    fn test_load_missing_rdb_file() {
+        let db = load(Path::new("/definitely/not/a/real/dump.rdb")).unwrap();
+        assert!(db.is_empty());
+    }
+
+    #[test]
+    fn test_crc64() {
+        assert_eq!(crc64(b"123456789"), 0xe9c6_d914_c4b8_d9ca);
+    }
+
+    #[test]
+    fn test_stream_round_trip() {
+        let mut stream = Stream::default();
+        // Enough entries for several nodes, with sequence numbers going backwards between
+        // times, and entries with different fields to the first in their node.
+        for i in 0..250u64 {
+            let mut fields: StreamFields = vec![("name".into(), format!("{i}").into())];
+            if i % 7 == 0 {
+                fields.push(("extra".into(), "x".repeat(i as usize).into()));
+            }
+            let seq = if i / 3 % 2 == 0 { 10 } else { 0 } + i % 3;
+            stream.add(StreamId::new(1000 + i / 3, seq), fields);
+        }
+        // Entries removed since, so the last ID is after every remaining entry.
+        stream.add(StreamId::new(5000, 0), vec![]);
+        stream.remove(&StreamId::new(5000, 0));
+
+        let mut db = HashMap::new();
+        db.insert(
+            "stream".to_owned(),
+            DbValue::new(Value::Stream(stream.clone()), None),
+        );
+        db.insert(
+            "empty".to_owned(),
+            DbValue::new(Stream::default().into(), None),
+        );
+        let (_, parsed) = parse_rdb(&serialise(&db)).unwrap();
+        assert_eq!(parsed["stream"].value, Value::Stream(stream.clone()));
+        assert_eq!(parsed["empty"].value, Value::Stream(Stream::default()));
+
+        let value = Value::Stream(stream);
+        assert_eq!(restore_value(&dump_value(&value)), Ok(value));
+    }
+
+    #[test]
+    fn test_resizedb_counts_saved_keys() {
+        let mut db = HashMap::new();
+        db.insert("live".to_owned(), DbValue::new("v".into(), None));
+        let past = Instant::now() - Duration::from_secs(1);
+        db.insert("expired".to_owned(), DbValue::new("v".into(), Some(past)));
+        let rdb = serialise(&db);
+        let resizedb = rdb.iter().position(|&b| b == OP_RESIZEDB).unwrap();
+        assert_eq!(&rdb[resizedb + 1..resizedb + 3], [1, 0]);
+    }
+
+    #[test]
+    fn test_dump_round_trip() {
+        let value = Value::String("hello".into());
+        let payload = dump_value(&value);
+        assert_eq!(&payload[..7], b"\x00\x05hello");
+        assert_eq!(restore_value(&payload), Ok(value));
+
+        let mut corrupted = payload.clone();
+        corrupted[2] = b'j';
+        assert_eq!(
+            restore_value(&corrupted),
+            Err(RestoreError::VersionOrChecksum)
+        );
+        assert_eq!(
+            restore_value(b"short"),
+            Err(RestoreError::VersionOrChecksum)
+        );
+    }
+}
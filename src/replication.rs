@@ -0,0 +1,643 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use bytes::{Buf, Bytes, BytesMut};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, watch};
+use tokio::task::AbortHandle;
+
+use crate::{
+    commands::{replication::ReplConfCommand, Command},
+    parse::{self, RespElement, RespSerialise},
+    rdb, Server,
+};
+
+/// How long a replica waits before reconnecting to its master after losing it.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Whether this server is a master or replicating another server.
+#[derive(Debug, Default)]
+enum Role {
+    #[default]
+    Master,
+    Replica {
+        host: String,
+        port: u16,
+        /// The task keeping this server in sync with its master.
+        task: AbortHandle,
+    },
+}
+
+/// The replication state of the server.
+#[derive(Debug)]
+pub(crate) struct Replication {
+    role: Mutex<Role>,
+    /// Identifies this server's history of writes to its replicas.
+    replid: String,
+    /// Number of bytes of commands propagated to replicas so far.
+    offset: AtomicU64,
+    /// Whether a replica has loaded its master's snapshot and is receiving its writes.
+    master_link_up: AtomicBool,
+    /// Every attached replica, by client id.
+    replicas: Mutex<HashMap<u64, ReplicaLink>>,
+    /// Signalled whenever a replica acknowledges an offset, to wake `WAIT`.
+    acks: watch::Sender<()>,
+    /// The task running a `FAILOVER`, while one is in progress and writes are paused.
+    failover: watch::Sender<Option<AbortHandle>>,
+}
+
+/// A replica attached to this server.
+#[derive(Debug)]
+struct ReplicaLink {
+    feed: mpsc::UnboundedSender<Bytes>,
+    /// Where the replica connected from, and the port it accepts connections on.
+    ip: String,
+    port: u16,
+    /// The replication offset the replica has confirmed it has processed.
+    acked: u64,
+}
+
+impl Default for Replication {
+    fn default() -> Self {
+        Replication {
+            role: Mutex::default(),
+            replid: random_id(),
+            offset: AtomicU64::new(0),
+            master_link_up: AtomicBool::new(false),
+            replicas: Mutex::default(),
+            acks: watch::channel(()).0,
+            failover: watch::channel(None).0,
+        }
+    }
+}
+
+/// A random 40 character hex ID, like Redis' replication and cluster node IDs.
+pub(crate) fn random_id() -> String {
+    // Each `RandomState` is seeded differently, which is random enough for an ID.
+    let state = RandomState::new();
+    (0..3)
+        .map(|i: u64| format!("{:016x}", state.hash_one(i)))
+        .collect::<String>()[..40]
+        .to_owned()
+}
+
+impl Replication {
+    pub(crate) fn offset(&self) -> u64 {
+        self.offset.load(Ordering::SeqCst)
+    }
+
+    /// Attach the client with `id` as a replica, replying to its `PSYNC` with a full resync.
+    ///
+    /// The returned feed starts with a snapshot of the keyspace, followed by every write
+    /// propagated after it.
+    pub(crate) fn attach_replica(
+        &self,
+        server: &Server,
+        id: u64,
+        ip: String,
+        port: u16,
+    ) -> (String, mpsc::UnboundedReceiver<Bytes>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        // Hold the lock so no writes are propagated between taking the snapshot and attaching.
+        // A write executed just before the snapshot but propagated after it may still be
+        // applied twice, as commands don't run one at a time like in Redis.
+        let mut replicas = self.replicas.lock().unwrap();
+        let snapshot = rdb::serialise(&server.db.snapshot());
+        let mut payload = format!("${}\r\n", snapshot.len()).into_bytes();
+        payload.extend_from_slice(&snapshot);
+        let _ = tx.send(payload.into());
+        let link = ReplicaLink {
+            feed: tx,
+            ip,
+            port,
+            acked: 0,
+        };
+        replicas.insert(id, link);
+        (format!("FULLRESYNC {} {}", self.replid, self.offset()), rx)
+    }
+
+    pub(crate) fn detach_replica(&self, id: u64) {
+        self.replicas.lock().unwrap().remove(&id);
+    }
+
+    /// Send a write command, exactly as the client sent it in RESP, to every replica.
+    pub(crate) fn propagate(&self, command: &[u8]) {
+        let mut replicas = self.replicas.lock().unwrap();
+        self.offset
+            .fetch_add(command.len() as u64, Ordering::SeqCst);
+        if replicas.is_empty() {
+            return;
+        }
+        let command = Bytes::copy_from_slice(command);
+        replicas.retain(|_, replica| replica.feed.send(command.clone()).is_ok());
+    }
+
+    /// Record that the replica with client `id` has processed everything up to `offset`.
+    pub(crate) fn ack(&self, id: u64, offset: u64) {
+        if let Some(replica) = self.replicas.lock().unwrap().get_mut(&id) {
+            replica.acked = replica.acked.max(offset);
+        }
+        self.acks.send_replace(());
+    }
+
+    /// Number of replicas which have acknowledged `offset`.
+    pub(crate) fn acked_replicas(&self, offset: u64) -> usize {
+        let replicas = self.replicas.lock().unwrap();
+        replicas.values().filter(|r| r.acked >= offset).count()
+    }
+
+    /// Wait until `numreplicas` replicas have acknowledged every write propagated so far,
+    /// or `timeout` passes, returning how many have. No timeout waits forever.
+    pub(crate) async fn wait(&self, numreplicas: usize, timeout: Option<Duration>) -> usize {
+        let offset = self.offset();
+        // Subscribe first, so acknowledgements received while checking aren't missed.
+        let mut acks = self.acks.subscribe();
+        let acked = self.acked_replicas(offset);
+        if acked >= numreplicas {
+            return acked;
+        }
+
+        let getack = RespElement::Array(vec![
+            RespElement::BulkString("REPLCONF".into()),
+            RespElement::BulkString("GETACK".into()),
+            RespElement::BulkString("*".into()),
+        ]);
+        self.propagate(&getack.serialise());
+
+        let deadline = timeout.map(|timeout| tokio::time::Instant::now() + timeout);
+        loop {
+            let changed = match deadline {
+                Some(deadline) => tokio::time::timeout_at(deadline, acks.changed()).await,
+                None => Ok(acks.changed().await),
+            };
+            let acked = self.acked_replicas(offset);
+            if acked >= numreplicas || !matches!(changed, Ok(Ok(()))) {
+                return acked;
+            }
+        }
+    }
+
+    pub(crate) fn replica_count(&self) -> usize {
+        self.replicas.lock().unwrap().len()
+    }
+
+    pub(crate) fn failover_in_progress(&self) -> bool {
+        self.failover.borrow().is_some()
+    }
+
+    /// Stop the failover in progress, returning whether there was one.
+    pub(crate) fn abort_failover(&self) -> bool {
+        self.failover
+            .send_if_modified(|failover| failover.take().map(|task| task.abort()).is_some())
+    }
+
+    /// Wait until writes are no longer paused by a failover.
+    pub(crate) async fn writes_resumed(&self) {
+        let mut failover = self.failover.subscribe();
+        let _ = failover.wait_for(Option::is_none).await;
+    }
+
+    /// The master this server is replicating, if any.
+    pub(crate) fn is_replica(&self) -> bool {
+        matches!(*self.role.lock().unwrap(), Role::Replica { .. })
+    }
+
+    pub(crate) fn master(&self) -> Option<(String, u16)> {
+        match &*self.role.lock().unwrap() {
+            Role::Master => None,
+            Role::Replica { host, port, .. } => Some((host.clone(), *port)),
+        }
+    }
+
+    /// The `replication` section of `INFO`.
+    pub(crate) fn info(&self) -> String {
+        let mut info = String::from("# Replication\r\n");
+        match &*self.role.lock().unwrap() {
+            Role::Master => info.push_str("role:master\r\n"),
+            Role::Replica { host, port, .. } => {
+                let status = if self.master_link_up.load(Ordering::SeqCst) {
+                    "up"
+                } else {
+                    "down"
+                };
+                info.push_str(&format!(
+                    "role:slave\r\nmaster_host:{}\r\nmaster_port:{}\r\nmaster_link_status:{}\r\n",
+                    host, port, status
+                ));
+            }
+        }
+
+        let replicas = self.replicas.lock().unwrap();
+        // Number the replicas in the order they attached.
+        let mut links: Vec<_> = replicas.iter().collect();
+        links.sort_by_key(|&(id, _)| id);
+        info.push_str(&format!("connected_slaves:{}\r\n", links.len()));
+        for (i, (_, link)) in links.into_iter().enumerate() {
+            info.push_str(&format!(
+                "slave{}:ip={},port={},state=online,offset={}\r\n",
+                i, link.ip, link.port, link.acked
+            ));
+        }
+        info.push_str(&format!(
+            "master_replid:{}\r\nmaster_repl_offset:{}\r\n",
+            self.replid,
+            self.offset()
+        ));
+        info
+    }
+}
+
+/// Start replicating the master at `host:port`, replacing any previous master.
+pub(crate) fn replicate_from(server: &Arc<Server>, host: String, port: u16) {
+    let mut role = server.replication.role.lock().unwrap();
+    if let Role::Replica { task, .. } = &*role {
+        task.abort();
+    }
+    let task = tokio::spawn(replicate(server.clone(), host.clone(), port)).abort_handle();
+    server
+        .replication
+        .master_link_up
+        .store(false, Ordering::SeqCst);
+    *role = Role::Replica { host, port, task };
+}
+
+/// Start a failover, pausing writes until a replica has caught up or `timeout` passes.
+///
+/// Handing the master role over to the replica isn't supported yet, so writes resume
+/// once the replica has caught up, and this server stays the master.
+pub(crate) fn failover(server: &Arc<Server>, timeout: Option<Duration>) {
+    let task_server = server.clone();
+    let task = tokio::spawn(async move {
+        task_server.replication.wait(1, timeout).await;
+        task_server.replication.failover.send_replace(None);
+    })
+    .abort_handle();
+    server.replication.failover.send_replace(Some(task));
+}
+
+/// Stop replicating and become a master again, keeping the data synced so far.
+pub(crate) fn promote(server: &Server) {
+    let mut role = server.replication.role.lock().unwrap();
+    if let Role::Replica { task, .. } = &*role {
+        task.abort();
+    }
+    server
+        .replication
+        .master_link_up
+        .store(false, Ordering::SeqCst);
+    *role = Role::Master;
+}
+
+/// Stay in sync with the master, reconnecting whenever the connection is lost.
+async fn replicate(server: Arc<Server>, host: String, port: u16) {
+    loop {
+        if let Err(e) = sync_with_master(&server, &host, port).await {
+            eprintln!("Lost connection to master {}:{}: {}", host, port, e);
+        }
+        server
+            .replication
+            .master_link_up
+            .store(false, Ordering::SeqCst);
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+/// Perform the handshake with the master, load its snapshot and then apply every
+/// command it propagates until the connection closes.
+async fn sync_with_master(server: &Arc<Server>, host: &str, port: u16) -> anyhow::Result<()> {
+    let mut master = MasterConnection {
+        stream: TcpStream::connect((host, port)).await?,
+        pending: BytesMut::new(),
+    };
+
+    master.expect(&["PING"], "PONG").await?;
+    let listening_port = server.port().to_string();
+    master
+        .expect(&["REPLCONF", "listening-port", &listening_port], "OK")
+        .await?;
+    master.expect(&["REPLCONF", "capa", "psync2"], "OK").await?;
+    let reply = master.command(&["PSYNC", "?", "-1"]).await?;
+    // The replication offset the snapshot was taken at.
+    let mut offset: u64 = match &reply {
+        RespElement::SimpleString(s) => s
+            .as_str()
+            .strip_prefix("FULLRESYNC ")
+            .and_then(|resync| resync.split(' ').nth(1))
+            .and_then(|offset| offset.parse().ok()),
+        _ => None,
+    }
+    .ok_or_else(|| anyhow!("unexpected reply to PSYNC: {:?}", reply))?;
+
+    let snapshot = master.read_snapshot().await?;
+    let (_, db) =
+        rdb::parse_rdb(&snapshot).map_err(|e| anyhow!("unable to parse snapshot: {:?}", e))?;
+    server.db.replace(db);
+    server
+        .replication
+        .master_link_up
+        .store(true, Ordering::SeqCst);
+
+    let max_bulk_len = server.proto_max_bulk_len();
+    loop {
+        let (element, len) = master.read_element(max_bulk_len).await?;
+        // Only serialise the command again when there's an append-only file to log it to.
+        let raw = server.aof.is_enabled().then(|| element.serialise());
+        match Command::try_from(element) {
+            // Acknowledge the offset up to, but not including, the request.
+            Ok(Command::ReplConf(ReplConfCommand::GetAck)) => {
+                master
+                    .send(&["REPLCONF", "ACK", &offset.to_string()])
+                    .await?;
+            }
+            // Replies are for the master's clients, so aren't sent back.
+            Ok(cmd) => {
+                let is_write = cmd.is_write();
+                cmd.execute(server);
+                if let Some(raw) = raw.filter(|_| is_write) {
+                    if let Err(e) = server.aof.append(&raw) {
+                        eprintln!("Unable to write to the append-only file: {}", e);
+                    }
+                }
+            }
+            Err(e) => eprintln!("Unable to apply command from master: {}", e),
+        }
+        offset += len as u64;
+    }
+}
+
+/// The connection a replica uses to receive the master's data.
+struct MasterConnection {
+    stream: TcpStream,
+    /// Bytes read from the master which haven't been parsed yet.
+    pending: BytesMut,
+}
+
+impl MasterConnection {
+    /// Read more input from the master, failing if it has closed the connection.
+    async fn fill(&mut self) -> anyhow::Result<()> {
+        if self.stream.read_buf(&mut self.pending).await? == 0 {
+            bail!("connection closed");
+        }
+        Ok(())
+    }
+
+    /// Read the next element, along with its length in bytes.
+    async fn read_element(&mut self, max_bulk_len: u64) -> anyhow::Result<(RespElement, usize)> {
+        loop {
+            match parse::parse_element(&self.pending, max_bulk_len) {
+                Ok((rest, element)) => {
+                    let consumed = self.pending.len() - rest.len();
+                    self.pending.advance(consumed);
+                    return Ok((element, consumed));
+                }
+                // The rest of the element hasn't arrived yet.
+                Err(nom::Err::Incomplete(_)) => self.fill().await?,
+                Err(nom::Err::Error(e) | nom::Err::Failure(e)) => {
+                    bail!("protocol error: {:?}", e.code)
+                }
+            }
+        }
+    }
+
+    /// Send a command without waiting for a reply.
+    async fn send(&mut self, args: &[&str]) -> anyhow::Result<()> {
+        let cmd = RespElement::Array(
+            args.iter()
+                .map(|&arg| RespElement::BulkString(arg.into()))
+                .collect(),
+        );
+        Ok(self.stream.write_all(&cmd.serialise()).await?)
+    }
+
+    /// Send a command and wait for its reply.
+    async fn command(&mut self, args: &[&str]) -> anyhow::Result<RespElement> {
+        self.send(args).await?;
+        Ok(self.read_element(u64::MAX).await?.0)
+    }
+
+    /// Send a command, failing unless the master replies with the simple string `expected`.
+    async fn expect(&mut self, args: &[&str], expected: &str) -> anyhow::Result<()> {
+        match self.command(args).await? {
+            RespElement::SimpleString(s) if s.as_str() == expected => Ok(()),
+            reply => bail!("unexpected reply to {}: {:?}", args[0], reply),
+        }
+    }
+
+    /// Read the RDB snapshot sent after a full resync. It's framed like a bulk string,
+    /// but without the trailing CRLF.
+    async fn read_snapshot(&mut self) -> anyhow::Result<Vec<u8>> {
+        let len = loop {
+            if let Some(end) = self.pending.windows(2).position(|w| w == b"\r\n") {
+                let header = std::str::from_utf8(&self.pending[..end])?;
+                let len: usize = header
+                    .strip_prefix('$')
+                    .and_then(|len| len.parse().ok())
+                    .ok_or_else(|| anyhow!("invalid snapshot header {:?}", header))?;
+                self.pending.advance(end + 2);
+                break len;
+            }
+            self.fill().await?;
+        };
+        while self.pending.len() < len {
+            self.fill().await?;
+        }
+        Ok(self.pending.split_to(len).to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::{
+        commands::test_util::{command, execute},
+        commands::{replication::FailoverCommand, CommandError},
+        db::Value,
+        OptValue,
+    };
+
+    fn ok(s: &str) -> RespElement {
+        RespElement::SimpleString(s.to_owned().into())
+    }
+
+    /// Read one command sent to the fake master.
+    async fn read_command(stream: &mut TcpStream, pending: &mut BytesMut) -> Vec<String> {
+        loop {
+            if let Ok((rest, RespElement::Array(args))) = parse::parse_element(pending, u64::MAX) {
+                let consumed = pending.len() - rest.len();
+                let args = args
+                    .into_iter()
+                    .map(|arg| match arg {
+                        RespElement::BulkString(arg) => arg.as_ref().to_owned(),
+                        arg => panic!("Unexpected argument {:?}", arg),
+                    })
+                    .collect();
+                pending.advance(consumed);
+                return args;
+            }
+            assert_ne!(stream.read_buf(pending).await.unwrap(), 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replica_mirrors_master() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let master_port = listener.local_addr().unwrap().port();
+
+        let mut opts = HashMap::new();
+        opts.insert("port".to_owned(), OptValue::UInt(6380));
+        let replica = Arc::new(Server::new(opts));
+        replica.db.insert(
+            "stale".to_owned(),
+            crate::db::DbValue::new("value".into(), None),
+        );
+        let port = master_port.to_string();
+        assert_eq!(
+            execute(&replica, &[b"REPLICAOF", b"127.0.0.1", port.as_bytes()]),
+            ok("OK")
+        );
+        assert_eq!(
+            execute(&replica, &[b"SLAVEOF", b"127.0.0.1", port.as_bytes()]),
+            ok("OK Already connected to specified master")
+        );
+
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut pending = BytesMut::new();
+        let replies: [(&[&str], &[u8]); 4] = [
+            (&["PING"], b"+PONG\r\n"),
+            (&["REPLCONF", "listening-port", "6380"], b"+OK\r\n"),
+            (&["REPLCONF", "capa", "psync2"], b"+OK\r\n"),
+            (
+                &["PSYNC", "?", "-1"],
+                b"+FULLRESYNC 8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb 0\r\n",
+            ),
+        ];
+        for (expected, reply) in replies {
+            assert_eq!(read_command(&mut stream, &mut pending).await, expected);
+            stream.write_all(reply).await.unwrap();
+        }
+
+        let mut snapshot = HashMap::new();
+        snapshot.insert(
+            "loaded".to_owned(),
+            crate::db::DbValue::new("from-rdb".into(), None),
+        );
+        let snapshot = rdb::serialise(&snapshot);
+        stream
+            .write_all(format!("${}\r\n", snapshot.len()).as_bytes())
+            .await
+            .unwrap();
+        stream.write_all(&snapshot).await.unwrap();
+        stream
+            .write_all(b"*3\r\n$3\r\nSET\r\n$3\r\nfoo\r\n$3\r\nbar\r\n")
+            .await
+            .unwrap();
+
+        let synced = async {
+            while replica.db.get_live("foo").is_none() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), synced)
+            .await
+            .expect("Timed out waiting for the replica");
+        assert_eq!(
+            replica.db.get_live("foo").unwrap().value,
+            Value::from("bar")
+        );
+        assert_eq!(
+            replica.db.get_live("loaded").unwrap().value,
+            Value::from("from-rdb")
+        );
+        assert!(replica.db.get_live("stale").is_none());
+        assert_eq!(
+            replica.replication.master(),
+            Some(("127.0.0.1".to_owned(), master_port))
+        );
+
+        assert_eq!(execute(&replica, &[b"REPLICAOF", b"no", b"one"]), ok("OK"));
+        assert_eq!(replica.replication.master(), None);
+    }
+
+    fn error(s: &str) -> RespElement {
+        RespElement::SimpleError(parse::SimpleError::from(s.to_owned()))
+    }
+
+    #[tokio::test]
+    async fn test_failover() {
+        let server = Arc::new(Server::new(HashMap::new()));
+        assert_eq!(
+            execute(&server, &[b"FAILOVER"]),
+            error("ERR FAILOVER requires connected replicas.")
+        );
+        assert_eq!(
+            execute(&server, &[b"FAILOVER", b"ABORT"]),
+            error("ERR No failover in progress.")
+        );
+
+        let _feed = server
+            .replication
+            .attach_replica(&server, 1, "127.0.0.1".to_owned(), 6380);
+        assert_eq!(
+            execute(&server, &[b"FAILOVER", b"TIMEOUT", b"60000"]),
+            ok("OK")
+        );
+        assert!(server.replication.failover_in_progress());
+        assert_eq!(
+            execute(&server, &[b"FAILOVER"]),
+            error("ERR FAILOVER already in progress.")
+        );
+        assert_eq!(execute(&server, &[b"FAILOVER", b"ABORT"]), ok("OK"));
+        assert!(!server.replication.failover_in_progress());
+        // Writes are no longer paused.
+        tokio::time::timeout(Duration::from_secs(1), server.replication.writes_resumed())
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn test_failover_invalid() {
+        assert_eq!(
+            command(&[
+                b"FAILOVER",
+                b"to",
+                b"127.0.0.1",
+                b"6380",
+                b"FORCE",
+                b"TIMEOUT",
+                b"10"
+            ])
+            .unwrap(),
+            Command::Failover(FailoverCommand::Start {
+                target: Some(("127.0.0.1".to_owned(), 6380)),
+                force: true,
+                timeout: Some(Duration::from_millis(10)),
+            })
+        );
+        assert!(matches!(
+            command(&[b"FAILOVER", b"ABORT", b"TIMEOUT", b"10"]),
+            Err(CommandError::SyntaxError)
+        ));
+        assert!(matches!(
+            command(&[b"FAILOVER", b"TIMEOUT", b"0"]),
+            Err(CommandError::InvalidFailoverTimeout)
+        ));
+        assert!(matches!(
+            command(&[b"FAILOVER", b"FORCE", b"TIMEOUT", b"10"]),
+            Err(CommandError::FailoverForceArgs)
+        ));
+        assert!(matches!(
+            command(&[b"FAILOVER", b"TO", b"127.0.0.1"]),
+            Err(CommandError::SyntaxError)
+        ));
+    }
+}
@@ -0,0 +1,170 @@
+use std::{
+    collections::VecDeque,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{clients::ClientAddr, parse::RespElement};
+
+/// Most arguments kept for each entry, like Redis' `SLOWLOG_ENTRY_MAX_ARGC`.
+const MAX_ARGS: usize = 32;
+
+/// Longest argument kept for each entry, like Redis' `SLOWLOG_ENTRY_MAX_STRING`.
+const MAX_ARG_LEN: usize = 128;
+
+/// A command which took longer than `slowlog-log-slower-than` to execute.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct SlowLogEntry {
+    pub(crate) id: u64,
+    /// UNIX time the command was executed, in seconds.
+    pub(crate) timestamp: u64,
+    pub(crate) duration: Duration,
+    pub(crate) args: Vec<String>,
+    pub(crate) addr: ClientAddr,
+}
+
+impl From<&SlowLogEntry> for RespElement {
+    fn from(entry: &SlowLogEntry) -> Self {
+        RespElement::Array(vec![
+            RespElement::Integer(entry.id as i64),
+            RespElement::Integer(entry.timestamp as i64),
+            RespElement::Integer(entry.duration.as_micros() as i64),
+            RespElement::Array(
+                entry
+                    .args
+                    .iter()
+                    .map(|arg| RespElement::BulkString(arg.clone().into()))
+                    .collect(),
+            ),
+            RespElement::BulkString(entry.addr.to_string().into()),
+            // Clients can't be named yet.
+            RespElement::BulkString(String::new().into()),
+        ])
+    }
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    next_id: u64,
+    /// Newest first, so the oldest is dropped from the back.
+    entries: VecDeque<SlowLogEntry>,
+}
+
+/// The most recent slow commands, bounded by `slowlog-max-len`.
+#[derive(Debug, Default)]
+pub(crate) struct SlowLog(Mutex<Entries>);
+
+impl SlowLog {
+    /// Record a slow command, dropping the oldest entries to keep at most `max_len`.
+    pub(crate) fn record(
+        &self,
+        args: &[RespElement],
+        duration: Duration,
+        addr: &ClientAddr,
+        max_len: usize,
+    ) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        let mut log = self.0.lock().unwrap();
+        let id = log.next_id;
+        log.next_id += 1;
+        log.entries.push_front(SlowLogEntry {
+            id,
+            timestamp,
+            duration,
+            args: truncate_args(args),
+            addr: addr.clone(),
+        });
+        log.entries.truncate(max_len);
+    }
+
+    /// Up to `count` entries, newest first, or all of them if `count` is `None`.
+    pub(crate) fn get(&self, count: Option<usize>) -> Vec<SlowLogEntry> {
+        let log = self.0.lock().unwrap();
+        let count = count.unwrap_or(log.entries.len());
+        log.entries.iter().take(count).cloned().collect()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.0.lock().unwrap().entries.len()
+    }
+
+    /// Remove every entry, without resetting the ids.
+    pub(crate) fn reset(&self) {
+        self.0.lock().unwrap().entries.clear();
+    }
+}
+
+/// Shorten the arguments kept for an entry like Redis does, so a huge command
+/// doesn't use lots of memory in the log.
+fn truncate_args(args: &[RespElement]) -> Vec<String> {
+    let kept = if args.len() > MAX_ARGS {
+        MAX_ARGS - 1
+    } else {
+        args.len()
+    };
+    let mut truncated: Vec<String> = args[..kept]
+        .iter()
+        .map(|arg| {
+            let arg = match arg {
+                RespElement::BulkString(arg) => arg.as_ref().to_owned(),
+                RespElement::Integer(i) => i.to_string(),
+                arg => format!("{:?}", arg),
+            };
+            match arg.char_indices().nth(MAX_ARG_LEN) {
+                Some((end, _)) => format!("{}... ({} more bytes)", &arg[..end], arg.len() - end),
+                None => arg,
+            }
+        })
+        .collect();
+    if kept < args.len() {
+        truncated.push(format!("... ({} more arguments)", args.len() - kept));
+    }
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<RespElement> {
+        args.iter()
+            .map(|&arg| RespElement::BulkString(arg.into()))
+            .collect()
+    }
+
+    #[test]
+    fn test_record_is_bounded() {
+        let log = SlowLog::default();
+        let addr = ClientAddr::Tcp("127.0.0.1:5000".parse().unwrap());
+        for key in ["a", "b", "c"] {
+            log.record(&args(&["GET", key]), Duration::from_millis(20), &addr, 2);
+        }
+        let entries = log.get(None);
+        assert_eq!(entries.iter().map(|e| e.id).collect::<Vec<_>>(), [2, 1]);
+        assert_eq!(entries[0].args, ["GET", "c"]);
+        assert_eq!(log.get(Some(1)).len(), 1);
+
+        log.reset();
+        assert_eq!(log.len(), 0);
+        log.record(&args(&["GET", "d"]), Duration::from_millis(20), &addr, 2);
+        assert_eq!(log.get(None)[0].id, 3);
+    }
+
+    #[test]
+    fn test_truncate_args() {
+        let long = "x".repeat(MAX_ARG_LEN + 10);
+        let many: Vec<String> = (0..40).map(|i| i.to_string()).collect();
+        let many: Vec<&str> = many.iter().map(String::as_str).collect();
+
+        let truncated = truncate_args(&args(&["SET", "key", &long]));
+        assert_eq!(
+            truncated[2],
+            format!("{}... (10 more bytes)", "x".repeat(MAX_ARG_LEN))
+        );
+        let truncated = truncate_args(&args(&many));
+        assert_eq!(truncated.len(), MAX_ARGS);
+        assert_eq!(truncated[MAX_ARGS - 1], "... (9 more arguments)");
+    }
+}
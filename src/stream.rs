@@ -0,0 +1,244 @@
+use std::{
+    collections::BTreeMap,
+    fmt, mem,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+
+/// The ID of a stream entry, ordered by time and then sequence number.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub(crate) struct StreamId {
+    pub(crate) ms: u64,
+    pub(crate) seq: u64,
+}
+
+impl StreamId {
+    pub(crate) const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub(crate) const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    pub(crate) fn new(ms: u64, seq: u64) -> Self {
+        StreamId { ms, seq }
+    }
+
+    /// The smallest ID after this one, if there is one.
+    pub(crate) fn next(self) -> Option<Self> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => Some(StreamId::new(self.ms.checked_add(1)?, 0)),
+        }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+/// The ID couldn't be parsed as `<ms>-<seq>`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) struct InvalidStreamId;
+
+impl FromStr for StreamId {
+    type Err = InvalidStreamId;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ms, seq) = s.split_once('-').ok_or(InvalidStreamId)?;
+        Ok(StreamId::new(
+            ms.parse().map_err(|_| InvalidStreamId)?,
+            seq.parse().map_err(|_| InvalidStreamId)?,
+        ))
+    }
+}
+
+/// The field/value pairs of a stream entry, in the order they were added.
+pub(crate) type StreamFields = Vec<(Bytes, Bytes)>;
+
+fn entry_memory_usage(fields: &StreamFields) -> usize {
+    size_of::<StreamId>()
+        + fields
+            .iter()
+            .map(|(field, value)| field.len() + value.len())
+            .sum::<usize>()
+}
+
+/// Which of the oldest entries `XTRIM` removes from a stream.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum Trim {
+    /// Keep at most this many entries.
+    MaxLen(usize),
+    /// Remove every entry before this ID.
+    MinId(StreamId),
+}
+
+/// An append-only log of entries, each keyed by an increasing ID.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub(crate) struct Stream {
+    /// Only changed through `add`, `remove` and `trim`, which keep `used_memory` up to date.
+    pub(crate) entries: BTreeMap<StreamId, StreamFields>,
+    /// The largest ID ever added, which may have since been removed.
+    pub(crate) last_id: StreamId,
+    /// Sum of the entries' memory usage, so it needn't be recounted after every change.
+    used_memory: usize,
+}
+
+impl Stream {
+    /// Approximate number of bytes used by the stream.
+    pub(crate) fn memory_usage(&self) -> usize {
+        self.used_memory
+    }
+
+    /// Approximate number of bytes used by the stream, estimated from the first
+    /// `samples` entries, or all of them if `samples` is zero.
+    pub(crate) fn sampled_memory_usage(&self, samples: usize) -> usize {
+        if samples == 0 || samples >= self.entries.len() {
+            return self.memory_usage();
+        }
+        let sampled: usize = self
+            .entries
+            .values()
+            .take(samples)
+            .map(entry_memory_usage)
+            .sum();
+        sampled * self.entries.len() / samples
+    }
+
+    /// The ID for a new entry added now, after every existing entry.
+    pub(crate) fn next_auto_id(&self) -> Option<StreamId> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64);
+        if now > self.last_id.ms {
+            Some(StreamId::new(now, 0))
+        } else {
+            self.last_id.next()
+        }
+    }
+
+    /// The ID for a new entry with the time `ms`, or `None` if it wouldn't be after
+    /// every existing entry.
+    pub(crate) fn next_id_at(&self, ms: u64) -> Option<StreamId> {
+        match ms.cmp(&self.last_id.ms) {
+            std::cmp::Ordering::Less => None,
+            // An empty stream's last ID is 0-0, so at time 0 it starts from 0-1.
+            std::cmp::Ordering::Equal => {
+                let seq = self.last_id.seq.checked_add(1)?;
+                Some(StreamId::new(ms, seq))
+            }
+            std::cmp::Ordering::Greater => Some(StreamId::new(ms, 0)),
+        }
+    }
+
+    /// Remove the oldest entries as `trim` says, returning how many were removed.
+    pub(crate) fn trim(&mut self, trim: Trim) -> usize {
+        let before = self.entries.len();
+        match trim {
+            Trim::MaxLen(max_len) => {
+                while self.entries.len() > max_len {
+                    if let Some((_, fields)) = self.entries.pop_first() {
+                        self.used_memory -= entry_memory_usage(&fields);
+                    }
+                }
+            }
+            Trim::MinId(min_id) => {
+                let kept = self.entries.split_off(&min_id);
+                let removed = mem::replace(&mut self.entries, kept);
+                self.used_memory -= removed.values().map(entry_memory_usage).sum::<usize>();
+            }
+        }
+        before - self.entries.len()
+    }
+
+    /// Append an entry, which the caller must have checked is after `last_id`.
+    pub(crate) fn add(&mut self, id: StreamId, fields: StreamFields) {
+        debug_assert!(id > self.last_id);
+        self.used_memory += entry_memory_usage(&fields);
+        self.entries.insert(id, fields);
+        self.last_id = id;
+    }
+
+    /// Remove the entry with `id`, returning whether there was one. `last_id` is left as it
+    /// is, so IDs are never reused.
+    pub(crate) fn remove(&mut self, id: &StreamId) -> bool {
+        match self.entries.remove(id) {
+            Some(fields) => {
+                self.used_memory -= entry_memory_usage(&fields);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("0-1", Ok(StreamId::new(0, 1)))]
+    #[case("1526919030474-55", Ok(StreamId::new(1526919030474, 55)))]
+    #[case("1526919030474", Err(InvalidStreamId))]
+    #[case("1-x", Err(InvalidStreamId))]
+    #[case("-1-0", Err(InvalidStreamId))]
+    fn test_parse_id(#[case] input: &str, #[case] expected: Result<StreamId, InvalidStreamId>) {
+        assert_eq!(input.parse(), expected);
+    }
+
+    #[test]
+    fn test_sampled_memory_usage() {
+        let mut stream = Stream::default();
+        for seq in 1..=4 {
+            stream.add(StreamId::new(0, seq), vec![("f".into(), "v".into())]);
+        }
+        let exact = stream.memory_usage();
+        assert_eq!(stream.sampled_memory_usage(0), exact);
+        assert_eq!(stream.sampled_memory_usage(2), exact);
+        assert_eq!(stream.sampled_memory_usage(10), exact);
+
+        stream.add(
+            StreamId::new(0, 5),
+            vec![("f".into(), "v".repeat(100).into())],
+        );
+        assert!(stream.sampled_memory_usage(2) < stream.memory_usage());
+    }
+
+    #[test]
+    fn test_memory_usage_follows_changes() {
+        let mut stream = Stream::default();
+        for seq in 1..=10 {
+            stream.add(
+                StreamId::new(1, seq),
+                vec![("f".into(), "v".repeat(seq as usize).into())],
+            );
+        }
+        let recounted =
+            |stream: &Stream| -> usize { stream.entries.values().map(entry_memory_usage).sum() };
+        assert_eq!(stream.memory_usage(), recounted(&stream));
+
+        assert!(stream.remove(&StreamId::new(1, 5)));
+        assert!(!stream.remove(&StreamId::new(1, 5)));
+        assert_eq!(stream.memory_usage(), recounted(&stream));
+        stream.trim(Trim::MaxLen(6));
+        assert_eq!(stream.memory_usage(), recounted(&stream));
+        stream.trim(Trim::MinId(StreamId::new(1, 9)));
+        assert_eq!(stream.entries.len(), 2);
+        assert_eq!(stream.memory_usage(), recounted(&stream));
+    }
+
+    #[test]
+    fn test_next_id_at() {
+        let mut stream = Stream::default();
+        assert_eq!(stream.next_id_at(0), Some(StreamId::new(0, 1)));
+        stream.add(StreamId::new(5, 3), vec![]);
+        assert_eq!(stream.next_id_at(4), None);
+        assert_eq!(stream.next_id_at(5), Some(StreamId::new(5, 4)));
+        assert_eq!(stream.next_id_at(6), Some(StreamId::new(6, 0)));
+    }
+}